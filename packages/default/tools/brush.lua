@@ -0,0 +1,57 @@
+local Tool = {}
+
+Tool.cursor = "circle"
+Tool.shortcut = "B"
+
+-- Declarative brush: Rust places the dabs, Lua only describes them.
+-- Set Tool.brush.tip = "tips/your_tip.png" to stamp an image from this package.
+Tool.brush = {
+    size = 20.0,
+    hardness = 0.5,
+    flow = 1.0,
+    opacity = 1.0,
+    spacing = 0.1,
+    jitter = 0.0,
+    angle = 0.0,
+    roundness = 1.0,
+    -- How much pen pressure scales each of these (0 = not at all)
+    pressure_size = 1.0,
+    pressure_opacity = 0.0,
+    pressure_flow = 0.0,
+}
+
+function Tool.on_ui(ui)
+    ui.heading("Brush Settings")
+
+    local brush = Tool.brush
+    brush.size = ui.slider("Size", brush.size, 1.0, 200.0)
+    brush.hardness = ui.slider("Hardness", brush.hardness, 0.0, 1.0)
+    brush.flow = ui.slider("Flow", brush.flow, 0.0, 1.0)
+    brush.opacity = ui.slider("Opacity", brush.opacity, 0.0, 1.0)
+    brush.spacing = ui.slider("Spacing", brush.spacing, 0.01, 2.0)
+    brush.jitter = ui.slider("Jitter", brush.jitter, 0.0, 2.0)
+    brush.angle = ui.slider("Angle", brush.angle, 0.0, 360.0)
+    brush.roundness = ui.slider("Roundness", brush.roundness, 0.05, 1.0)
+
+    -- Pen pressure
+    brush.pressure_size = ui.slider("Pressure: Size", brush.pressure_size, 0.0, 1.0)
+    brush.pressure_opacity = ui.slider("Pressure: Opacity", brush.pressure_opacity, 0.0, 1.0)
+    brush.pressure_flow = ui.slider("Pressure: Flow", brush.pressure_flow, 0.0, 1.0)
+
+    ui.separator()
+    if ui.button("Reset Defaults") then
+        brush.size = 20.0
+        brush.hardness = 0.5
+        brush.flow = 1.0
+        brush.opacity = 1.0
+        brush.spacing = 0.1
+        brush.jitter = 0.0
+        brush.angle = 0.0
+        brush.roundness = 1.0
+        brush.pressure_size = 1.0
+        brush.pressure_opacity = 0.0
+        brush.pressure_flow = 0.0
+    end
+end
+
+return Tool
@@ -0,0 +1,29 @@
+local Tool = {}
+
+Tool.cursor = "circle"
+Tool.shortcut = "G"
+Tool.stabilize = false
+Tool.size = 1.0
+Tool.tolerance = 32
+Tool.contiguous = true
+Tool.antialiasing = true
+Tool.opacity = 1.0
+
+function Tool.on_ui(ui)
+    ui.heading("Paint Bucket")
+
+    Tool.tolerance = ui.slider("Tolerance", Tool.tolerance, 0, 255)
+    Tool.opacity = ui.slider("Opacity", Tool.opacity, 0.0, 1.0)
+    Tool.contiguous = ui.checkbox("Contiguous", Tool.contiguous)
+    Tool.antialiasing = ui.checkbox("Antialiasing", Tool.antialiasing)
+end
+
+-- Fill once per click, not on every mouse move
+function Tool.on_press(api, event)
+    local c = api.color
+    local alpha = math.floor(c[4] * Tool.opacity)
+    api.flood_fill(event.x, event.y, { c[1], c[2], c[3], alpha }, math.floor(Tool.tolerance),
+        Tool.contiguous, Tool.antialiasing)
+end
+
+return Tool
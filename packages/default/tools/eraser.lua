@@ -0,0 +1,29 @@
+local Tool = {}
+
+Tool.cursor = "circle"
+Tool.shortcut = "E"
+
+-- Everything this tool paints removes alpha from the layer instead of adding color
+Tool.composite = "erase"
+
+Tool.brush = {
+    size = 20.0,
+    hardness = 0.8,
+    flow = 1.0,
+    opacity = 1.0,
+    spacing = 0.1,
+    pressure_size = 1.0,
+}
+
+function Tool.on_ui(ui)
+    ui.heading("Eraser Settings")
+
+    local brush = Tool.brush
+    brush.size = ui.slider("Size", brush.size, 1.0, 200.0)
+    brush.hardness = ui.slider("Hardness", brush.hardness, 0.0, 1.0)
+    brush.flow = ui.slider("Flow", brush.flow, 0.0, 1.0)
+    brush.opacity = ui.slider("Opacity", brush.opacity, 0.0, 1.0)
+    brush.pressure_size = ui.slider("Pressure: Size", brush.pressure_size, 0.0, 1.0)
+end
+
+return Tool
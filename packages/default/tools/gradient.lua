@@ -0,0 +1,75 @@
+local Tool = {}
+
+Tool.cursor = "circle"
+Tool.shortcut = "Shift+G"
+Tool.stabilize = false
+Tool.size = 1.0
+
+local SHAPES = { "linear", "radial", "conical", "diamond", "reflected" }
+local REPEATS = { "none", "repeat", "mirror" }
+local PRESETS = {
+    "Color to Transparent", "Primary to Secondary", "Color to White", "Color to Black", "Rainbow",
+}
+
+Tool.shape = 1
+Tool.repeat_mode = 1
+Tool.preset = 1
+Tool.dither = true
+Tool.opacity = 1.0
+
+function Tool.on_ui(ui)
+    ui.heading("Gradient")
+
+    Tool.shape = ui.combo("Shape", Tool.shape, SHAPES)
+    Tool.repeat_mode = ui.combo("Repeat", Tool.repeat_mode, REPEATS)
+    Tool.preset = ui.combo("Colors", Tool.preset, PRESETS)
+
+    Tool.opacity = ui.slider("Opacity", Tool.opacity, 0.0, 1.0)
+    Tool.dither = ui.checkbox("Dither", Tool.dither)
+end
+
+local function stops(api, r, g, b, a)
+    local opacity = Tool.opacity
+    a = math.floor(a * opacity)
+    local preset = PRESETS[Tool.preset]
+    if preset == "Primary to Secondary" then
+        -- Dragging with the right button runs from secondary to primary
+        local from, to = api.primary, api.secondary
+        if api.button == "right" then
+            from, to = to, from
+        end
+        return {
+            { 0, from[1], from[2], from[3], math.floor(from[4] * opacity) },
+            { 1, to[1], to[2], to[3], math.floor(to[4] * opacity) },
+        }
+    elseif preset == "Color to White" then
+        return { { 0, r, g, b, a }, { 1, 255, 255, 255, a } }
+    elseif preset == "Color to Black" then
+        return { { 0, r, g, b, a }, { 1, 0, 0, 0, a } }
+    elseif preset == "Rainbow" then
+        return {
+            { 0.0, 255, 0, 0, a },
+            { 0.2, 255, 255, 0, a },
+            { 0.4, 0, 255, 0, a },
+            { 0.6, 0, 255, 255, a },
+            { 0.8, 0, 0, 255, a },
+            { 1.0, 255, 0, 255, a },
+        }
+    end
+    return { { 0, r, g, b, a }, { 1, r, g, b, 0 } }
+end
+
+function Tool.on_paint(api, x1, y1, x2, y2, r, g, b, a)
+    -- Remember where the drag started; the preview is redrawn from there every frame
+    if api.stroke_start then
+        Tool.origin_x, Tool.origin_y = x1, y1
+    end
+    api.clear_stroke()
+    api.fill_gradient(Tool.origin_x, Tool.origin_y, x2, y2, stops(api, r, g, b, a), {
+        shape = SHAPES[Tool.shape],
+        repeat_mode = REPEATS[Tool.repeat_mode],
+        dither = Tool.dither,
+    })
+end
+
+return Tool
@@ -0,0 +1,82 @@
+local Tool = {}
+
+Tool.cursor = "circle"
+Tool.shortcut = "U"
+Tool.stabilize = false
+Tool.size = 1.0
+
+local KINDS = { "line", "rect", "ellipse", "rounded_rect" }
+local KIND_NAMES = { "Line", "Rectangle", "Ellipse", "Rounded Rectangle" }
+local STYLES = { "Outline", "Fill", "Fill + Outline" }
+
+Tool.kind = 2
+Tool.style = 1
+Tool.width = 3.0
+Tool.radius = 10.0
+Tool.opacity = 1.0
+Tool.fill_opacity = 0.5
+Tool.antialiasing = true
+
+function Tool.on_ui(ui)
+    ui.heading("Shapes")
+
+    Tool.kind = ui.combo("Shape", Tool.kind, KIND_NAMES)
+    if KINDS[Tool.kind] ~= "line" then
+        Tool.style = ui.combo("Style", Tool.style, STYLES)
+    end
+    Tool.width = ui.slider("Width", Tool.width, 1.0, 50.0)
+    if KINDS[Tool.kind] == "rounded_rect" then
+        Tool.radius = ui.slider("Corner Radius", Tool.radius, 0.0, 100.0)
+    end
+    Tool.opacity = ui.slider("Opacity", Tool.opacity, 0.0, 1.0)
+    if STYLES[Tool.style] == "Fill + Outline" then
+        Tool.fill_opacity = ui.slider("Fill Opacity", Tool.fill_opacity, 0.0, 1.0)
+    end
+    Tool.antialiasing = ui.checkbox("Antialiasing", Tool.antialiasing)
+    ui.label("Hold Shift for squares, circles and 45 degree lines")
+end
+
+-- Shift: equal sides for boxes, 45 degree steps for lines
+local function constrain(kind, x1, y1, x2, y2)
+    local dx, dy = x2 - x1, y2 - y1
+    if kind == "line" then
+        local length = math.sqrt(dx * dx + dy * dy)
+        local step = math.pi / 4
+        local angle = math.floor(math.atan(dy, dx) / step + 0.5) * step
+        return x1 + math.cos(angle) * length, y1 + math.sin(angle) * length
+    end
+    local side = math.max(math.abs(dx), math.abs(dy))
+    local sx = dx < 0 and -1 or 1
+    local sy = dy < 0 and -1 or 1
+    return x1 + side * sx, y1 + side * sy
+end
+
+function Tool.on_press(api, event)
+    Tool.origin_x, Tool.origin_y = event.x, event.y
+end
+
+function Tool.on_paint(api, x1, y1, x2, y2, r, g, b, a)
+    local kind = KINDS[Tool.kind]
+    local ex, ey = x2, y2
+    if api.shift then
+        ex, ey = constrain(kind, Tool.origin_x, Tool.origin_y, x2, y2)
+    end
+
+    local alpha = math.floor(a * Tool.opacity)
+    local style = STYLES[Tool.style]
+    local options = { width = Tool.width, radius = Tool.radius, antialias = Tool.antialiasing }
+    if kind == "line" or style ~= "Fill" then
+        options.outline = { r, g, b, alpha }
+    end
+    if style == "Fill" then
+        options.fill = { r, g, b, alpha }
+    elseif style == "Fill + Outline" then
+        options.fill = { r, g, b, math.floor(alpha * Tool.fill_opacity) }
+    end
+
+    -- Rubber band: throw away last frame's preview and draw it again
+    api.clear_stroke()
+    api.draw_shape(kind, Tool.origin_x, Tool.origin_y, ex, ey, options)
+end
+
+return Tool
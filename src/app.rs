@@ -1,12 +1,158 @@
 use egui::{Color32, Id, LayerId, Order, Stroke, TextureHandle, TextureOptions};
+use image::ImageError;
 use image::io::Reader as ImageReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use winit::keyboard::{Key, ModifiersState, NamedKey};
 use winit::{event::*, window::Window};
 
 use crate::canvas::Canvas;
-use crate::commands::PaintCommand;
+use crate::colors::{self, ColorPair, ColorTarget};
+use crate::document::Document;
+use crate::eyedropper::{EyedropperOptions, SampleSize};
+use crate::image_io::{self, ExportOptions};
+use crate::input::{InputEvent, InputPipeline, PointerEvent, PressureCurve, Sample};
+use crate::layers::{BlendMode, Layer};
+use crate::new_document::{self, Background, NewDocument, Preset};
 use crate::packages::PackageManager;
-use crate::scripting::{CursorType, LuaEngine};
+use crate::palette::{self, Palette, PaletteFormat, RecentColors};
+use crate::project;
+use crate::scripting::{self, CursorType, LuaEngine, ToolEvent};
+use crate::selection::{SelectionMode, SelectionShape, SelectionTool, WandOptions};
+use crate::shortcuts::{self, ActionDef, Keymap, Shortcut};
+use crate::stabilizer::{StabilizerSettings, StrokePoint};
+use crate::stroke::{StrokeContext, ToolStroke};
+use crate::text::{FontLibrary, MAX_TEXT_SIZE, MIN_TEXT_SIZE, TextAlign, TextTool};
+use crate::viewport::Viewport;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FileDialogKind {
+    Open,
+    SaveAs,
+    Export,
+    ImportPalette,
+    ExportPalette,
+}
+
+impl FileDialogKind {
+    fn title(&self) -> &'static str {
+        match self {
+            FileDialogKind::Open => "Open Image",
+            FileDialogKind::SaveAs => "Save As",
+            FileDialogKind::Export => "Export",
+            FileDialogKind::ImportPalette => "Import Palette",
+            FileDialogKind::ExportPalette => "Export Palette",
+        }
+    }
+
+    fn action(&self) -> &'static str {
+        match self {
+            FileDialogKind::Open => "Open",
+            FileDialogKind::SaveAs => "Save",
+            FileDialogKind::Export => "Export",
+            FileDialogKind::ImportPalette => "Import",
+            FileDialogKind::ExportPalette => "Export",
+        }
+    }
+}
+
+/// Which palette the Palette window shows
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PaletteChoice {
+    // Saved inside the document
+    Document,
+    // The user's library (config folder)
+    Saved(usize),
+    // Shipped with a package; read-only
+    Package(usize),
+}
+
+/// Anything that can be picked in the Tools window
+#[derive(Clone, Copy, PartialEq)]
+enum ToolChoice {
+    // Index into the package manager's tools
+    Lua(usize),
+    Selection(SelectionTool),
+    Text,
+    Eyedropper,
+}
+
+impl ToolChoice {
+    fn all(packages: &PackageManager) -> Vec<ToolChoice> {
+        (0..packages.tools.len())
+            .map(ToolChoice::Lua)
+            .chain(SelectionTool::ALL.map(ToolChoice::Selection))
+            .chain([ToolChoice::Text, ToolChoice::Eyedropper])
+            .collect()
+    }
+
+    fn name(&self, packages: &PackageManager) -> String {
+        match self {
+            ToolChoice::Lua(index) => packages.tools[*index].name.clone(),
+            ToolChoice::Selection(tool) => tool.name().to_string(),
+            ToolChoice::Text => "Text".to_string(),
+            ToolChoice::Eyedropper => "Eyedropper".to_string(),
+        }
+    }
+
+    /// Default key; Lua tools declare theirs with `Tool.shortcut`
+    fn default_shortcut(&self, packages: &PackageManager) -> Option<String> {
+        let key = match self {
+            ToolChoice::Lua(index) => return scripting::declared_shortcut(&packages.tools[*index]),
+            ToolChoice::Selection(SelectionTool::Rect) => "M",
+            ToolChoice::Selection(SelectionTool::Ellipse) => "Shift+M",
+            ToolChoice::Selection(SelectionTool::Lasso) => "L",
+            ToolChoice::Selection(SelectionTool::PolygonLasso) => "Shift+L",
+            ToolChoice::Selection(SelectionTool::MagicWand) => "W",
+            ToolChoice::Text => "T",
+            ToolChoice::Eyedropper => "I",
+        };
+        Some(key.to_string())
+    }
+}
+
+/// "Polygon Lasso" -> "tool.polygon_lasso"
+fn tool_action_id(name: &str) -> String {
+    format!("tool.{}", name.to_lowercase().replace(' ', "_"))
+}
+
+/// One "switch to this tool" action per tool
+fn tool_actions(packages: &PackageManager) -> Vec<ActionDef> {
+    ToolChoice::all(packages)
+        .iter()
+        .map(|choice| {
+            let name = choice.name(packages);
+            let shortcut = choice.default_shortcut(packages);
+            let defaults: Vec<&str> = shortcut.as_deref().into_iter().collect();
+            ActionDef::new(&tool_action_id(&name), &name, &defaults)
+        })
+        .collect()
+}
+
+/// What was done to a swatch this frame (applied after drawing)
+enum SwatchAction {
+    Pick(ColorTarget, [u8; 4]),
+    Move(usize, usize),
+    Replace(usize),
+    Remove(usize),
+    AddToPalette([u8; 4]),
+}
+
+struct FileDialog {
+    kind: FileDialogKind,
+    path: String,
+    error: Option<String>,
+}
+
+struct NewDocumentDialog {
+    settings: NewDocument,
+    // Built-in presets first, then the user's own
+    presets: Vec<Preset>,
+    user_presets: Vec<Preset>,
+    preset_name: String,
+    error: Option<String>,
+}
 
 pub struct AppState {
     surface: wgpu::Surface<'static>,
@@ -15,8 +161,12 @@ pub struct AppState {
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
     render_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
+    view_buffer: wgpu::Buffer,
 
+    document: Document,
+    // The document's texture on the GPU
     canvas: Canvas,
     lua: LuaEngine,
     packages: PackageManager,
@@ -25,13 +175,71 @@ pub struct AppState {
     egui_state: egui_winit::State,
     egui_renderer: egui_wgpu::Renderer,
 
-    mouse_pos: (f32, f32),
-    last_mouse_pos: Option<(f32, f32)>,
-    mouse_pressed: bool,
-    brush_color: [f32; 3],
+    // Window position (physical pixels) as reported by winit
+    mouse_pos: (f64, f64),
+    // The Lua tool stroke being drawn, if any (painted on the next update)
+    stroke: ToolStroke,
+    // Mouse and pen/touch events become samples with pressure here
+    input: InputPipeline,
+    modifiers: ModifiersState,
+    // Last position on_hover was told about
+    last_hover_pos: Option<(f64, f64)>,
+    // Event timestamps for Lua tools count from here
+    started: Instant,
+    viewport: Viewport,
+    // Space (or the middle button) turns dragging into panning
+    space_held: bool,
+    panning: bool,
+    colors: ColorPair,
+    // Which button is drawing (or picking): left paints with primary, right with secondary
+    paint_button: Option<ColorTarget>,
     // Removed: brush_size, antialiasing (Lua handles these now)
     active_cursor_texture: Option<TextureHandle>,
     active_tool_name: String,
+
+    // A selection tool replaces the Lua tool while it is active
+    selection_tool: Option<SelectionTool>,
+    selection_mode: SelectionMode,
+    selection_antialias: bool,
+    wand_options: WandOptions,
+    // Canvas points of the selection being dragged out (or clicked, for the polygon lasso)
+    selection_points: Option<Vec<(f64, f64)>>,
+    last_click: Option<Instant>,
+    // Marching ants for the current selection, rebuilt when its version changes
+    selection_outline: (u64, Vec<[(f64, f64); 2]>),
+
+    // The text tool also replaces the Lua tool while it is active
+    text_active: bool,
+    text_tool: TextTool,
+    // What the stroke buffer currently shows, so the preview is only redrawn on changes
+    text_preview: Option<(TextTool, [f32; 4])>,
+    fonts: Rc<FontLibrary>,
+    font_families: Vec<String>,
+
+    eyedropper_active: bool,
+    eyedropper: EyedropperOptions,
+    // Button held down with the eyedropper (or Alt): the color follows the cursor
+    picking_color: bool,
+
+    // Where Save writes to; None until the document has been opened or saved
+    document_path: Option<PathBuf>,
+    export_options: ExportOptions,
+    file_dialog: Option<FileDialog>,
+    new_document_dialog: Option<NewDocumentDialog>,
+    saved_palettes: Vec<Palette>,
+    palette_choice: PaletteChoice,
+    new_palette_name: String,
+    recent_colors: RecentColors,
+
+    keymap: Keymap,
+    shortcut_editor_open: bool,
+    // Action waiting for a key press in the shortcut editor
+    capturing_shortcut: Option<String>,
+    shortcut_filter: String,
+
+    // Remembered between File > New invocations
+    last_new_document: NewDocument,
+    status: String,
 }
 
 impl AppState {
@@ -67,18 +275,19 @@ impl AppState {
         };
         surface.configure(&device, &config);
 
-        let canvas = Canvas::new(&device, &queue, 800, 600);
+        let defaults = NewDocument::default();
+        let document = Document::new(defaults.width, defaults.height, defaults.fill_color());
+        let canvas = Canvas::new(&device, &queue, &document);
         let mut packages = PackageManager::new();
         packages.load_packages();
 
-        let mut lua = LuaEngine::new();
-        let mut active_tool_name = "None".to_string();
-
-        if let Some(first_tool) = packages.tools.first() {
-            println!("Auto-loading tool: {}", first_tool.name);
-            lua.load_tool(first_tool);
-            active_tool_name = first_tool.name.clone();
-        }
+        // Packages can ship fonts too
+        let fonts = Rc::new(FontLibrary::scan(&[PathBuf::from("packages")]));
+        let font_families = fonts.families();
+        let lua = LuaEngine::new(fonts.clone());
+        let mut actions = shortcuts::builtin_actions();
+        actions.extend(tool_actions(&packages));
+        let keymap = Keymap::new(actions);
 
         let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -99,23 +308,29 @@ impl AppState {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
-            ],
-            label: None,
-        });
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&canvas.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&canvas.sampler),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
             label: None,
         });
+        let view_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("View Uniform"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group =
+            Self::create_canvas_bind_group(&device, &bind_group_layout, &canvas, &view_buffer);
+        let mut viewport = Viewport::new();
+        viewport.fit((document.width, document.height), (size.width, size.height));
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
             layout: Some(
@@ -154,14 +369,17 @@ impl AppState {
         );
         let egui_renderer = egui_wgpu::Renderer::new(&device, config.format, None, 1);
 
-        Self {
+        let mut state = Self {
             surface,
             device,
             queue,
             config,
             size,
             render_pipeline,
+            bind_group_layout,
             bind_group,
+            view_buffer,
+            document,
             canvas,
             lua,
             packages,
@@ -169,13 +387,313 @@ impl AppState {
             egui_state,
             egui_renderer,
             mouse_pos: (0.0, 0.0),
-            last_mouse_pos: None,
-            mouse_pressed: false,
-            brush_color: [0.0, 0.0, 0.0],
+            stroke: ToolStroke::new(StabilizerSettings::default()),
+            input: InputPipeline::new(PressureCurve::load()),
+            modifiers: ModifiersState::empty(),
+            last_hover_pos: None,
+            started: Instant::now(),
+            viewport,
+            space_held: false,
+            panning: false,
+            colors: ColorPair::default(),
+            paint_button: None,
             // Removed size/aa defaults
             active_cursor_texture: None,
-            active_tool_name,
+            active_tool_name: "None".to_string(),
+            selection_tool: None,
+            selection_mode: SelectionMode::Replace,
+            selection_antialias: true,
+            wand_options: WandOptions::default(),
+            selection_points: None,
+            last_click: None,
+            selection_outline: (0, Vec::new()),
+            text_active: false,
+            text_tool: TextTool::default(),
+            text_preview: None,
+            fonts,
+            font_families,
+            eyedropper_active: false,
+            eyedropper: EyedropperOptions::default(),
+            picking_color: false,
+            document_path: None,
+            export_options: ExportOptions::default(),
+            file_dialog: None,
+            new_document_dialog: None,
+            last_new_document: NewDocument::default(),
+            saved_palettes: palette::load_saved_palettes(),
+            palette_choice: PaletteChoice::Document,
+            new_palette_name: String::new(),
+            recent_colors: RecentColors::default(),
+            keymap,
+            shortcut_editor_open: false,
+            capturing_shortcut: None,
+            shortcut_filter: String::new(),
+            status: String::new(),
+        };
+        if !state.packages.tools.is_empty() {
+            println!("Auto-loading tool: {}", state.packages.tools[0].name);
+            state.activate_tool(ToolChoice::Lua(0));
         }
+        state
+    }
+
+    fn create_canvas_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        canvas: &Canvas,
+        view_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&canvas.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&canvas.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: view_buffer.as_entire_binding(),
+                },
+            ],
+            label: None,
+        })
+    }
+
+    /// Swaps in a new document; the old texture goes away with the old bind group
+    fn set_document(&mut self, document: Document) {
+        let canvas = Canvas::new(&self.device, &self.queue, &document);
+        self.bind_group = Self::create_canvas_bind_group(
+            &self.device,
+            &self.bind_group_layout,
+            &canvas,
+            &self.view_buffer,
+        );
+        self.document = document;
+        self.canvas = canvas;
+        self.stroke.reset();
+        self.selection_points = None;
+        // The preview lived in the old document's stroke buffer
+        self.text_preview = None;
+        self.fit_to_window();
+    }
+
+    fn canvas_size(&self) -> (u32, u32) {
+        (self.document.width, self.document.height)
+    }
+
+    fn window_size(&self) -> (u32, u32) {
+        (self.size.width, self.size.height)
+    }
+
+    fn fit_to_window(&mut self) {
+        self.viewport.fit(self.canvas_size(), self.window_size());
+    }
+
+    /// Zooms around the window center (menu and keyboard zoom)
+    fn zoom_by(&mut self, factor: f64) {
+        let center = (self.size.width as f64 / 2.0, self.size.height as f64 / 2.0);
+        self.viewport
+            .zoom_at(factor, center, self.canvas_size(), self.window_size());
+    }
+
+    /// Where the cursor is on the canvas, in canvas pixels
+    fn mouse_canvas_pos(&self) -> (f64, f64) {
+        self.viewport
+            .screen_to_canvas(self.mouse_pos, self.canvas_size(), self.window_size())
+    }
+
+    pub fn update_title(&self, window: &Window) {
+        let name = self
+            .document_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Untitled".to_string());
+        window.set_title(&format!("{} - Pixle {}", name, env!("CARGO_PKG_VERSION")));
+    }
+
+    fn open_file_dialog(&mut self, kind: FileDialogKind) {
+        let path = match (&self.document_path, kind) {
+            (Some(path), FileDialogKind::SaveAs | FileDialogKind::Export) => {
+                path.to_string_lossy().to_string()
+            }
+            (None, FileDialogKind::SaveAs) => format!("Untitled.{}", project::EXTENSION),
+            (_, FileDialogKind::ExportPalette) => format!("{}.gpl", self.current_palette().name),
+            _ => String::new(),
+        };
+        self.file_dialog = Some(FileDialog {
+            kind,
+            path,
+            error: None,
+        });
+    }
+
+    fn open_new_document_dialog(&mut self) {
+        self.new_document_dialog = Some(NewDocumentDialog {
+            settings: self.last_new_document.clone(),
+            presets: new_document::builtin_presets(),
+            user_presets: new_document::load_user_presets(),
+            preset_name: String::new(),
+            error: None,
+        });
+    }
+
+    /// File > New: replaces the document (and its texture) with a blank one
+    fn create_new_document(&mut self, window: &Window) {
+        let Some(dialog) = &mut self.new_document_dialog else {
+            return;
+        };
+        if let Err(e) = dialog.settings.validate() {
+            dialog.error = Some(e);
+            return;
+        }
+        let settings = dialog.settings.clone();
+        self.new_document_dialog = None;
+
+        let mut document = Document::new(settings.width, settings.height, settings.fill_color());
+        document.dpi = settings.dpi;
+        self.set_document(document);
+        self.document_path = None;
+        self.status = format!("New {} x {} document", settings.width, settings.height);
+        self.last_new_document = settings;
+        self.update_title(window);
+    }
+
+    /// Opens a .pixle project, or any flat image as a single layer
+    fn open_document(&mut self, path: &Path) -> Result<(), String> {
+        let document = if project::is_project_path(path) {
+            let doc = project::load_project(path).map_err(|e| e.to_string())?;
+            let mut document = Document::from_layers(doc.width, doc.height, doc.layers);
+            document.active_layer = doc.active_layer;
+            document.dpi = doc.dpi;
+            document.palette.swatches = doc.palette;
+            document.selection.set_mask(doc.selection);
+            document
+        } else {
+            let img = image_io::load_image(path).map_err(|e| match e {
+                ImageError::Limits(_) => {
+                    let max = new_document::MAX_DOCUMENT_SIZE;
+                    format!("image is larger than {} x {} pixels", max, max)
+                }
+                e => e.to_string(),
+            })?;
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "Background".to_string());
+            let layer = Layer::from_pixels(&name, img.pixels);
+            Document::from_layers(img.width, img.height, vec![layer])
+        };
+        self.set_document(document);
+        self.document_path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Saves the layered project for .pixle paths, otherwise a flattened image
+    fn save_document(&self, path: &Path) -> Result<(), String> {
+        if !project::is_project_path(path) {
+            return self.save_image(path);
+        }
+        let doc = project::Project {
+            width: self.document.width,
+            height: self.document.height,
+            layers: self.document.layers.clone(),
+            active_layer: self.document.active_layer,
+            dpi: self.document.dpi,
+            palette: self.document.palette.swatches.clone(),
+            selection: self.document.selection.mask().map(|m| m.to_vec()),
+        };
+        project::save_project(path, &doc).map_err(|e| e.to_string())
+    }
+
+    fn save_image(&self, path: &Path) -> Result<(), String> {
+        image_io::save_image(
+            path,
+            self.document.width,
+            self.document.height,
+            &self.document.flatten(),
+            &self.export_options,
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    /// File > Save: writes to the current path, or asks for one
+    fn save(&mut self) {
+        match self.document_path.clone() {
+            Some(path) => {
+                self.status = match self.save_document(&path) {
+                    Ok(()) => format!("Saved {}", path.display()),
+                    Err(e) => format!("Save failed: {}", e),
+                };
+            }
+            None => self.open_file_dialog(FileDialogKind::SaveAs),
+        }
+    }
+
+    fn confirm_file_dialog(&mut self, window: &Window) {
+        let Some(dialog) = &self.file_dialog else {
+            return;
+        };
+        let kind = dialog.kind;
+        let path = PathBuf::from(dialog.path.trim());
+
+        let result = match kind {
+            FileDialogKind::Open => self.open_document(&path),
+            FileDialogKind::SaveAs => self.save_document(&path),
+            FileDialogKind::Export => self.save_image(&path),
+            FileDialogKind::ImportPalette => self.import_palette(&path),
+            FileDialogKind::ExportPalette => palette::save_palette(&path, self.current_palette()),
+        };
+
+        match result {
+            Ok(()) => {
+                if kind == FileDialogKind::SaveAs {
+                    self.document_path = Some(path.clone());
+                }
+                self.status = format!("{} {}", kind.action(), path.display());
+                self.file_dialog = None;
+                self.update_title(window);
+            }
+            Err(e) => {
+                if let Some(dialog) = &mut self.file_dialog {
+                    dialog.error = Some(e);
+                }
+            }
+        }
+    }
+
+    fn current_palette(&self) -> &Palette {
+        match self.palette_choice {
+            PaletteChoice::Document => &self.document.palette,
+            PaletteChoice::Saved(i) => &self.saved_palettes[i],
+            PaletteChoice::Package(i) => &self.packages.palettes[i],
+        }
+    }
+
+    /// Adds a palette file to the library (replacing one with the same name) and shows it
+    fn import_palette(&mut self, path: &Path) -> Result<(), String> {
+        let imported = palette::load_palette(path)?;
+        palette::save_to_library(&imported)?;
+        let index = match self
+            .saved_palettes
+            .iter()
+            .position(|p| p.name == imported.name)
+        {
+            Some(index) => {
+                self.saved_palettes[index] = imported;
+                index
+            }
+            None => {
+                self.saved_palettes.push(imported);
+                self.saved_palettes.len() - 1
+            }
+        };
+        self.palette_choice = PaletteChoice::Saved(index);
+        Ok(())
     }
 
     fn load_cursor_image(&mut self, path_str: &str) {
@@ -183,19 +701,19 @@ impl AppState {
         if !path.exists() {
             return;
         }
-        if let Ok(reader) = ImageReader::open(path) {
-            if let Ok(img) = reader.decode() {
-                let size = [img.width() as usize, img.height() as usize];
-                let color_image = egui::ColorImage::from_rgba_unmultiplied(
-                    size,
-                    img.to_rgba8().as_flat_samples().as_slice(),
-                );
-                self.active_cursor_texture = Some(self.egui_ctx.load_texture(
-                    "custom_cursor",
-                    color_image,
-                    TextureOptions::LINEAR,
-                ));
-            }
+        if let Ok(reader) = ImageReader::open(path)
+            && let Ok(img) = reader.decode()
+        {
+            let size = [img.width() as usize, img.height() as usize];
+            let color_image = egui::ColorImage::from_rgba_unmultiplied(
+                size,
+                img.to_rgba8().as_flat_samples().as_slice(),
+            );
+            self.active_cursor_texture = Some(self.egui_ctx.load_texture(
+                "custom_cursor",
+                color_image,
+                TextureOptions::LINEAR,
+            ));
         }
     }
 
@@ -212,68 +730,950 @@ impl AppState {
         let _ = self.egui_state.on_window_event(window, event);
         match event {
             WindowEvent::CursorMoved { position, .. } => {
-                self.mouse_pos = (position.x as f32, position.y as f32);
+                let event = InputEvent::CursorMoved {
+                    x: position.x,
+                    y: position.y,
+                };
+                if let Some(PointerEvent::Move(sample)) = self.input.handle(&event) {
+                    self.move_pointer(sample);
+                }
+            }
+            // Pens and fingers draw like the left button, with pressure
+            WindowEvent::Touch(touch) => match self.input.handle(&InputEvent::from(touch)) {
+                Some(PointerEvent::Down(sample)) => {
+                    self.move_pointer(sample);
+                    self.handle_paint_button(ColorTarget::Primary, true);
+                }
+                Some(PointerEvent::Move(sample)) => self.move_pointer(sample),
+                Some(PointerEvent::Up(sample)) => {
+                    self.move_pointer(sample);
+                    self.handle_paint_button(ColorTarget::Primary, false);
+                }
+                None => {}
+            },
+            // Mouse buttons emulated for a touch would end its stroke early
+            WindowEvent::MouseInput { .. } if self.input.touch_active() => {}
+            WindowEvent::MouseInput {
+                state: element_state,
+                button: MouseButton::Middle,
+                ..
+            } => {
+                self.panning = *element_state == ElementState::Pressed
+                    && !self.egui_ctx.is_pointer_over_area();
             }
             WindowEvent::MouseInput {
                 state: element_state,
                 button: MouseButton::Left,
                 ..
             } => {
-                self.mouse_pressed = *element_state == ElementState::Pressed;
-
-                if !self.mouse_pressed {
-                    // MOUSE RELEASED: Commit the stroke!
-                    self.last_mouse_pos = None;
-                    self.canvas.commit_stroke();
-                    self.canvas.update_texture(&self.queue); // Update one last time to clear the preview
+                let pressed = *element_state == ElementState::Pressed;
+                if pressed && self.space_held && !self.egui_ctx.is_pointer_over_area() {
+                    self.panning = true;
+                    return;
+                }
+                if !pressed && self.panning {
+                    self.panning = false;
+                    return;
+                }
+                self.handle_paint_button(ColorTarget::Primary, pressed);
+            }
+            WindowEvent::MouseInput {
+                state: element_state,
+                button: MouseButton::Right,
+                ..
+            } => {
+                let pressed = *element_state == ElementState::Pressed;
+                self.handle_paint_button(ColorTarget::Secondary, pressed);
+            }
+            WindowEvent::MouseWheel { delta, .. } if !self.egui_ctx.is_pointer_over_area() => {
+                let (steps_x, steps) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (*x as f64, *y as f64),
+                    MouseScrollDelta::PixelDelta(pos) => (pos.x / 50.0, pos.y / 50.0),
+                };
+                // Lua tools can take the wheel over (e.g. for resizing the brush)
+                if self.lua_tool_active() {
+                    let event = ToolEvent {
+                        scroll: (steps_x, steps),
+                        ..self.tool_event()
+                    };
+                    if self.dispatch_tool_event("on_scroll", event) {
+                        return;
+                    }
                 }
+                self.viewport.zoom_at(
+                    1.1f64.powf(steps),
+                    self.mouse_pos,
+                    self.canvas_size(),
+                    self.window_size(),
+                );
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            // The shortcut editor is waiting for a key
+            WindowEvent::KeyboardInput { event, .. }
+                if self.capturing_shortcut.is_some() && event.state == ElementState::Pressed =>
+            {
+                self.capture_shortcut(&event.logical_key);
+            }
+            WindowEvent::KeyboardInput { event, .. }
+                if event.logical_key == Key::Named(NamedKey::Space) =>
+            {
+                self.space_held = event.state == ElementState::Pressed;
             }
+            WindowEvent::KeyboardInput { event, .. } if !self.egui_ctx.wants_keyboard_input() => {
+                // Lua tools see keys first and can keep them from triggering shortcuts
+                if self.lua_tool_active()
+                    && let Some(shortcut) = Shortcut::from_key(&event.logical_key, self.modifiers)
+                {
+                    let tool_event = ToolEvent {
+                        key: Some(shortcut.key),
+                        pressed: event.state == ElementState::Pressed,
+                        ..self.tool_event()
+                    };
+                    if self.dispatch_tool_event("on_key", tool_event) {
+                        return;
+                    }
+                }
+                if event.state != ElementState::Pressed {
+                    return;
+                }
+                match event.logical_key {
+                    // Polygon lasso: Enter closes the shape, Escape drops it.
+                    // Same for pending text: Enter commits, Escape throws it away
+                    Key::Named(NamedKey::Enter) if self.text_active => self.commit_text(),
+                    Key::Named(NamedKey::Escape) if self.text_active => self.cancel_text(),
+                    Key::Named(NamedKey::Enter) => self.finish_selection(),
+                    Key::Named(NamedKey::Escape) => self.selection_points = None,
+                    _ => self.handle_shortcut(&event.logical_key),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn move_pointer(&mut self, sample: Sample) {
+        let new_pos = (sample.x, sample.y);
+        if self.panning {
+            self.viewport.pan.0 += new_pos.0 - self.mouse_pos.0;
+            self.viewport.pan.1 += new_pos.1 - self.mouse_pos.1;
+        }
+        self.mouse_pos = new_pos;
+        // Every sample counts while drawing, not just the last one of the frame
+        if self.stroke.is_active() {
+            let point = StrokePoint {
+                x: sample.x,
+                y: sample.y,
+                pressure: sample.pressure,
+            };
+            self.with_tool(|stroke, ctx| {
+                stroke.push(ctx, point);
+                false
+            });
+        }
+    }
+
+    /// Left or right button on the canvas; `button` is the color the right one paints with
+    fn handle_paint_button(&mut self, button: ColorTarget, pressed: bool) {
+        // One button at a time: the other one is ignored until this one is released
+        match (pressed, self.paint_button) {
+            (true, Some(_)) => return,
+            (false, Some(held)) if held != button => return,
             _ => {}
         }
+        // Selection and text only use the left button
+        let left_only = self.selection_tool.is_some() || self.text_active;
+        if button == ColorTarget::Secondary && left_only {
+            return;
+        }
+        self.paint_button = pressed.then_some(button);
+
+        // Alt+click picks a color in any tool (selection tools use Alt for subtracting)
+        let alt_pick = self.modifiers.alt_key() && self.selection_tool.is_none();
+        if pressed && (self.eyedropper_active || alt_pick) && !self.egui_ctx.is_pointer_over_area()
+        {
+            self.picking_color = true;
+            self.pick_color();
+            return;
+        }
+        if !pressed && self.picking_color {
+            self.picking_color = false;
+            return;
+        }
+        if let Some(tool) = self.selection_tool {
+            self.handle_selection_click(tool, pressed);
+            return;
+        }
+        if self.text_active {
+            // Clicking (again) places the text; typing happens in the panel
+            if pressed && !self.egui_ctx.is_pointer_over_area() {
+                self.text_tool.anchor = Some(self.mouse_canvas_pos());
+            }
+            return;
+        }
+
+        if pressed {
+            // Clicks on the panels aren't strokes
+            if self.egui_ctx.is_pointer_over_area() {
+                return;
+            }
+            let start = StrokePoint {
+                x: self.mouse_pos.0,
+                y: self.mouse_pos.1,
+                pressure: self.input.last().pressure,
+            };
+            self.with_tool(|stroke, ctx| stroke.press(ctx, button, start));
+        } else if self.stroke.is_active() {
+            self.with_tool(|stroke, ctx| {
+                stroke.release(ctx);
+                // Committing clears the preview
+                true
+            });
+            self.recent_colors
+                .push(colors::to_rgba8(self.colors.get(button)));
+        }
+    }
+
+    fn handle_shortcut(&mut self, key: &Key) {
+        let Some(shortcut) = Shortcut::from_key(key, self.modifiers) else {
+            return;
+        };
+        if let Some(id) = self.keymap.action_for(&shortcut).map(str::to_string) {
+            self.run_action(&id);
+        }
+    }
+
+    /// Runs an action from the keymap (or a menu) by its id
+    fn run_action(&mut self, id: &str) {
+        // Don't rewrite history (or swap documents) under a stroke that's still being drawn
+        if self.stroke.is_active() && !id.starts_with("colors.") {
+            return;
+        }
+        match id {
+            "file.new" => self.open_new_document_dialog(),
+            "file.open" => self.open_file_dialog(FileDialogKind::Open),
+            "file.save" => self.save(),
+            "file.save_as" => self.open_file_dialog(FileDialogKind::SaveAs),
+            "file.export" => self.open_file_dialog(FileDialogKind::Export),
+            "edit.undo" => self.undo(),
+            "edit.redo" => self.redo(),
+            "select.all" => self.document.select_all(),
+            "select.none" => self.document.select_none(),
+            "select.invert" => self.document.invert_selection(),
+            "view.zoom_in" => self.zoom_by(1.25),
+            "view.zoom_out" => self.zoom_by(0.8),
+            "view.fit" => self.fit_to_window(),
+            "view.actual_size" => self.viewport.actual_size(),
+            "colors.swap" => self.colors.swap(),
+            "colors.reset" => self.colors.reset(),
+            _ => {
+                let choice = ToolChoice::all(&self.packages)
+                    .into_iter()
+                    .find(|c| tool_action_id(&c.name(&self.packages)) == id);
+                match choice {
+                    Some(choice) => self.activate_tool(choice),
+                    None => println!("Unknown action: {}", id),
+                }
+            }
+        }
+    }
+
+    /// Binds the key pressed while the shortcut editor was waiting for one
+    fn capture_shortcut(&mut self, key: &Key) {
+        let Some(id) = self.capturing_shortcut.clone() else {
+            return;
+        };
+        if *key == Key::Named(NamedKey::Escape) {
+            self.capturing_shortcut = None;
+            return;
+        }
+        // A modifier on its own: keep waiting for the actual key
+        let Some(shortcut) = Shortcut::from_key(key, self.modifiers) else {
+            return;
+        };
+        self.capturing_shortcut = None;
+        self.keymap.set(&id, vec![shortcut.clone()]);
+        let conflicts = self.keymap.conflicts_with(&id);
+        if !conflicts.is_empty() {
+            self.status = format!("{} is also used by {}", shortcut, conflicts.join(", "));
+        }
+        self.save_keymap();
+    }
+
+    fn save_keymap(&mut self) {
+        if let Err(e) = self.keymap.save() {
+            self.status = format!("Could not save shortcuts: {}", e);
+        }
+    }
+
+    fn activate_tool(&mut self, choice: ToolChoice) {
+        if self.lua_tool_active() {
+            self.dispatch_tool_event("on_deactivate", self.tool_event());
+            // Whatever preview the tool left behind goes with it
+            self.document.clear_stroke();
+            self.canvas.update_texture(&self.queue, &self.document);
+        }
+        self.leave_builtin_tools();
+        match choice {
+            ToolChoice::Lua(index) => {
+                let tool = self.packages.tools[index].clone();
+                self.lua.load_tool(&tool);
+                self.last_hover_pos = None;
+                self.dispatch_tool_event("on_activate", self.tool_event());
+                match self.lua.get_current_cursor() {
+                    CursorType::SystemCircle => self.active_cursor_texture = None,
+                    CursorType::CustomImage(path) => self.load_cursor_image(&path),
+                }
+            }
+            ToolChoice::Selection(tool) => self.selection_tool = Some(tool),
+            ToolChoice::Text => self.text_active = true,
+            ToolChoice::Eyedropper => self.eyedropper_active = true,
+        }
+        self.active_tool_name = choice.name(&self.packages);
+    }
+
+    /// Mouse button on the canvas while a selection tool is active
+    fn handle_selection_click(&mut self, tool: SelectionTool, pressed: bool) {
+        if self.egui_ctx.is_pointer_over_area() && self.selection_points.is_none() {
+            return;
+        }
+        let pos = self.mouse_canvas_pos();
+        if tool == SelectionTool::MagicWand {
+            if pressed && pos.0 >= 0.0 && pos.1 >= 0.0 {
+                let mode = self.selection_mode_for_modifiers();
+                self.document.select_color(
+                    (pos.0 as u32, pos.1 as u32),
+                    &self.wand_options,
+                    mode,
+                    self.selection_antialias,
+                );
+            }
+            return;
+        }
+        if tool != SelectionTool::PolygonLasso {
+            // Drag tools: press starts, release finishes
+            if pressed {
+                self.selection_points = Some(vec![pos, pos]);
+            } else {
+                self.finish_selection();
+            }
+            return;
+        }
+        if !pressed {
+            return;
+        }
+
+        let double_click = self
+            .last_click
+            .is_some_and(|t| t.elapsed() < Duration::from_millis(300));
+        self.last_click = Some(Instant::now());
+        let Some(points) = &mut self.selection_points else {
+            // The last point always follows the cursor
+            self.selection_points = Some(vec![pos, pos]);
+            return;
+        };
+        // Clicking on the first point (or double clicking) closes the polygon
+        let first = self.viewport.canvas_to_screen(
+            points[0],
+            (self.document.width, self.document.height),
+            (self.size.width, self.size.height),
+        );
+        let near_start = (first.0 - self.mouse_pos.0).hypot(first.1 - self.mouse_pos.1) < 6.0
+            && points.len() > 3;
+        if double_click || near_start {
+            points.pop();
+            self.finish_selection();
+        } else {
+            points.push(pos);
+        }
+    }
+
+    /// Modifiers override the mode picked in the panel
+    fn selection_mode_for_modifiers(&self) -> SelectionMode {
+        match (self.modifiers.shift_key(), self.modifiers.alt_key()) {
+            (true, true) => SelectionMode::Intersect,
+            (true, false) => SelectionMode::Add,
+            (false, true) => SelectionMode::Subtract,
+            (false, false) => self.selection_mode,
+        }
+    }
+
+    /// Turns the collected points into a shape and merges it into the document's selection
+    fn finish_selection(&mut self) {
+        let (Some(tool), Some(points)) = (self.selection_tool, self.selection_points.take()) else {
+            return;
+        };
+        let mode = self.selection_mode_for_modifiers();
+        let Some(shape) = tool.shape(&points) else {
+            return;
+        };
+        // A plain click with a drag tool just deselects
+        let outline = shape.outline();
+        let (min_x, max_x) = outline.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| {
+            (lo.min(p.0), hi.max(p.0))
+        });
+        let (min_y, max_y) = outline.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| {
+            (lo.min(p.1), hi.max(p.1))
+        });
+        if max_x - min_x < 1.0 || max_y - min_y < 1.0 {
+            if mode == SelectionMode::Replace {
+                self.document.select_none();
+            }
+            return;
+        }
+        self.document.select(&shape, mode, self.selection_antialias);
+    }
+
+    /// Sets the eyedropper's target color from the pixels under the cursor
+    fn pick_color(&mut self) {
+        let (x, y) = self.mouse_canvas_pos();
+        if x < 0.0 || y < 0.0 {
+            return;
+        }
+        let options = self.eyedropper;
+        let Some(color) = self.document.sample_color(
+            x as u32,
+            y as u32,
+            options.size.radius(),
+            options.sample_merged,
+        ) else {
+            return;
+        };
+        // The right button picks into the other color
+        let target = match self.paint_button {
+            Some(ColorTarget::Secondary) => options.target.other(),
+            _ => options.target,
+        };
+        self.colors.set(target, color.map(|c| c as f32 / 255.0));
+    }
+
+    /// Drops out of whichever built-in tool is active (pending text gets committed)
+    fn leave_builtin_tools(&mut self) {
+        self.selection_tool = None;
+        self.selection_points = None;
+        self.commit_text();
+        self.text_active = false;
+        self.eyedropper_active = false;
+    }
+
+    /// Bakes the previewed text onto the active layer
+    fn commit_text(&mut self) {
+        if self.text_preview.take().is_some() {
+            self.recent_colors
+                .push(colors::to_rgba8(self.colors.primary));
+            self.document.commit_stroke_as("Text");
+            self.canvas.update_texture(&self.queue, &self.document);
+        }
+        self.text_tool.text.clear();
+        self.text_tool.anchor = None;
+    }
+
+    fn cancel_text(&mut self) {
+        if self.text_preview.take().is_some() {
+            self.document.clear_stroke();
+            self.canvas.update_texture(&self.queue, &self.document);
+        }
+        self.text_tool.anchor = None;
+    }
+
+    /// Redraws the text preview into the stroke buffer when anything about it changed
+    fn update_text_preview(&mut self) {
+        let key = (self.text_tool.clone(), self.colors.primary);
+        if self.text_preview.as_ref() == Some(&key) {
+            return;
+        }
+        let color = colors::to_rgba8(self.colors.primary);
+        self.document.clear_stroke();
+        if let Some(cmd) = self.text_tool.command(&self.fonts, color) {
+            self.document.apply_command(&cmd);
+        }
+        self.canvas.update_texture(&self.queue, &self.document);
+        self.text_preview = Some(key);
+    }
+
+    /// True when none of the built-in tools has taken over from the Lua one
+    fn lua_tool_active(&self) -> bool {
+        self.selection_tool.is_none() && !self.text_active && !self.eyedropper_active
+    }
+
+    /// An event for Lua tool callbacks at the cursor's current position
+    fn tool_event(&self) -> ToolEvent {
+        let (x, y) = self.mouse_canvas_pos();
+        ToolEvent {
+            x,
+            y,
+            button: self.paint_button,
+            pressure: self.input.last().pressure,
+            tilt: self.input.last().tilt,
+            time: self.started.elapsed().as_secs_f64(),
+            ..Default::default()
+        }
+    }
+
+    /// Calls a Lua tool callback and applies what it painted; true if the tool handled the event
+    fn dispatch_tool_event(&mut self, callback: &str, event: ToolEvent) -> bool {
+        let mut handled = false;
+        self.with_tool(|_, ctx| {
+            let (h, painted) = ctx.dispatch(callback, &event);
+            handled = h;
+            painted
+        });
+        handled
+    }
+
+    /// Runs `f` with the Lua tool stroke and what it paints on; the texture is updated
+    /// when `f` returns true (something was painted)
+    fn with_tool(&mut self, f: impl FnOnce(&mut ToolStroke, &mut StrokeContext) -> bool) {
+        let event = self.tool_event();
+        let (viewport, canvas, window) = (self.viewport, self.canvas_size(), self.window_size());
+        let to_canvas = move |x, y| viewport.screen_to_canvas((x, y), canvas, window);
+        let mut ctx = StrokeContext {
+            lua: &mut self.lua,
+            document: &mut self.document,
+            colors: &self.colors,
+            modifiers: self.modifiers,
+            event,
+            to_canvas: &to_canvas,
+        };
+        if f(&mut self.stroke, &mut ctx) {
+            self.canvas.update_texture(&self.queue, &self.document);
+        }
+    }
+
+    fn undo(&mut self) {
+        if self.document.undo() {
+            self.canvas.update_texture(&self.queue, &self.document);
+        }
+    }
+
+    fn redo(&mut self) {
+        if self.document.redo() {
+            self.canvas.update_texture(&self.queue, &self.document);
+        }
     }
 
     pub fn update(&mut self) {
+        if self.picking_color {
+            self.pick_color();
+            return;
+        }
+        if self.text_active {
+            self.update_text_preview();
+            return;
+        }
+
+        // Selection tools follow the cursor even over panels so a drag isn't lost
+        if let (Some(tool), Some(points)) = (self.selection_tool, &mut self.selection_points) {
+            let pos = self.viewport.screen_to_canvas(
+                self.mouse_pos,
+                (self.document.width, self.document.height),
+                (self.size.width, self.size.height),
+            );
+            if tool == SelectionTool::Lasso {
+                let last = points[points.len() - 1];
+                if (last.0 - pos.0).hypot(last.1 - pos.1) >= 1.0 / self.viewport.zoom {
+                    points.push(pos);
+                }
+            } else if let Some(last) = points.last_mut() {
+                *last = pos;
+            }
+            return;
+        }
+
         if self.egui_ctx.is_pointer_over_area() || self.egui_ctx.is_using_pointer() {
             return;
         }
 
-        if self.mouse_pressed {
-            let scale_x = self.canvas.width as f32 / self.size.width as f32;
-            let scale_y = self.canvas.height as f32 / self.size.height as f32;
-            let current_pos = self.mouse_pos;
-            let start_pos = self.last_mouse_pos.unwrap_or(current_pos);
+        let current_pos = self.mouse_canvas_pos();
+        if self.stroke.is_active() {
+            self.with_tool(|stroke, ctx| stroke.paint(ctx));
+        } else if self.lua_tool_active() && self.last_hover_pos != Some(current_pos) {
+            let event = ToolEvent {
+                prev: self.last_hover_pos,
+                ..self.tool_event()
+            };
+            self.dispatch_tool_event("on_hover", event);
+            self.last_hover_pos = Some(current_pos);
+        }
+    }
+
+    /// Marching ants around the selection, plus the outline of the one being made
+    fn draw_selection(&mut self, ctx: &egui::Context) {
+        let selection = &self.document.selection;
+        if self.selection_outline.0 != selection.version() {
+            self.selection_outline = (selection.version(), selection.outline());
+        }
+
+        let painter = ctx.layer_painter(LayerId::background());
+        let canvas = self.canvas_size();
+        let window = self.window_size();
+        let to_screen = |p: (f64, f64)| {
+            let (x, y) = self.viewport.canvas_to_screen(p, canvas, window);
+            egui::pos2(x as f32, y as f32)
+        };
+        // The dashes crawl along over time
+        let offset = (ctx.input(|i| i.time) * 16.0 % 8.0) as f32;
+        let ants = |painter: &egui::Painter, path: &[egui::Pos2]| {
+            painter.add(egui::Shape::line(
+                path.to_vec(),
+                Stroke::new(1.0, Color32::WHITE),
+            ));
+            painter.extend(egui::Shape::dashed_line_with_offset(
+                path,
+                Stroke::new(1.0, Color32::BLACK),
+                &[4.0],
+                &[4.0],
+                offset,
+            ));
+        };
+
+        for [a, b] in &self.selection_outline.1 {
+            ants(&painter, &[to_screen(*a), to_screen(*b)]);
+        }
 
-            let start_tex_x = (start_pos.0 * scale_x) as u32;
-            let start_tex_y = (start_pos.1 * scale_y) as u32;
-            let end_tex_x = (current_pos.0 * scale_x) as u32;
-            let end_tex_y = (current_pos.1 * scale_y) as u32;
+        if let (Some(tool), Some(points)) = (self.selection_tool, &self.selection_points) {
+            // A lasso that isn't a closed shape yet shows what has been drawn so far
+            let shape = tool
+                .shape(points)
+                .unwrap_or_else(|| SelectionShape::Polygon(points.clone()));
+            let mut path: Vec<egui::Pos2> = shape.outline().into_iter().map(to_screen).collect();
+            if let Some(first) = path.first().copied() {
+                path.push(first);
+            }
+            ants(&painter, &path);
+        }
+    }
 
-            let commands = self.lua.process_input(
-                start_tex_x,
-                start_tex_y,
-                end_tex_x,
-                end_tex_y,
-                self.brush_color,
+    /// Text tool options in the Tools window
+    fn text_tool_ui(&mut self, ui: &mut egui::Ui) {
+        let text = &mut self.text_tool;
+        if text.anchor.is_none() {
+            ui.label("Click on the canvas to place the text");
+        }
+        ui.add(
+            egui::TextEdit::multiline(&mut text.text)
+                .desired_rows(3)
+                .hint_text("Text"),
+        );
+        egui::ComboBox::from_label("Font")
+            .selected_text(&text.family)
+            .show_ui(ui, |ui| {
+                for family in &self.font_families {
+                    ui.selectable_value(&mut text.family, family.clone(), family);
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("Size");
+            ui.add(
+                egui::DragValue::new(&mut text.size)
+                    .clamp_range(MIN_TEXT_SIZE..=MAX_TEXT_SIZE)
+                    .suffix(" px"),
             );
+            ui.checkbox(&mut text.bold, "Bold");
+            ui.checkbox(&mut text.italic, "Italic");
+        });
+        ui.horizontal(|ui| {
+            for align in TextAlign::ALL {
+                ui.radio_value(&mut text.align, align, align.name());
+            }
+        });
+        ui.add(egui::Slider::new(&mut text.line_spacing, 0.5..=3.0).text("Line Spacing"));
+        ui.checkbox(&mut text.antialias, "Anti-alias");
+        ui.horizontal(|ui| {
+            let pending = self.text_tool.anchor.is_some() && !self.text_tool.text.is_empty();
+            if ui
+                .add_enabled(pending, egui::Button::new("Commit"))
+                .on_hover_text("Enter")
+                .clicked()
+            {
+                self.commit_text();
+            }
+            if ui
+                .add_enabled(pending, egui::Button::new("Cancel"))
+                .on_hover_text("Escape")
+                .clicked()
+            {
+                self.cancel_text();
+            }
+        });
+    }
+
+    /// The pressure response curve, with a plot of it and the live pressure
+    fn pressure_ui(&mut self, ui: &mut egui::Ui) {
+        let curve = &mut self.input.curve;
+        let sliders = [
+            ui.add(egui::Slider::new(&mut curve.threshold, 0.0..=0.9).text("Threshold")),
+            ui.add(
+                egui::Slider::new(&mut curve.gamma, 0.2..=5.0)
+                    .logarithmic(true)
+                    .text("Firmness"),
+            ),
+            ui.add(egui::Slider::new(&mut curve.min, 0.0..=1.0).text("Minimum")),
+            ui.add(egui::Slider::new(&mut curve.max, 0.0..=1.0).text("Maximum")),
+        ];
+        // Written once a drag ends (or a value is typed in), not on every frame of it
+        let mut save = sliders
+            .iter()
+            .any(|r| r.drag_released() || (r.changed() && !r.dragged()));
+
+        // Input pressure left to right, what tools get bottom to top
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(120.0, 120.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_stroke(rect, 0.0, Stroke::new(1.0, Color32::GRAY));
+        let to_screen = |x: f32, y: f32| {
+            egui::pos2(
+                rect.left() + x * rect.width(),
+                rect.bottom() - y * rect.height(),
+            )
+        };
+        let points = (0..=32)
+            .map(|i| {
+                let x = i as f32 / 32.0;
+                to_screen(x, curve.apply(x))
+            })
+            .collect();
+        painter.add(egui::Shape::line(points, Stroke::new(2.0, Color32::WHITE)));
+        let current = self.input.last().pressure;
+        ui.label(format!("Pressure: {:.2}", current));
+
+        if ui.button("Reset").clicked() {
+            self.input.curve = PressureCurve::default();
+            save = true;
+        }
+        if save && let Err(e) = self.input.curve.save() {
+            self.status = format!("Could not save pressure curve: {}", e);
+        }
+    }
+
+    /// Every action with its shortcuts; "Set" waits for the next key press
+    fn shortcut_editor_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Filter");
+            ui.text_edit_singleline(&mut self.shortcut_filter);
+        });
+        let filter = self.shortcut_filter.to_lowercase();
+        let actions: Vec<ActionDef> = self
+            .keymap
+            .actions()
+            .iter()
+            .filter(|a| a.label.to_lowercase().contains(&filter))
+            .cloned()
+            .collect();
+        let mut changed = false;
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                egui::Grid::new("shortcut_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for action in &actions {
+                            ui.label(&action.label);
+                            if self.capturing_shortcut.as_ref() == Some(&action.id) {
+                                ui.label("Press a key... (Esc cancels)");
+                            } else {
+                                let conflicts = self.keymap.conflicts_with(&action.id);
+                                let label = self.keymap.label(&action.id);
+                                if !conflicts.is_empty() {
+                                    ui.colored_label(Color32::RED, label).on_hover_text(format!(
+                                        "Also used by {}",
+                                        conflicts.join(", ")
+                                    ));
+                                } else if label.is_empty() {
+                                    ui.weak("None");
+                                } else {
+                                    ui.label(label);
+                                }
+                            }
+                            if ui.small_button("Set").clicked() {
+                                self.capturing_shortcut = Some(action.id.clone());
+                            }
+                            if ui.small_button("Clear").clicked() {
+                                self.keymap.set(&action.id, Vec::new());
+                                changed = true;
+                            }
+                            let is_default =
+                                self.keymap.bindings(&action.id) == action.defaults.as_slice();
+                            if ui
+                                .add_enabled(!is_default, egui::Button::new("Reset").small())
+                                .clicked()
+                            {
+                                self.keymap.reset(&action.id);
+                                changed = true;
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        ui.separator();
+        if ui.button("Reset All").clicked() {
+            let ids: Vec<String> = self.keymap.actions().iter().map(|a| a.id.clone()).collect();
+            for id in ids {
+                self.keymap.reset(&id);
+            }
+            changed = true;
+        }
+        if changed {
+            self.save_keymap();
+        }
+    }
+
+    /// Contents of the Palette window
+    fn palette_ui(&mut self, ui: &mut egui::Ui) {
+        let packages = &self.packages;
+        let saved = &self.saved_palettes;
+        let choice = &mut self.palette_choice;
+        egui::ComboBox::from_id_source("palette_choice")
+            .selected_text(match *choice {
+                PaletteChoice::Document => "Document".to_string(),
+                PaletteChoice::Saved(i) => saved[i].name.clone(),
+                PaletteChoice::Package(i) => packages.palettes[i].name.clone(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(choice, PaletteChoice::Document, "Document");
+                for (i, p) in saved.iter().enumerate() {
+                    ui.selectable_value(choice, PaletteChoice::Saved(i), &p.name);
+                }
+                for (i, p) in packages.palettes.iter().enumerate() {
+                    let label = format!("{} (package)", p.name);
+                    ui.selectable_value(choice, PaletteChoice::Package(i), label);
+                }
+            });
+
+        // Package palettes can be used and copied, not edited
+        let editable = !matches!(self.palette_choice, PaletteChoice::Package(_));
+        let mut actions = Vec::new();
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing = egui::vec2(2.0, 2.0);
+            for (i, swatch) in self.current_palette().swatches.iter().enumerate() {
+                let response = swatch_button(ui, swatch.color, &swatch.name);
+                if editable {
+                    // Drag a swatch onto another one to move it there
+                    response.dnd_set_drag_payload(i);
+                    if let Some(from) = response.dnd_release_payload::<usize>() {
+                        actions.push(SwatchAction::Move(*from, i));
+                    }
+                }
+                if response.clicked() {
+                    actions.push(SwatchAction::Pick(ColorTarget::Primary, swatch.color));
+                }
+                response.context_menu(|ui| {
+                    if ui.button("Use as Secondary").clicked() {
+                        actions.push(SwatchAction::Pick(ColorTarget::Secondary, swatch.color));
+                        ui.close_menu();
+                    }
+                    if editable && ui.button("Replace with Primary").clicked() {
+                        actions.push(SwatchAction::Replace(i));
+                        ui.close_menu();
+                    }
+                    if editable && ui.button("Remove").clicked() {
+                        actions.push(SwatchAction::Remove(i));
+                        ui.close_menu();
+                    }
+                });
+            }
+        });
+        let primary = colors::to_rgba8(self.colors.primary);
+        if editable && ui.button("Add Primary Color").clicked() {
+            actions.push(SwatchAction::AddToPalette(primary));
+        }
 
-            let mut dirty = false;
-            for cmd in commands {
-                match cmd {
-                    PaintCommand::DrawPixel { x, y, r, g, b, a } => {
-                        // CHANGE: Draw to temporary stroke buffer
-                        self.canvas.draw_to_stroke(x, y, r, g, b, a);
-                        dirty = true;
+        ui.separator();
+        ui.label("Recent");
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing = egui::vec2(2.0, 2.0);
+            for color in self.recent_colors.colors() {
+                let response = swatch_button(ui, *color, "");
+                if response.clicked() {
+                    actions.push(SwatchAction::Pick(ColorTarget::Primary, *color));
+                }
+                response.context_menu(|ui| {
+                    if ui.button("Use as Secondary").clicked() {
+                        actions.push(SwatchAction::Pick(ColorTarget::Secondary, *color));
+                        ui.close_menu();
+                    }
+                    if editable && ui.button("Add to Palette").clicked() {
+                        actions.push(SwatchAction::AddToPalette(*color));
+                        ui.close_menu();
                     }
+                });
+            }
+        });
+
+        let edited = actions.iter().any(|a| !matches!(a, SwatchAction::Pick(..)));
+        for action in actions {
+            let palette = match self.palette_choice {
+                PaletteChoice::Document => &mut self.document.palette,
+                PaletteChoice::Saved(i) => &mut self.saved_palettes[i],
+                PaletteChoice::Package(i) => &mut self.packages.palettes[i],
+            };
+            match action {
+                SwatchAction::Pick(target, color) => {
+                    self.colors.set(target, color.map(|c| c as f32 / 255.0));
                 }
+                SwatchAction::Move(from, to) => palette.move_swatch(from, to),
+                SwatchAction::Replace(i) => palette.swatches[i].color = primary,
+                SwatchAction::Remove(i) => palette.remove(i),
+                SwatchAction::AddToPalette(color) => palette.add(color),
             }
+        }
+        // Library palettes are written back right away
+        if edited
+            && let PaletteChoice::Saved(i) = self.palette_choice
+            && let Err(e) = palette::save_to_library(&self.saved_palettes[i])
+        {
+            self.status = format!("Could not save palette: {}", e);
+        }
 
-            // CHANGE: Update texture (which now composites Stroke + Main)
-            if dirty {
-                self.canvas.update_texture(&self.queue);
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.new_palette_name);
+            let name = self.new_palette_name.trim().to_string();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("New"))
+                .on_hover_text("Start an empty palette in your library")
+                .clicked()
+            {
+                self.add_to_library(Palette::new(&name));
+                self.new_palette_name.clear();
+            }
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Save Copy"))
+                .on_hover_text("Copy this palette into your library under the new name")
+                .clicked()
+            {
+                let mut copy = self.current_palette().clone();
+                copy.name = name;
+                self.add_to_library(copy);
+                self.new_palette_name.clear();
             }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Import…").clicked() {
+                self.open_file_dialog(FileDialogKind::ImportPalette);
+            }
+            if ui.button("Export…").clicked() {
+                self.open_file_dialog(FileDialogKind::ExportPalette);
+            }
+            if let PaletteChoice::Saved(i) = self.palette_choice
+                && ui.button("Delete").clicked()
+            {
+                let removed = self.saved_palettes.remove(i);
+                if let Err(e) = palette::delete_from_library(&removed.name) {
+                    self.status = format!("Could not delete palette: {}", e);
+                }
+                self.palette_choice = PaletteChoice::Document;
+            }
+        });
+    }
 
-            self.last_mouse_pos = Some(current_pos);
+    fn add_to_library(&mut self, palette: Palette) {
+        if let Err(e) = palette::save_to_library(&palette) {
+            self.status = format!("Could not save palette: {}", e);
+            return;
         }
+        self.saved_palettes.retain(|p| p.name != palette.name);
+        self.saved_palettes.push(palette);
+        self.palette_choice = PaletteChoice::Saved(self.saved_palettes.len() - 1);
     }
 
     pub fn render(&mut self, window: &Window) -> Result<(), wgpu::SurfaceError> {
@@ -285,6 +1685,12 @@ impl AppState {
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
+        let view_uniform = self
+            .viewport
+            .uniform(self.canvas_size(), self.window_size());
+        self.queue
+            .write_buffer(&self.view_buffer, 0, &view_uniform.to_bytes());
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -308,44 +1714,481 @@ impl AppState {
         let raw_input = self.egui_state.take_egui_input(window);
         let ctx = self.egui_ctx.clone();
 
+        let mut layers_changed = false;
+        let mut file_dialog_confirmed = false;
+        let mut new_document_confirmed = false;
         let full_output = ctx.run(raw_input, |ctx| {
+            egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+                egui::menu::bar(ui, |ui| {
+                    let menus: [(&str, &[(&str, &str)]); 4] = [
+                        (
+                            "File",
+                            &[
+                                ("file.new", "New..."),
+                                ("file.open", "Open..."),
+                                ("file.save", "Save"),
+                                ("file.save_as", "Save As..."),
+                                ("file.export", "Export..."),
+                            ],
+                        ),
+                        ("Edit", &[("edit.undo", "Undo"), ("edit.redo", "Redo")]),
+                        (
+                            "Select",
+                            &[
+                                ("select.all", "All"),
+                                ("select.none", "Deselect"),
+                                ("select.invert", "Invert"),
+                            ],
+                        ),
+                        (
+                            "View",
+                            &[
+                                ("view.zoom_in", "Zoom In"),
+                                ("view.zoom_out", "Zoom Out"),
+                                ("view.fit", "Fit to Window"),
+                                ("view.actual_size", "Actual Size"),
+                            ],
+                        ),
+                    ];
+                    for (title, items) in menus {
+                        ui.menu_button(title, |ui| {
+                            for (id, label) in items {
+                                let button =
+                                    egui::Button::new(*label).shortcut_text(self.keymap.label(id));
+                                if ui.add(button).clicked() {
+                                    self.run_action(id);
+                                    ui.close_menu();
+                                }
+                            }
+                            if title == "Edit" {
+                                ui.separator();
+                                if ui.button("Keyboard Shortcuts...").clicked() {
+                                    self.shortcut_editor_open = true;
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    }
+                    ui.label(format!("{:.0}%", self.viewport.zoom * 100.0));
+                    ui.separator();
+                    ui.label(&self.status);
+                });
+            });
+
+            if let Some(dialog) = &mut self.file_dialog {
+                let mut open = true;
+                egui::Window::new(dialog.kind.title())
+                    .collapsible(false)
+                    .open(&mut open)
+                    .show(ctx, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Path");
+                            ui.text_edit_singleline(&mut dialog.path);
+                        });
+
+                        let extension = Path::new(dialog.path.trim())
+                            .extension()
+                            .map(|e| e.to_string_lossy().to_lowercase())
+                            .unwrap_or_default();
+                        if dialog.kind == FileDialogKind::ImportPalette {
+                            ui.label(format!(
+                                "Supported: {}",
+                                palette::SUPPORTED_EXTENSIONS.join(", ")
+                            ));
+                        } else if dialog.kind == FileDialogKind::ExportPalette {
+                            match PaletteFormat::from_path(Path::new(dialog.path.trim())) {
+                                Some(format) if format.has_alpha() => {
+                                    ui.label(format.name());
+                                }
+                                Some(format) => {
+                                    ui.label(format!(
+                                        "{} (colors are saved opaque)",
+                                        format.name()
+                                    ));
+                                }
+                                None => {
+                                    ui.label(format!(
+                                        "Supported: {}",
+                                        palette::SUPPORTED_EXTENSIONS.join(", ")
+                                    ));
+                                }
+                            }
+                        } else if dialog.kind == FileDialogKind::Open {
+                            ui.label(format!(
+                                "Supported: {}, {}",
+                                project::EXTENSION,
+                                image_io::SUPPORTED_EXTENSIONS.join(", ")
+                            ));
+                        } else if extension == project::EXTENSION {
+                            ui.label("Layered Pixle project (lossless)");
+                        } else if extension == "jpg" || extension == "jpeg" {
+                            ui.add(
+                                egui::Slider::new(&mut self.export_options.jpeg_quality, 1..=100)
+                                    .text("JPEG Quality"),
+                            );
+                        } else if extension == "png" {
+                            let compression = &mut self.export_options.png_compression;
+                            egui::ComboBox::from_label("PNG Compression")
+                                .selected_text(image_io::png_compression_name(*compression))
+                                .show_ui(ui, |ui| {
+                                    for level in [
+                                        image::codecs::png::CompressionType::Fast,
+                                        image::codecs::png::CompressionType::Default,
+                                        image::codecs::png::CompressionType::Best,
+                                    ] {
+                                        ui.selectable_value(
+                                            compression,
+                                            level,
+                                            image_io::png_compression_name(level),
+                                        );
+                                    }
+                                });
+                        }
+
+                        if let Some(error) = &dialog.error {
+                            ui.colored_label(Color32::RED, error);
+                        }
+                        if ui.button(dialog.kind.action()).clicked() {
+                            file_dialog_confirmed = true;
+                        }
+                    });
+                if !open {
+                    self.file_dialog = None;
+                }
+            }
+
+            if let Some(dialog) = &mut self.new_document_dialog {
+                let mut open = true;
+                egui::Window::new("New Document")
+                    .collapsible(false)
+                    .open(&mut open)
+                    .show(ctx, |ui| {
+                        new_document_confirmed = new_document_ui(ui, dialog);
+                    });
+                if !open {
+                    self.new_document_dialog = None;
+                }
+            }
+
             egui::Window::new("Tools").show(ctx, |ui| {
                 ui.heading("Pixle");
                 ui.label(format!("Active: {}", self.active_tool_name));
                 ui.separator();
 
                 // 1. Draw Tool Selector
-                let tool_count = self.packages.tools.len();
-                for i in 0..tool_count {
-                    let tool_name = self.packages.tools[i].name.clone();
-                    if ui.button(&tool_name).clicked() {
-                        let tool = self.packages.tools[i].clone();
-                        self.lua.load_tool(&tool);
-                        self.active_tool_name = tool_name;
-                        match self.lua.get_current_cursor() {
-                            CursorType::SystemCircle => self.active_cursor_texture = None,
-                            CursorType::CustomImage(path) => self.load_cursor_image(&path),
+                for i in 0..self.packages.tools.len() {
+                    let choice = ToolChoice::Lua(i);
+                    let name = choice.name(&self.packages);
+                    let hint = self.keymap.with_shortcut(&name, &tool_action_id(&name));
+                    if ui.button(&name).on_hover_text(hint).clicked() {
+                        // Switching away keeps whatever text was typed
+                        self.activate_tool(choice);
+                    }
+                }
+                ui.separator();
+
+                // Selection, text and the eyedropper are built in
+                ui.horizontal_wrapped(|ui| {
+                    let builtin = SelectionTool::ALL
+                        .map(ToolChoice::Selection)
+                        .into_iter()
+                        .chain([ToolChoice::Text, ToolChoice::Eyedropper]);
+                    for choice in builtin {
+                        let selected = match choice {
+                            ToolChoice::Selection(tool) => self.selection_tool == Some(tool),
+                            ToolChoice::Text => self.text_active,
+                            ToolChoice::Eyedropper => self.eyedropper_active,
+                            ToolChoice::Lua(_) => false,
+                        };
+                        let name = choice.name(&self.packages);
+                        let hint = self.keymap.with_shortcut(&name, &tool_action_id(&name));
+                        if ui
+                            .selectable_label(selected, &name)
+                            .on_hover_text(hint)
+                            .clicked()
+                        {
+                            self.activate_tool(choice);
                         }
                     }
+                });
+                if self.selection_tool.is_some() {
+                    ui.horizontal(|ui| {
+                        for mode in SelectionMode::ALL {
+                            ui.radio_value(&mut self.selection_mode, mode, mode.name());
+                        }
+                    });
+                    ui.checkbox(&mut self.selection_antialias, "Anti-alias");
+                    if self.selection_tool == Some(SelectionTool::MagicWand) {
+                        let wand = &mut self.wand_options;
+                        ui.add(egui::Slider::new(&mut wand.tolerance, 0..=255).text("Tolerance"));
+                        ui.checkbox(&mut wand.contiguous, "Contiguous");
+                        ui.checkbox(&mut wand.sample_merged, "Sample All Layers");
+                    }
+                    ui.label("Shift: add, Alt: subtract, Shift+Alt: intersect");
+                }
+                if self.text_active {
+                    self.text_tool_ui(ui);
                 }
+                if self.eyedropper_active {
+                    let options = &mut self.eyedropper;
+                    egui::ComboBox::from_label("Sample Size")
+                        .selected_text(options.size.name())
+                        .show_ui(ui, |ui| {
+                            for size in SampleSize::ALL {
+                                ui.selectable_value(&mut options.size, size, size.name());
+                            }
+                        });
+                    ui.checkbox(&mut options.sample_merged, "Sample All Layers");
+                    ui.horizontal(|ui| {
+                        ui.label("Set");
+                        for target in ColorTarget::ALL {
+                            ui.radio_value(&mut options.target, target, target.name());
+                        }
+                    });
+                    ui.label("Alt+click picks a color with any tool");
+                }
+                ui.separator();
+
+                // 2. Global Colors (Managed by Rust, but could be Lua)
+                ui.horizontal(|ui| {
+                    ui.label("Primary");
+                    ui.color_edit_button_rgba_unmultiplied(&mut self.colors.primary);
+                    ui.label("Secondary");
+                    ui.color_edit_button_rgba_unmultiplied(&mut self.colors.secondary);
+                    let swap_hint = self.keymap.with_shortcut("Swap", "colors.swap");
+                    if ui.small_button("⇄").on_hover_text(swap_hint).clicked() {
+                        self.colors.swap();
+                    }
+                    let reset_hint = self.keymap.with_shortcut("Black and white", "colors.reset");
+                    if ui.small_button("Reset").on_hover_text(reset_hint).clicked() {
+                        self.colors.reset();
+                    }
+                });
+                ui.separator();
+
+                ui.collapsing("Pen Pressure", |ui| self.pressure_ui(ui));
                 ui.separator();
 
-                // 2. Global Color Picker (Managed by Rust, but could be Lua)
-                ui.label("Global Color");
-                ui.color_edit_button_rgb(&mut self.brush_color);
+                ui.collapsing("Stroke Smoothing", |ui| {
+                    let settings = &mut self.stroke.stabilizer.settings;
+                    ui.add(egui::Slider::new(&mut settings.string, 0.0..=1.0).text("String"))
+                        .on_hover_text("The brush trails the cursor on a string");
+                    ui.add(egui::Slider::new(&mut settings.average, 0.0..=1.0).text("Average"))
+                        .on_hover_text("Averages the last few samples");
+                    ui.add(egui::Slider::new(&mut settings.spline, 0.0..=1.0).text("Spline"))
+                        .on_hover_text("Curves between samples instead of straight lines");
+                    if ui.button("Off").clicked() {
+                        *settings = StabilizerSettings::default();
+                    }
+                });
+                ui.separator();
+
+                // 3. History
+                ui.horizontal(|ui| {
+                    let undo_hint = self
+                        .document
+                        .history
+                        .undo_label()
+                        .unwrap_or("Nothing to undo");
+                    if ui
+                        .add_enabled(self.document.history.can_undo(), egui::Button::new("Undo"))
+                        .on_hover_text(self.keymap.with_shortcut(undo_hint, "edit.undo"))
+                        .clicked()
+                    {
+                        self.undo();
+                    }
+                    let redo_hint = self
+                        .document
+                        .history
+                        .redo_label()
+                        .unwrap_or("Nothing to redo");
+                    if ui
+                        .add_enabled(self.document.history.can_redo(), egui::Button::new("Redo"))
+                        .on_hover_text(self.keymap.with_shortcut(redo_hint, "edit.redo"))
+                        .clicked()
+                    {
+                        self.redo();
+                    }
+                });
+                let mut budget_mb = self.document.history.memory_budget() / (1024 * 1024);
+                ui.horizontal(|ui| {
+                    ui.label("History Budget (MB)");
+                    if ui
+                        .add(egui::DragValue::new(&mut budget_mb).clamp_range(16..=8192))
+                        .changed()
+                    {
+                        self.document
+                            .history
+                            .set_memory_budget(budget_mb * 1024 * 1024);
+                    }
+                });
+                ui.label(format!(
+                    "History: {:.1} MB used",
+                    self.document.history.memory_used() as f64 / (1024.0 * 1024.0)
+                ));
                 ui.separator();
 
-                // 3. Lua Defined UI
+                // 4. Lua Defined UI
                 // This replaces the hardcoded sliders
                 self.lua.draw_ui(ui);
             });
 
-            if !ctx.is_pointer_over_area() {
+            egui::Window::new("Palette").show(ctx, |ui| self.palette_ui(ui));
+
+            if self.shortcut_editor_open {
+                let mut open = true;
+                egui::Window::new("Keyboard Shortcuts")
+                    .open(&mut open)
+                    .show(ctx, |ui| self.shortcut_editor_ui(ui));
+                if !open {
+                    self.shortcut_editor_open = false;
+                    self.capturing_shortcut = None;
+                }
+            }
+
+            egui::Window::new("Layers").show(ctx, |ui| {
+                // Top of the stack is listed first
+                for index in (0..self.document.layers.len()).rev() {
+                    ui.horizontal(|ui| {
+                        let is_active = self.document.active_layer == index;
+                        let layer = &self.document.layers[index];
+                        let (mut visible, mut locked) = (layer.visible, layer.locked);
+                        if ui
+                            .checkbox(&mut visible, "👁")
+                            .on_hover_text("Visible")
+                            .changed()
+                        {
+                            let label = if visible { "Show Layer" } else { "Hide Layer" };
+                            self.document
+                                .edit_layer(label, index, |l| l.visible = visible);
+                            self.document.finish_layer_edit();
+                            layers_changed = true;
+                        }
+                        if ui
+                            .checkbox(&mut locked, "🔒")
+                            .on_hover_text("Locked")
+                            .changed()
+                        {
+                            let label = if locked { "Lock Layer" } else { "Unlock Layer" };
+                            self.document
+                                .edit_layer(label, index, |l| l.locked = locked);
+                            self.document.finish_layer_edit();
+                        }
+                        let name = self.document.layers[index].name.clone();
+                        if ui.selectable_label(is_active, name).clicked() {
+                            self.document.set_active_layer(index);
+                        }
+                    });
+                }
+                ui.separator();
+
+                let active = self.document.active_layer;
+                let layer_count = self.document.layers.len();
+                ui.horizontal_wrapped(|ui| {
+                    if ui.button("Add").clicked() {
+                        self.document.add_layer();
+                        layers_changed = true;
+                    }
+                    if ui
+                        .add_enabled(layer_count > 1, egui::Button::new("Delete"))
+                        .clicked()
+                    {
+                        self.document.delete_layer(active);
+                        layers_changed = true;
+                    }
+                    if ui.button("Duplicate").clicked() {
+                        self.document.duplicate_layer(active);
+                        layers_changed = true;
+                    }
+                    if ui
+                        .add_enabled(active + 1 < layer_count, egui::Button::new("Up"))
+                        .clicked()
+                    {
+                        self.document.move_layer(active, active + 1);
+                        layers_changed = true;
+                    }
+                    if ui
+                        .add_enabled(active > 0, egui::Button::new("Down"))
+                        .clicked()
+                    {
+                        self.document.move_layer(active, active - 1);
+                        layers_changed = true;
+                    }
+                    if ui
+                        .add_enabled(active > 0, egui::Button::new("Merge Down"))
+                        .clicked()
+                    {
+                        self.document.merge_down(active);
+                        layers_changed = true;
+                    }
+                });
+                ui.separator();
+
+                // Active layer properties. Typing a name or dragging the opacity
+                // becomes one undo step once the field loses focus / the drag ends.
+                let active = self.document.active_layer;
+                let layer = &self.document.layers[active];
+                let (mut name, mut opacity, mut blend_mode) =
+                    (layer.name.clone(), layer.opacity, layer.blend_mode);
+                let response = ui.text_edit_singleline(&mut name);
+                if response.changed() {
+                    self.document
+                        .edit_layer("Rename Layer", active, |l| l.name = name);
+                }
+                if response.lost_focus() {
+                    self.document.finish_layer_edit();
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Opacity");
+                    let response = ui.add(egui::Slider::new(&mut opacity, 0.0..=1.0));
+                    if response.changed() {
+                        self.document
+                            .edit_layer("Layer Opacity", active, |l| l.opacity = opacity);
+                        layers_changed = true;
+                    }
+                    if response.drag_released() || (response.changed() && !response.dragged()) {
+                        self.document.finish_layer_edit();
+                    }
+                });
+                egui::ComboBox::from_label("Blend Mode")
+                    .selected_text(blend_mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in BlendMode::ALL {
+                            if ui
+                                .selectable_value(&mut blend_mode, mode, mode.name())
+                                .changed()
+                            {
+                                self.document
+                                    .edit_layer("Blend Mode", active, |l| l.blend_mode = mode);
+                                self.document.finish_layer_edit();
+                                layers_changed = true;
+                            }
+                        }
+                    });
+            });
+
+            self.draw_selection(ctx);
+
+            if !ctx.is_pointer_over_area()
+                && (self.selection_tool.is_some() || self.text_active || self.eyedropper_active)
+            {
+                // Selection tools (and text) get a plain crosshair
+                let painter =
+                    ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("cursor_overlay")));
+                let (x, y) = (self.mouse_pos.0 as f32, self.mouse_pos.1 as f32);
+                for stroke in [
+                    Stroke::new(3.0, Color32::WHITE),
+                    Stroke::new(1.0, Color32::BLACK),
+                ] {
+                    painter.line_segment([egui::pos2(x - 8.0, y), egui::pos2(x + 8.0, y)], stroke);
+                    painter.line_segment([egui::pos2(x, y - 8.0), egui::pos2(x, y + 8.0)], stroke);
+                }
+            } else if !ctx.is_pointer_over_area() {
                 let painter =
                     ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("cursor_overlay")));
                 let mouse_pos = egui::Pos2 {
-                    x: self.mouse_pos.0,
-                    y: self.mouse_pos.1,
+                    x: self.mouse_pos.0 as f32,
+                    y: self.mouse_pos.1 as f32,
                 };
 
                 if let Some(texture) = &self.active_cursor_texture {
@@ -360,8 +2203,7 @@ impl AppState {
                 } else {
                     // Update: Ask Lua for the size
                     let lua_size = self.lua.get_tool_size();
-                    let zoom_ratio = self.size.width as f32 / self.canvas.width as f32;
-                    let visual_radius = (lua_size / 2.0) * zoom_ratio;
+                    let visual_radius = (lua_size / 2.0) * self.viewport.zoom as f32;
                     painter.circle_stroke(
                         mouse_pos,
                         visual_radius,
@@ -376,6 +2218,16 @@ impl AppState {
             }
         });
 
+        if file_dialog_confirmed {
+            self.confirm_file_dialog(window);
+        }
+        if new_document_confirmed {
+            self.create_new_document(window);
+        }
+        if layers_changed {
+            self.canvas.update_texture(&self.queue, &self.document);
+        }
+
         self.egui_state
             .handle_platform_output(window, full_output.platform_output);
         let clipped_primitives = self
@@ -422,3 +2274,127 @@ impl AppState {
         Ok(())
     }
 }
+
+/// Contents of the File > New window; returns true when Create is clicked
+fn new_document_ui(ui: &mut egui::Ui, dialog: &mut NewDocumentDialog) -> bool {
+    let settings = &mut dialog.settings;
+
+    let mut chosen: Option<Preset> = None;
+    egui::ComboBox::from_label("Preset")
+        .selected_text("Choose...")
+        .show_ui(ui, |ui| {
+            for preset in dialog.presets.iter().chain(&dialog.user_presets) {
+                if ui.selectable_label(false, &preset.name).clicked() {
+                    chosen = Some(preset.clone());
+                }
+            }
+        });
+    if let Some(preset) = chosen {
+        settings.apply_preset(&preset);
+        dialog.preset_name = preset.name;
+    }
+
+    let max = new_document::MAX_DOCUMENT_SIZE;
+    ui.horizontal(|ui| {
+        ui.label("Width");
+        ui.add(egui::DragValue::new(&mut settings.width).clamp_range(1..=max));
+        ui.label("Height");
+        ui.add(egui::DragValue::new(&mut settings.height).clamp_range(1..=max));
+        ui.label("px");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Resolution");
+        ui.add(
+            egui::DragValue::new(&mut settings.dpi)
+                .clamp_range(1.0..=2400.0)
+                .speed(1.0),
+        );
+        ui.label("DPI");
+    });
+    ui.label(format!(
+        "Print size: {:.2} x {:.2} in",
+        settings.width as f64 / settings.dpi,
+        settings.height as f64 / settings.dpi
+    ));
+
+    ui.horizontal(|ui| {
+        ui.label("Background");
+        for background in Background::ALL {
+            ui.radio_value(&mut settings.background, background, background.name());
+        }
+    });
+    if settings.background == Background::Custom {
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            ui.color_edit_button_srgba_unmultiplied(&mut settings.custom_color);
+        });
+    }
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label("Preset name");
+        ui.text_edit_singleline(&mut dialog.preset_name);
+    });
+    ui.horizontal(|ui| {
+        let name = dialog.preset_name.trim().to_string();
+        if ui
+            .add_enabled(!name.is_empty(), egui::Button::new("Save Preset"))
+            .clicked()
+        {
+            // Saving under an existing name replaces it
+            dialog.user_presets.retain(|p| p.name != name);
+            dialog.user_presets.push(Preset {
+                name: name.clone(),
+                width: settings.width,
+                height: settings.height,
+                dpi: settings.dpi,
+            });
+            dialog.error = new_document::save_user_presets(&dialog.user_presets).err();
+        }
+        let is_user_preset = dialog.user_presets.iter().any(|p| p.name == name);
+        if ui
+            .add_enabled(is_user_preset, egui::Button::new("Delete Preset"))
+            .clicked()
+        {
+            dialog.user_presets.retain(|p| p.name != name);
+            dialog.error = new_document::save_user_presets(&dialog.user_presets).err();
+        }
+    });
+
+    if let Some(error) = &dialog.error {
+        ui.colored_label(Color32::RED, error);
+    }
+    ui.separator();
+    ui.button("Create").clicked()
+}
+
+/// A clickable color square (checkered behind translucent colors)
+fn swatch_button(ui: &mut egui::Ui, color: [u8; 4], name: &str) -> egui::Response {
+    let (rect, response) =
+        ui.allocate_exact_size(egui::vec2(18.0, 18.0), egui::Sense::click_and_drag());
+    let painter = ui.painter();
+    if color[3] < 255 {
+        painter.rect_filled(rect, 0.0, Color32::WHITE);
+        let half = rect.width() / 2.0;
+        for (dx, dy) in [(0.0, 0.0), (half, half)] {
+            let cell =
+                egui::Rect::from_min_size(rect.min + egui::vec2(dx, dy), egui::vec2(half, half));
+            painter.rect_filled(cell, 0.0, Color32::LIGHT_GRAY);
+        }
+    }
+    let [r, g, b, a] = color;
+    painter.rect_filled(rect, 0.0, Color32::from_rgba_unmultiplied(r, g, b, a));
+    let outline = if response.hovered() {
+        Color32::WHITE
+    } else {
+        Color32::DARK_GRAY
+    };
+    painter.rect_stroke(rect, 0.0, Stroke::new(1.0, outline));
+
+    let hex = format!("#{:02X}{:02X}{:02X}", r, g, b);
+    if name.is_empty() {
+        response.on_hover_text(hex)
+    } else {
+        response.on_hover_text(format!("{} ({})", name, hex))
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::commands::PaintCommand;
+
+/// Brush parameters. Lua tools describe these declaratively in `Tool.brush`.
+#[derive(Clone, Debug)]
+pub struct BrushSettings {
+    // Diameter in canvas pixels
+    pub size: f64,
+    // 1.0 = crisp edge, 0.0 = soft all the way from the center
+    pub hardness: f64,
+    // Alpha of each dab
+    pub flow: f64,
+    // Ceiling for the whole stroke
+    pub opacity: f64,
+    // Distance between dabs, as a fraction of the size
+    pub spacing: f64,
+    // Random offset of each dab, as a fraction of the size
+    pub jitter: f64,
+    // Rotation of the tip in degrees
+    pub angle: f64,
+    // Height / width of the tip (1.0 = circle)
+    pub roundness: f64,
+    pub tip: Option<Arc<BrushTip>>,
+    // How much pen pressure scales size, opacity and flow:
+    // 0 = not at all, 1 = from nothing at no pressure up to the full value
+    pub pressure_size: f64,
+    pub pressure_opacity: f64,
+    pub pressure_flow: f64,
+}
+
+impl Default for BrushSettings {
+    fn default() -> Self {
+        Self {
+            size: 10.0,
+            hardness: 0.8,
+            flow: 1.0,
+            opacity: 1.0,
+            spacing: 0.1,
+            jitter: 0.0,
+            angle: 0.0,
+            roundness: 1.0,
+            tip: None,
+            pressure_size: 0.0,
+            pressure_opacity: 0.0,
+            pressure_flow: 0.0,
+        }
+    }
+}
+
+/// A grayscale stamp shape loaded from an image (255 = fully painted)
+#[derive(Debug)]
+pub struct BrushTip {
+    pub width: u32,
+    pub height: u32,
+    pub mask: Vec<u8>,
+}
+
+impl BrushTip {
+    /// Uses the alpha channel if the image has transparency, otherwise dark = paint
+    pub fn load(path: &Path) -> Option<Self> {
+        let img = image::open(path).ok()?.to_rgba8();
+        let has_alpha = img.pixels().any(|p| p[3] < 255);
+        let mask = img
+            .pixels()
+            .map(|p| {
+                if has_alpha {
+                    p[3]
+                } else {
+                    let luma = (p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114) / 1000;
+                    255 - luma as u8
+                }
+            })
+            .collect();
+        Some(Self {
+            width: img.width(),
+            height: img.height(),
+            mask,
+        })
+    }
+
+    /// Bilinear lookup with u, v in 0..1; outside the tip is empty
+    pub fn sample(&self, u: f64, v: f64) -> f64 {
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return 0.0;
+        }
+        let fx = (u * self.width as f64 - 0.5).max(0.0);
+        let fy = (v * self.height as f64 - 0.5).max(0.0);
+        let (x0, y0) = (fx as u32, fy as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (fx - x0 as f64, fy - y0 as f64);
+        let at = |x: u32, y: u32| self.mask[(y * self.width + x) as usize] as f64 / 255.0;
+        let top = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+        let bottom = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+/// Places dabs along the stroke path at a fixed spacing
+pub struct BrushEngine {
+    // Distance left to travel before the next dab
+    carry: f64,
+    rng: u64,
+    tips: HashMap<PathBuf, Option<Arc<BrushTip>>>,
+}
+
+impl BrushEngine {
+    pub fn new() -> Self {
+        Self {
+            carry: 0.0,
+            rng: 0x2545_f491_4f6c_dd1d,
+            tips: HashMap::new(),
+        }
+    }
+
+    /// Call when the mouse goes down so the first dab lands on the press point
+    pub fn begin_stroke(&mut self) {
+        self.carry = 0.0;
+    }
+
+    /// Loads (and caches) a tip image; missing or broken files fall back to the round tip
+    pub fn load_tip(&mut self, path: &Path) -> Option<Arc<BrushTip>> {
+        self.tips
+            .entry(path.to_path_buf())
+            .or_insert_with(|| {
+                let tip = BrushTip::load(path);
+                if tip.is_none() {
+                    println!("Could not load brush tip: {}", path.display());
+                }
+                tip.map(Arc::new)
+            })
+            .clone()
+    }
+
+    /// Dabs from `from` to `to`; `pressure` is the pen pressure at either end
+    pub fn stroke(
+        &mut self,
+        settings: &BrushSettings,
+        from: (f64, f64),
+        to: (f64, f64),
+        pressure: (f32, f32),
+        color: [u8; 3],
+    ) -> Vec<PaintCommand> {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = (dx * dx + dy * dy).sqrt();
+
+        let mut dabs = Vec::new();
+        let mut travelled = self.carry;
+        while travelled <= length {
+            let t = if length > 0.0 {
+                travelled / length
+            } else {
+                0.0
+            };
+            let p = pressure.0 as f64 + (pressure.1 - pressure.0) as f64 * t;
+            let size = settings.size * pressure_scale(settings.pressure_size, p);
+            let flow = settings.flow * pressure_scale(settings.pressure_flow, p);
+            let opacity = settings.opacity * pressure_scale(settings.pressure_opacity, p);
+            let scatter = settings.jitter * size;
+            let x = from.0 + dx * t + self.next_signed() * scatter;
+            let y = from.1 + dy * t + self.next_signed() * scatter;
+            dabs.push(PaintCommand::Dab {
+                x,
+                y,
+                size,
+                hardness: settings.hardness,
+                angle: settings.angle,
+                roundness: settings.roundness,
+                color,
+                alpha: (flow * opacity).clamp(0.0, 1.0),
+                tip: settings.tip.clone(),
+            });
+            // Light pressure makes smaller dabs, which need to sit closer together
+            travelled += (settings.spacing * size).max(0.5);
+        }
+        self.carry = travelled - length;
+        dabs
+    }
+
+    /// Cheap xorshift; returns -1..1
+    fn next_signed(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+    }
+}
+
+/// 1 at full pressure; `amount` of the way down to 0 at no pressure
+fn pressure_scale(amount: f64, pressure: f64) -> f64 {
+    1.0 - amount.clamp(0.0, 1.0) * (1.0 - pressure.clamp(0.0, 1.0))
+}
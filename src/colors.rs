@@ -0,0 +1,69 @@
+/// Which of the two colors something reads or sets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorTarget {
+    Primary,
+    Secondary,
+}
+
+impl ColorTarget {
+    pub const ALL: [ColorTarget; 2] = [ColorTarget::Primary, ColorTarget::Secondary];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorTarget::Primary => "Primary",
+            ColorTarget::Secondary => "Secondary",
+        }
+    }
+
+    pub fn other(&self) -> Self {
+        match self {
+            ColorTarget::Primary => ColorTarget::Secondary,
+            ColorTarget::Secondary => ColorTarget::Primary,
+        }
+    }
+}
+
+/// The primary (foreground) and secondary (background) colors, straight RGBA in 0..1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorPair {
+    pub primary: [f32; 4],
+    pub secondary: [f32; 4],
+}
+
+impl Default for ColorPair {
+    fn default() -> Self {
+        Self {
+            primary: [0.0, 0.0, 0.0, 1.0],
+            secondary: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl ColorPair {
+    pub fn get(&self, target: ColorTarget) -> [f32; 4] {
+        match target {
+            ColorTarget::Primary => self.primary,
+            ColorTarget::Secondary => self.secondary,
+        }
+    }
+
+    pub fn set(&mut self, target: ColorTarget, color: [f32; 4]) {
+        match target {
+            ColorTarget::Primary => self.primary = color,
+            ColorTarget::Secondary => self.secondary = color,
+        }
+    }
+
+    pub fn swap(&mut self) {
+        std::mem::swap(&mut self.primary, &mut self.secondary);
+    }
+
+    /// Back to black on white
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+pub fn to_rgba8(color: [f32; 4]) -> [u8; 4] {
+    color.map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8)
+}
@@ -1,4 +1,20 @@
-#[derive(Clone, Copy, Debug)]
+use std::sync::Arc;
+
+use crate::brush::BrushTip;
+use crate::gradient::Gradient;
+use crate::layers::CompositeOp;
+use crate::text::TextStyle;
+use ab_glyph::FontArc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShapeKind {
+    Line,
+    Rect,
+    Ellipse,
+    RoundedRect { radius: f64 },
+}
+
+#[derive(Clone, Debug)]
 pub enum PaintCommand {
     // Added 'a' (alpha)
     DrawPixel {
@@ -9,4 +25,107 @@ pub enum PaintCommand {
         b: u8,
         a: u8,
     },
+    // Shapes use fractional canvas coordinates (pixel i spans i..i+1)
+    FillRect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        color: [u8; 4],
+        antialias: bool,
+    },
+    FillCircle {
+        cx: f64,
+        cy: f64,
+        radius: f64,
+        color: [u8; 4],
+        antialias: bool,
+    },
+    // A round-capped line `width` pixels thick
+    DrawLine {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        width: f64,
+        color: [u8; 4],
+        antialias: bool,
+    },
+    // Even-odd fill of a closed polygon
+    FillPolygon {
+        points: Vec<(f64, f64)>,
+        color: [u8; 4],
+        antialias: bool,
+    },
+    // A horizontal run of `len` pixels starting at (x, y)
+    DrawSpan {
+        x: i32,
+        y: i32,
+        len: u32,
+        color: [u8; 4],
+    },
+    // Blits an RGBA image with its top-left corner at (x, y)
+    Stamp {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+        opacity: f32,
+    },
+    // One brush engine dab centered on (x, y)
+    Dab {
+        x: f64,
+        y: f64,
+        size: f64,
+        hardness: f64,
+        angle: f64,
+        roundness: f64,
+        color: [u8; 3],
+        alpha: f64,
+        tip: Option<Arc<BrushTip>>,
+    },
+    // Fills the area around (x, y) whose color is within `tolerance` of that pixel
+    // on the active layer (resolved by Document, since it needs to see the layer)
+    FloodFill {
+        x: u32,
+        y: u32,
+        color: [u8; 4],
+        tolerance: u8,
+        contiguous: bool,
+        antialias: bool,
+    },
+    // A shape spanned by two points, filled and/or outlined `width` pixels wide
+    // (centered on the edge). Lines only use the outline color.
+    Shape {
+        kind: ShapeKind,
+        start: (f64, f64),
+        end: (f64, f64),
+        width: f64,
+        fill: Option<[u8; 4]>,
+        outline: Option<[u8; 4]>,
+        antialias: bool,
+    },
+    // Text with its first line's top at y; x is the left edge, center or right edge
+    // depending on the alignment
+    Text {
+        x: f64,
+        y: f64,
+        text: String,
+        font: FontArc,
+        style: TextStyle,
+        color: [u8; 4],
+    },
+    // Fills the whole canvas (within the selection) with a gradient
+    Gradient(Gradient),
+    // Throws away what the stroke has painted so far, for tools that redraw a live preview
+    ClearStroke,
+    // Commits what the stroke has painted so far as its own history step
+    CommitStroke {
+        label: String,
+    },
+    // Draws nothing; changes how the current stroke is committed (e.g. erase)
+    SetComposite {
+        op: CompositeOp,
+    },
 }
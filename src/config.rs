@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+
+/// Where per-user files (presets, palettes, key bindings) live.
+/// Falls back to the working directory when no home folder can be found.
+pub fn config_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return PathBuf::from(dir).join("pixle");
+    }
+    if let Some(dir) = std::env::var_os("APPDATA") {
+        return PathBuf::from(dir).join("Pixle");
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        return PathBuf::from(home).join(".config").join("pixle");
+    }
+    PathBuf::from(".")
+}
+
+/// A file inside the config folder
+pub fn config_file(name: &str) -> PathBuf {
+    config_dir().join(name)
+}
@@ -0,0 +1,834 @@
+use crate::commands::PaintCommand;
+use crate::fill;
+use crate::history::{
+    Change, DEFAULT_HISTORY_BUDGET, History, HistoryEntry, LayerProps, LayerStack, Region,
+};
+use crate::layers::{self, CompositeOp, Layer, StrokePreview};
+use crate::palette::Palette;
+use crate::raster;
+use crate::selection::{Selection, SelectionMode, SelectionShape, WandOptions};
+
+pub const DEFAULT_DPI: f64 = 72.0;
+
+/// The image being edited, with everything that happens to it on the CPU:
+/// layers, the stroke in progress, selection and history. The GPU side only
+/// ever sees `composite()` (see `Canvas`).
+pub struct Document {
+    // The permanent image, bottom layer first
+    pub layers: Vec<Layer>,
+    pub active_layer: usize,
+    // The temporary layer for the current stroke (painted onto the active layer)
+    pub stroke_buffer: Vec<u8>,
+    // Area touched by the current stroke (so commits only snapshot what changed)
+    stroke_bounds: Option<Region>,
+    // How the current stroke lands on the layer (tools switch this for erasers etc.)
+    pub stroke_op: CompositeOp,
+
+    pub history: History,
+    // A property edit still in progress (typing a name, dragging opacity): label, layer, before
+    layer_edit: Option<(String, usize, LayerProps)>,
+    // Painting only lands inside this (when something is selected)
+    pub selection: Selection,
+
+    pub width: u32,
+    pub height: u32,
+    // Print resolution; only metadata, pixels are never resampled for it
+    pub dpi: f64,
+    // Colors saved with this document
+    pub palette: Palette,
+}
+
+impl Document {
+    /// A single-layer document filled with `background` (use alpha 0 for a transparent one)
+    pub fn new(width: u32, height: u32, background: [u8; 4]) -> Self {
+        let background = Layer::new("Background", width, height, background);
+        Self::from_layers(width, height, vec![background])
+    }
+
+    /// Builds a document around existing layers (e.g. an opened file).
+    /// Every layer must hold width * height RGBA pixels.
+    pub fn from_layers(width: u32, height: u32, layers: Vec<Layer>) -> Self {
+        let pixel_count = (width * height) as usize;
+        Self {
+            active_layer: layers.len() - 1,
+            layers,
+            // Empty Stroke Buffer (Transparent)
+            stroke_buffer: vec![0; pixel_count * 4],
+            stroke_bounds: None,
+            stroke_op: CompositeOp::Over,
+            history: History::new(DEFAULT_HISTORY_BUDGET),
+            layer_edit: None,
+            selection: Selection::new(width, height),
+            width,
+            height,
+            dpi: DEFAULT_DPI,
+            palette: Palette::new("Document"),
+        }
+    }
+
+    /// All visible layers merged into one RGBA image (what gets exported)
+    pub fn flatten(&self) -> Vec<u8> {
+        let mut flat = vec![0u8; self.stroke_buffer.len()];
+        layers::composite(&self.layers, None, &mut flat);
+        flat
+    }
+
+    /// What's on screen: every visible layer plus the stroke in progress
+    pub fn composite(&self) -> Vec<u8> {
+        let mut composited = vec![0u8; self.stroke_buffer.len()];
+        layers::composite(
+            &self.layers,
+            Some(StrokePreview {
+                layer: self.active_layer,
+                pixels: &self.stroke_buffer,
+                op: self.stroke_op,
+            }),
+            &mut composited,
+        );
+        composited
+    }
+
+    /// Draws to the temporary Stroke Buffer
+    /// Logic: MAX ALPHA (Prevents dots from getting darker)
+    pub fn draw_to_stroke(&mut self, x: u32, y: u32, r: u8, g: u8, b: u8, a: u8) {
+        if x >= self.width || y >= self.height || self.layers[self.active_layer].locked {
+            return;
+        }
+        let i = ((y * self.width + x) * 4) as usize;
+
+        // Partially selected pixels get a proportionally weaker stroke
+        let a = (a as u32 * self.selection.coverage(x, y) as u32 / 255) as u8;
+        let current_a = self.stroke_buffer[i + 3];
+
+        // MAGIC TRICK: Only update if the new alpha is higher than what's there.
+        // This ensures overlapping segments don't add up, they just stay at the max opacity.
+        if a > current_a {
+            self.stroke_buffer[i] = r;
+            self.stroke_buffer[i + 1] = g;
+            self.stroke_buffer[i + 2] = b;
+            self.stroke_buffer[i + 3] = a;
+
+            self.stroke_bounds = Some(match self.stroke_bounds {
+                Some(bounds) => bounds.include(x, y),
+                None => Region::new(x, y, 1, 1),
+            });
+        }
+    }
+
+    /// Rasterizes a tool command into the Stroke Buffer
+    pub fn apply_command(&mut self, cmd: &PaintCommand) {
+        match cmd {
+            PaintCommand::SetComposite { op } => {
+                self.stroke_op = *op;
+                return;
+            }
+            PaintCommand::ClearStroke => {
+                self.clear_stroke();
+                return;
+            }
+            PaintCommand::CommitStroke { label } => {
+                self.commit_stroke_as(label);
+                return;
+            }
+            PaintCommand::FloodFill {
+                x,
+                y,
+                color,
+                tolerance,
+                contiguous,
+                antialias,
+            } => {
+                self.flood_fill((*x, *y), *color, *tolerance, *contiguous, *antialias);
+                return;
+            }
+            _ => {}
+        }
+        let (width, height) = (self.width, self.height);
+        raster::rasterize(cmd, width, height, |x, y, [r, g, b, a]| {
+            self.draw_to_stroke(x, y, r, g, b, a)
+        });
+    }
+
+    /// Empties the Stroke Buffer without committing anything
+    pub fn clear_stroke(&mut self) {
+        let Some(bounds) = self.stroke_bounds.take() else {
+            return;
+        };
+        let row_len = (bounds.width * 4) as usize;
+        for y in bounds.y..bounds.y + bounds.height {
+            let start = ((y * self.width + bounds.x) * 4) as usize;
+            self.stroke_buffer[start..start + row_len].fill(0);
+        }
+    }
+
+    /// Paints the region similar to the seed pixel of the active layer into the Stroke Buffer
+    fn flood_fill(
+        &mut self,
+        seed: (u32, u32),
+        color: [u8; 4],
+        tolerance: u8,
+        contiguous: bool,
+        antialias: bool,
+    ) {
+        let matched = fill::similar_pixels(
+            &self.layers[self.active_layer].pixel_buffer,
+            self.width,
+            self.height,
+            seed,
+            tolerance,
+            contiguous,
+        );
+        let coverage = fill::coverage_mask(&matched, self.width, self.height, antialias);
+        for (i, c) in coverage.iter().enumerate() {
+            if *c > 0 {
+                let (x, y) = (i as u32 % self.width, i as u32 / self.width);
+                let a = (color[3] as u32 * *c as u32 / 255) as u8;
+                self.draw_to_stroke(x, y, color[0], color[1], color[2], a);
+            }
+        }
+    }
+
+    /// Permanently bakes the stroke onto the active layer and records it in the history
+    pub fn commit_stroke(&mut self) {
+        self.commit_stroke_as("Brush Stroke");
+    }
+
+    /// `commit_stroke` with a different name in the history
+    pub fn commit_stroke_as(&mut self, label: &str) {
+        self.finish_layer_edit();
+        // Every stroke starts out painting "over" again
+        let op = std::mem::replace(&mut self.stroke_op, CompositeOp::Over);
+        let Some(bounds) = self.stroke_bounds.take() else {
+            return;
+        };
+        let layer_index = self.active_layer;
+        let before = self.read_region(layer_index, bounds);
+
+        let pixel_buffer = &mut self.layers[layer_index].pixel_buffer;
+        for i in (0..self.stroke_buffer.len()).step_by(4) {
+            // The stroke buffer is already clipped, but never touch pixels outside the selection
+            let (x, y) = ((i / 4) as u32 % self.width, (i / 4) as u32 / self.width);
+            if self.selection.coverage(x, y) == 0 {
+                self.stroke_buffer[i..i + 4].fill(0);
+                continue;
+            }
+            if self.stroke_buffer[i + 3] > 0 {
+                let src = layers::pixel_at(&self.stroke_buffer, i);
+                let dst = layers::pixel_at(pixel_buffer, i);
+                pixel_buffer[i..i + 4].copy_from_slice(&op.apply(dst, src));
+
+                // Clear Stroke Buffer as we go
+                self.stroke_buffer[i..i + 4].fill(0);
+            }
+        }
+
+        self.record_edit(label, layer_index, bounds, before);
+    }
+
+    /// Copies the RGBA pixels of a region out of a layer
+    pub fn read_region(&self, layer: usize, region: Region) -> Vec<u8> {
+        let pixel_buffer = &self.layers[layer].pixel_buffer;
+        let mut out = Vec::with_capacity(region.byte_len());
+        let row_len = (region.width * 4) as usize;
+        for y in region.y..region.y + region.height {
+            let start = ((y * self.width + region.x) * 4) as usize;
+            out.extend_from_slice(&pixel_buffer[start..start + row_len]);
+        }
+        out
+    }
+
+    /// Pushes an undo step for a pixel edit that has already been applied.
+    /// `before` is the region's content from before the edit (see `read_region`).
+    pub fn record_edit(&mut self, label: &str, layer: usize, region: Region, before: Vec<u8>) {
+        let after = self.read_region(layer, region);
+        self.push_history(HistoryEntry {
+            label: label.to_string(),
+            change: Change::Pixels {
+                layer,
+                region,
+                before,
+                after,
+            },
+        });
+    }
+
+    /// A copy of the layers, for `record_layers`
+    fn layer_stack(&self) -> LayerStack {
+        LayerStack {
+            layers: self.layers.clone(),
+            active_layer: self.active_layer,
+        }
+    }
+
+    /// Pushes an undo step for a change to the layer stack that has already been applied
+    fn record_layers(&mut self, label: &str, before: LayerStack) {
+        let after = self.layer_stack();
+        self.push_history(HistoryEntry {
+            label: label.to_string(),
+            change: Change::Layers { before, after },
+        });
+    }
+
+    fn record_selection(&mut self, label: &str, before: Option<Vec<u8>>) {
+        let after = self.selection.mask().map(|m| m.to_vec());
+        self.push_history(HistoryEntry {
+            label: label.to_string(),
+            change: Change::Selection { before, after },
+        });
+    }
+
+    /// Any property edit still in progress lands first so the steps stay in order
+    fn push_history(&mut self, entry: HistoryEntry) {
+        self.finish_layer_edit();
+        self.history.push(entry);
+    }
+
+    /// Changes a layer's properties (name, visibility, opacity, ...). Repeated edits with
+    /// the same label and layer (a drag, typing) become one undo step in `finish_layer_edit`.
+    pub fn edit_layer(&mut self, label: &str, index: usize, edit: impl FnOnce(&mut Layer)) {
+        if self
+            .layer_edit
+            .as_ref()
+            .is_some_and(|(l, i, _)| l != label || *i != index)
+        {
+            self.finish_layer_edit();
+        }
+        if self.layer_edit.is_none() {
+            let before = LayerProps::of(&self.layers[index]);
+            self.layer_edit = Some((label.to_string(), index, before));
+        }
+        edit(&mut self.layers[index]);
+    }
+
+    /// Records the property edit in progress, if any, as one undo step
+    pub fn finish_layer_edit(&mut self) {
+        let Some((label, layer, before)) = self.layer_edit.take() else {
+            return;
+        };
+        let after = LayerProps::of(&self.layers[layer]);
+        if after != before {
+            self.history.push(HistoryEntry {
+                label,
+                change: Change::LayerProps {
+                    layer,
+                    before,
+                    after,
+                },
+            });
+        }
+    }
+
+    /// Makes another layer the one being painted on
+    pub fn set_active_layer(&mut self, index: usize) {
+        self.finish_layer_edit();
+        self.active_layer = index;
+    }
+
+    /// Combines a shape into the selection
+    pub fn select(&mut self, shape: &SelectionShape, mode: SelectionMode, antialias: bool) {
+        let before = self.selection.mask().map(|m| m.to_vec());
+        let mask = shape.rasterize(self.width, self.height, antialias);
+        self.selection.combine(mask, mode);
+        self.record_selection("Select", before);
+    }
+
+    /// Eyedropper: the color around (x, y), averaged over a (2 * radius + 1) square.
+    /// Reads the active layer, or the merged image with `merged`. None outside the canvas.
+    pub fn sample_color(&self, x: u32, y: u32, radius: u32, merged: bool) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let mut sum = [0u64; 4];
+        let mut count = 0u64;
+        for sy in y.saturating_sub(radius)..=(y + radius).min(self.height - 1) {
+            for sx in x.saturating_sub(radius)..=(x + radius).min(self.width - 1) {
+                let i = ((sy * self.width + sx) * 4) as usize;
+                let [r, g, b, a] = if merged {
+                    layers::merged_pixel(&self.layers, i)
+                } else {
+                    layers::pixel_at(&self.layers[self.active_layer].pixel_buffer, i)
+                };
+                // Weight by alpha so transparent neighbours don't drag the color to black
+                let a64 = a as u64;
+                sum[0] += r as u64 * a64;
+                sum[1] += g as u64 * a64;
+                sum[2] += b as u64 * a64;
+                sum[3] += a64;
+                count += 1;
+            }
+        }
+        if sum[3] == 0 {
+            return Some([0, 0, 0, 0]);
+        }
+        let color = |c: u64| ((c + sum[3] / 2) / sum[3]) as u8;
+        Some([
+            color(sum[0]),
+            color(sum[1]),
+            color(sum[2]),
+            ((sum[3] + count / 2) / count) as u8,
+        ])
+    }
+
+    /// The exact RGBA value at (x, y) of the active layer (or the merged image).
+    /// None outside the canvas.
+    pub fn pixel(&self, x: u32, y: u32, merged: bool) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        Some(if merged {
+            layers::merged_pixel(&self.layers, i)
+        } else {
+            layers::pixel_at(&self.layers[self.active_layer].pixel_buffer, i)
+        })
+    }
+
+    /// RGBA pixels of a `width` x `height` block at (x, y) of the active layer (or the merged
+    /// image), row by row. Whatever lies outside the canvas comes back transparent.
+    pub fn copy_region(&self, x: i64, y: i64, width: u32, height: u32, merged: bool) -> Vec<u8> {
+        let mut out = vec![0u8; width as usize * height as usize * 4];
+        for (j, sy) in (y..y + height as i64).enumerate() {
+            for (i, sx) in (x..x + width as i64).enumerate() {
+                if sx < 0 || sy < 0 {
+                    continue;
+                }
+                if let Some(pixel) = self.pixel(sx as u32, sy as u32, merged) {
+                    let dst = (j * width as usize + i) * 4;
+                    out[dst..dst + 4].copy_from_slice(&pixel);
+                }
+            }
+        }
+        out
+    }
+
+    /// Selection coverage (one byte per pixel) of a block, like `copy_region`.
+    /// Outside the canvas nothing is selected.
+    pub fn selection_region(&self, x: i64, y: i64, width: u32, height: u32) -> Vec<u8> {
+        let mut out = vec![0u8; width as usize * height as usize];
+        for (j, sy) in (y..y + height as i64).enumerate() {
+            for (i, sx) in (x..x + width as i64).enumerate() {
+                if sx >= 0 && sy >= 0 && sx < self.width as i64 && sy < self.height as i64 {
+                    out[j * width as usize + i] = self.selection.coverage(sx as u32, sy as u32);
+                }
+            }
+        }
+        out
+    }
+
+    /// Magic wand: selects pixels similar in color to the one at `seed`
+    pub fn select_color(
+        &mut self,
+        seed: (u32, u32),
+        options: &WandOptions,
+        mode: SelectionMode,
+        antialias: bool,
+    ) {
+        if seed.0 >= self.width || seed.1 >= self.height {
+            return;
+        }
+        let merged;
+        let pixels = if options.sample_merged {
+            merged = self.flatten();
+            &merged
+        } else {
+            &self.layers[self.active_layer].pixel_buffer
+        };
+        let matched = fill::similar_pixels(
+            pixels,
+            self.width,
+            self.height,
+            seed,
+            options.tolerance,
+            options.contiguous,
+        );
+        let mask = fill::coverage_mask(&matched, self.width, self.height, antialias);
+
+        let before = self.selection.mask().map(|m| m.to_vec());
+        self.selection.combine(mask, mode);
+        self.record_selection("Magic Wand", before);
+    }
+
+    pub fn select_all(&mut self) {
+        let before = self.selection.mask().map(|m| m.to_vec());
+        self.selection.select_all();
+        self.record_selection("Select All", before);
+    }
+
+    pub fn select_none(&mut self) {
+        if !self.selection.is_active() {
+            return;
+        }
+        let before = self.selection.mask().map(|m| m.to_vec());
+        self.selection.select_none();
+        self.record_selection("Deselect", before);
+    }
+
+    pub fn invert_selection(&mut self) {
+        if !self.selection.is_active() {
+            return;
+        }
+        let before = self.selection.mask().map(|m| m.to_vec());
+        self.selection.invert();
+        self.record_selection("Invert Selection", before);
+    }
+
+    pub fn add_layer(&mut self) {
+        self.finish_layer_edit();
+        let before = self.layer_stack();
+        let name = format!("Layer {}", self.layers.len() + 1);
+        let layer = Layer::new(&name, self.width, self.height, [0, 0, 0, 0]);
+        self.active_layer += 1;
+        self.layers.insert(self.active_layer, layer);
+        self.record_layers("Add Layer", before);
+    }
+
+    pub fn delete_layer(&mut self, index: usize) {
+        self.finish_layer_edit();
+        // A document always keeps at least one layer
+        if self.layers.len() <= 1 {
+            return;
+        }
+        let before = self.layer_stack();
+        self.layers.remove(index);
+        if self.active_layer >= index && self.active_layer > 0 {
+            self.active_layer -= 1;
+        }
+        self.record_layers("Delete Layer", before);
+    }
+
+    pub fn duplicate_layer(&mut self, index: usize) {
+        self.finish_layer_edit();
+        let before = self.layer_stack();
+        let mut copy = self.layers[index].clone();
+        copy.name = format!("{} copy", copy.name);
+        self.layers.insert(index + 1, copy);
+        self.active_layer = index + 1;
+        self.record_layers("Duplicate Layer", before);
+    }
+
+    /// Moves a layer to a new position in the stack (0 is the bottom)
+    pub fn move_layer(&mut self, from: usize, to: usize) {
+        self.finish_layer_edit();
+        if from == to || to >= self.layers.len() {
+            return;
+        }
+        let before = self.layer_stack();
+        let layer = self.layers.remove(from);
+        self.layers.insert(to, layer);
+        self.active_layer = to;
+        self.record_layers("Move Layer", before);
+    }
+
+    /// Flattens a layer into the one below it, using its opacity and blend mode
+    pub fn merge_down(&mut self, index: usize) {
+        self.finish_layer_edit();
+        if index == 0 {
+            return;
+        }
+        let before = self.layer_stack();
+        let upper = self.layers.remove(index);
+        let lower = &mut self.layers[index - 1];
+        if upper.visible {
+            for i in (0..lower.pixel_buffer.len()).step_by(4) {
+                let src = layers::pixel_at(&upper.pixel_buffer, i);
+                let dst = layers::pixel_at(&lower.pixel_buffer, i);
+                lower.pixel_buffer[i..i + 4].copy_from_slice(&layers::blend_pixel(
+                    dst,
+                    src,
+                    upper.opacity,
+                    upper.blend_mode,
+                ));
+            }
+        }
+        self.active_layer = index - 1;
+        self.record_layers("Merge Down", before);
+    }
+
+    /// Returns true if something was undone (texture needs an update)
+    pub fn undo(&mut self) -> bool {
+        // A half-finished rename or opacity drag is undone as a whole
+        self.finish_layer_edit();
+        let Some(entry) = self.history.undo() else {
+            return false;
+        };
+        match &entry.change {
+            Change::Pixels {
+                layer,
+                region,
+                before,
+                ..
+            } => write_pixels(
+                &mut self.layers[*layer].pixel_buffer,
+                self.width,
+                *region,
+                before,
+            ),
+            Change::Layers { before, .. } => {
+                self.layers = before.layers.clone();
+                self.active_layer = before.active_layer;
+            }
+            Change::LayerProps { layer, before, .. } => before.apply_to(&mut self.layers[*layer]),
+            Change::Selection { before, .. } => self.selection.set_mask(before.clone()),
+        }
+        true
+    }
+
+    /// Returns true if something was redone (texture needs an update)
+    pub fn redo(&mut self) -> bool {
+        self.finish_layer_edit();
+        let Some(entry) = self.history.redo() else {
+            return false;
+        };
+        match &entry.change {
+            Change::Pixels {
+                layer,
+                region,
+                after,
+                ..
+            } => write_pixels(
+                &mut self.layers[*layer].pixel_buffer,
+                self.width,
+                *region,
+                after,
+            ),
+            Change::Layers { after, .. } => {
+                self.layers = after.layers.clone();
+                self.active_layer = after.active_layer;
+            }
+            Change::LayerProps { layer, after, .. } => after.apply_to(&mut self.layers[*layer]),
+            Change::Selection { after, .. } => self.selection.set_mask(after.clone()),
+        }
+        true
+    }
+}
+
+fn write_pixels(buffer: &mut [u8], buffer_width: u32, region: Region, pixels: &[u8]) {
+    let row_len = (region.width * 4) as usize;
+    for (row, y) in (region.y..region.y + region.height).enumerate() {
+        let start = ((y * buffer_width + region.x) * 4) as usize;
+        buffer[start..start + row_len].copy_from_slice(&pixels[row * row_len..(row + 1) * row_len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        layers::pixel_at(pixels, ((y * width + x) * 4) as usize)
+    }
+
+    fn rect(x: f64, y: f64, width: f64, height: f64, color: [u8; 4]) -> PaintCommand {
+        PaintCommand::FillRect {
+            x,
+            y,
+            width,
+            height,
+            color,
+            antialias: false,
+        }
+    }
+
+    #[test]
+    fn stroke_stays_out_of_the_layer_until_committed() {
+        let mut doc = Document::new(8, 8, WHITE);
+        doc.apply_command(&rect(2.0, 2.0, 2.0, 2.0, RED));
+
+        assert_eq!(pixel(&doc.layers[0].pixel_buffer, 8, 2, 2), WHITE);
+        assert_eq!(pixel(&doc.composite(), 8, 2, 2), RED);
+        assert_eq!(pixel(&doc.flatten(), 8, 2, 2), WHITE);
+
+        doc.commit_stroke();
+        assert_eq!(pixel(&doc.layers[0].pixel_buffer, 8, 2, 2), RED);
+        assert!(doc.stroke_buffer.iter().all(|&b| b == 0));
+        assert_eq!(doc.history.undo_label(), Some("Brush Stroke"));
+    }
+
+    #[test]
+    fn overlapping_dabs_keep_the_highest_alpha() {
+        let mut doc = Document::new(4, 4, [0, 0, 0, 0]);
+        doc.draw_to_stroke(1, 1, 0, 0, 255, 100);
+        doc.draw_to_stroke(1, 1, 0, 0, 255, 100);
+        doc.draw_to_stroke(1, 1, 0, 0, 255, 50);
+        assert_eq!(pixel(&doc.stroke_buffer, 4, 1, 1), [0, 0, 255, 100]);
+        doc.draw_to_stroke(1, 1, 0, 255, 0, 200);
+        assert_eq!(pixel(&doc.stroke_buffer, 4, 1, 1), [0, 255, 0, 200]);
+    }
+
+    #[test]
+    fn clear_stroke_discards_without_history() {
+        let mut doc = Document::new(4, 4, WHITE);
+        doc.apply_command(&rect(0.0, 0.0, 4.0, 4.0, RED));
+        doc.apply_command(&PaintCommand::ClearStroke);
+        doc.commit_stroke();
+        assert!(doc.layers[0].pixel_buffer.chunks(4).all(|p| p == WHITE));
+        assert!(!doc.history.can_undo());
+    }
+
+    #[test]
+    fn undo_and_redo_restore_pixels() {
+        let mut doc = Document::new(6, 6, WHITE);
+        doc.apply_command(&rect(1.0, 1.0, 3.0, 3.0, RED));
+        doc.commit_stroke();
+        let painted = doc.layers[0].pixel_buffer.clone();
+
+        assert!(doc.undo());
+        assert!(doc.layers[0].pixel_buffer.chunks(4).all(|p| p == WHITE));
+        assert!(doc.redo());
+        assert_eq!(doc.layers[0].pixel_buffer, painted);
+        assert!(!doc.redo());
+    }
+
+    #[test]
+    fn painting_is_clipped_to_the_selection() {
+        let mut doc = Document::new(8, 8, WHITE);
+        doc.select(
+            &SelectionShape::rect_between((0.0, 0.0), (4.0, 8.0)),
+            SelectionMode::Replace,
+            false,
+        );
+        doc.apply_command(&rect(0.0, 0.0, 8.0, 8.0, RED));
+        doc.commit_stroke();
+        assert_eq!(pixel(&doc.layers[0].pixel_buffer, 8, 3, 5), RED);
+        assert_eq!(pixel(&doc.layers[0].pixel_buffer, 8, 4, 5), WHITE);
+    }
+
+    #[test]
+    fn erase_stroke_removes_alpha() {
+        let mut doc = Document::new(4, 4, RED);
+        doc.apply_command(&PaintCommand::SetComposite {
+            op: CompositeOp::Erase,
+        });
+        doc.apply_command(&rect(0.0, 0.0, 2.0, 4.0, WHITE));
+        doc.commit_stroke();
+        assert_eq!(pixel(&doc.layers[0].pixel_buffer, 4, 0, 0)[3], 0);
+        assert_eq!(pixel(&doc.layers[0].pixel_buffer, 4, 3, 0), RED);
+        // The next stroke paints normally again
+        assert_eq!(doc.stroke_op, CompositeOp::Over);
+    }
+
+    #[test]
+    fn locked_layers_are_not_painted() {
+        let mut doc = Document::new(4, 4, WHITE);
+        doc.layers[0].locked = true;
+        doc.apply_command(&rect(0.0, 0.0, 4.0, 4.0, RED));
+        doc.commit_stroke();
+        assert!(doc.layers[0].pixel_buffer.chunks(4).all(|p| p == WHITE));
+    }
+
+    #[test]
+    fn flood_fill_stops_at_edges() {
+        let mut doc = Document::new(6, 6, WHITE);
+        doc.apply_command(&rect(3.0, 0.0, 1.0, 6.0, RED));
+        doc.commit_stroke();
+        doc.apply_command(&PaintCommand::FloodFill {
+            x: 0,
+            y: 0,
+            color: [0, 0, 255, 255],
+            tolerance: 0,
+            contiguous: true,
+            antialias: false,
+        });
+        doc.commit_stroke();
+        let pixels = &doc.layers[0].pixel_buffer;
+        assert_eq!(pixel(pixels, 6, 2, 5), [0, 0, 255, 255]);
+        assert_eq!(pixel(pixels, 6, 3, 5), RED);
+        assert_eq!(pixel(pixels, 6, 5, 5), WHITE);
+    }
+
+    #[test]
+    fn layer_operations_are_undoable() {
+        let mut doc = Document::new(4, 4, WHITE);
+        doc.add_layer();
+        assert_eq!((doc.layers.len(), doc.active_layer), (2, 1));
+        doc.apply_command(&rect(0.0, 0.0, 4.0, 4.0, [255, 0, 0, 128]));
+        doc.commit_stroke();
+        doc.merge_down(1);
+        assert_eq!(doc.layers.len(), 1);
+        assert_eq!(
+            pixel(&doc.layers[0].pixel_buffer, 4, 0, 0),
+            [255, 127, 127, 255]
+        );
+
+        assert!(doc.undo());
+        assert_eq!(doc.layers.len(), 2);
+        assert_eq!(doc.active_layer, 1);
+    }
+
+    #[test]
+    fn layer_property_edits_are_undoable() {
+        let mut doc = Document::new(2, 2, WHITE);
+        // A slider drag is many edits but one step
+        for opacity in [0.8, 0.5, 0.25] {
+            doc.edit_layer("Layer Opacity", 0, |l| l.opacity = opacity);
+        }
+        doc.finish_layer_edit();
+        doc.edit_layer("Blend Mode", 0, |l| {
+            l.blend_mode = layers::BlendMode::Screen
+        });
+        doc.finish_layer_edit();
+        assert!(matches!(
+            doc.history.undo().map(|e| &e.change),
+            Some(Change::LayerProps { .. })
+        ));
+        doc.history.redo();
+
+        assert!(doc.undo());
+        assert_eq!(doc.layers[0].blend_mode, layers::BlendMode::Normal);
+        assert_eq!(doc.history.undo_label(), Some("Layer Opacity"));
+        assert!(doc.undo());
+        assert_eq!(doc.layers[0].opacity, 1.0);
+        assert!(doc.redo());
+        assert_eq!(doc.layers[0].opacity, 0.25);
+    }
+
+    #[test]
+    fn pending_rename_lands_before_the_next_layer_operation() {
+        let mut doc = Document::new(2, 2, WHITE);
+        doc.edit_layer("Rename Layer", 0, |l| l.name = "Sky".to_string());
+        // The name field still has focus when Add is clicked
+        doc.add_layer();
+        doc.finish_layer_edit();
+        assert_eq!(doc.history.undo_label(), Some("Add Layer"));
+
+        assert!(doc.undo());
+        assert_eq!(doc.layers.len(), 1);
+        assert_eq!(doc.layers[0].name, "Sky");
+        assert!(doc.undo());
+        assert_eq!(doc.layers[0].name, "Background");
+        assert!(!doc.history.can_undo());
+    }
+
+    #[test]
+    fn pending_rename_lands_before_a_stroke() {
+        let mut doc = Document::new(2, 2, WHITE);
+        doc.edit_layer("Rename Layer", 0, |l| l.name = "Ink".to_string());
+        doc.apply_command(&rect(0.0, 0.0, 1.0, 1.0, RED));
+        doc.commit_stroke();
+
+        assert!(doc.undo());
+        assert_eq!(pixel(&doc.layers[0].pixel_buffer, 2, 0, 0), WHITE);
+        assert_eq!(doc.layers[0].name, "Ink");
+        assert!(doc.undo());
+        assert_eq!(doc.layers[0].name, "Background");
+    }
+
+    #[test]
+    fn sample_color_ignores_transparent_neighbours() {
+        let mut doc = Document::new(3, 3, [0, 0, 0, 0]);
+        doc.draw_to_stroke(1, 1, 200, 100, 0, 255);
+        doc.commit_stroke();
+        assert_eq!(doc.sample_color(1, 1, 1, false), Some([200, 100, 0, 28]));
+        assert_eq!(doc.sample_color(3, 0, 0, false), None);
+    }
+
+    #[test]
+    fn copy_region_pads_outside_the_canvas() {
+        let doc = Document::new(2, 2, RED);
+        let region = doc.copy_region(-1, 0, 2, 1, false);
+        assert_eq!(region, [0, 0, 0, 0, 255, 0, 0, 255]);
+    }
+}
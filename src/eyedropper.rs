@@ -0,0 +1,54 @@
+use crate::colors::ColorTarget;
+
+/// How many pixels the eyedropper averages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleSize {
+    Point,
+    Average3x3,
+    Average5x5,
+}
+
+impl SampleSize {
+    pub const ALL: [SampleSize; 3] = [
+        SampleSize::Point,
+        SampleSize::Average3x3,
+        SampleSize::Average5x5,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleSize::Point => "Point",
+            SampleSize::Average3x3 => "3x3 Average",
+            SampleSize::Average5x5 => "5x5 Average",
+        }
+    }
+
+    /// Pixels on each side of the center
+    pub fn radius(&self) -> u32 {
+        match self {
+            SampleSize::Point => 0,
+            SampleSize::Average3x3 => 1,
+            SampleSize::Average5x5 => 2,
+        }
+    }
+}
+
+/// Eyedropper settings (shared by the tool and the Alt+click shortcut)
+#[derive(Clone, Copy, Debug)]
+pub struct EyedropperOptions {
+    pub size: SampleSize,
+    // Pick what you see instead of just the active layer
+    pub sample_merged: bool,
+    // Which color the left button sets (the right button sets the other one)
+    pub target: ColorTarget,
+}
+
+impl Default for EyedropperOptions {
+    fn default() -> Self {
+        Self {
+            size: SampleSize::Point,
+            sample_merged: true,
+            target: ColorTarget::Primary,
+        }
+    }
+}
@@ -0,0 +1,112 @@
+/// Which pixels count as "the same color" as the seed pixel.
+/// `pixels` is RGBA; a pixel matches when no channel (alpha included) differs by more
+/// than `tolerance`. With `contiguous` only pixels connected to the seed (4-way) match,
+/// otherwise every similar pixel in the image does.
+pub fn similar_pixels(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    seed: (u32, u32),
+    tolerance: u8,
+    contiguous: bool,
+) -> Vec<bool> {
+    let (w, h) = (width as usize, height as usize);
+    let mut matched = vec![false; w * h];
+    if seed.0 >= width || seed.1 >= height {
+        return matched;
+    }
+    let seed_index = seed.1 as usize * w + seed.0 as usize;
+    let target = pixel(pixels, seed_index);
+    let similar = |i: usize| {
+        let p = pixel(pixels, i);
+        (0..4).all(|c| p[c].abs_diff(target[c]) <= tolerance)
+    };
+
+    if !contiguous {
+        for (i, m) in matched.iter_mut().enumerate() {
+            *m = similar(i);
+        }
+        return matched;
+    }
+
+    // Scanline flood: fill a whole horizontal run, then queue the rows above and below
+    let mut stack = vec![(seed.0 as usize, seed.1 as usize)];
+    while let Some((x, y)) = stack.pop() {
+        let row = y * w;
+        if matched[row + x] || !similar(row + x) {
+            continue;
+        }
+        let mut left = x;
+        while left > 0 && !matched[row + left - 1] && similar(row + left - 1) {
+            left -= 1;
+        }
+        let mut right = x;
+        while right + 1 < w && !matched[row + right + 1] && similar(row + right + 1) {
+            right += 1;
+        }
+        for m in &mut matched[row + left..=row + right] {
+            *m = true;
+        }
+
+        for ny in [y.wrapping_sub(1), y + 1] {
+            if ny >= h {
+                continue;
+            }
+            // One seed per run of candidates in the neighbouring row
+            let mut in_run = false;
+            for nx in left..=right {
+                let i = ny * w + nx;
+                let candidate = !matched[i] && similar(i);
+                if candidate && !in_run {
+                    stack.push((nx, ny));
+                }
+                in_run = candidate;
+            }
+        }
+    }
+    matched
+}
+
+/// Turns a hard mask into 8-bit coverage. With `antialias` pixels along the boundary
+/// get the 3x3 average of their neighbourhood so the edge isn't stair-stepped.
+pub fn coverage_mask(matched: &[bool], width: u32, height: u32, antialias: bool) -> Vec<u8> {
+    let hard: Vec<u8> = matched.iter().map(|m| if *m { 255 } else { 0 }).collect();
+    if !antialias {
+        return hard;
+    }
+    let (w, h) = (width as i64, height as i64);
+    let mut soft = hard.clone();
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = 0u32;
+            let mut edge = false;
+            let center = hard[(y * w + x) as usize];
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    // Outside the canvas counts as the same as the center pixel
+                    let (nx, ny) = (x + dx, y + dy);
+                    let value = if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                        center
+                    } else {
+                        hard[(ny * w + nx) as usize]
+                    };
+                    edge |= value != center;
+                    sum += value as u32;
+                }
+            }
+            if edge {
+                soft[(y * w + x) as usize] = (sum / 9) as u8;
+            }
+        }
+    }
+    soft
+}
+
+fn pixel(pixels: &[u8], i: usize) -> [u8; 4] {
+    [
+        pixels[i * 4],
+        pixels[i * 4 + 1],
+        pixels[i * 4 + 2],
+        pixels[i * 4 + 3],
+    ]
+}
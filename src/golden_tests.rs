@@ -0,0 +1,402 @@
+// Golden-image tests: scenes are painted through the CPU document (no GPU needed) and
+// compared with the PNGs in tests/golden. After an intended change to how things render,
+// rewrite them with `UPDATE_GOLDEN=1 cargo test` and look at the new images before
+// committing. Mismatches are written to target/golden-failures for a look.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use winit::event::{Force, TouchPhase};
+use winit::keyboard::ModifiersState;
+
+use crate::brush::{BrushEngine, BrushSettings};
+use crate::colors::{ColorPair, ColorTarget};
+use crate::commands::{PaintCommand, ShapeKind};
+use crate::document::Document;
+use crate::gradient::{Gradient, GradientShape, GradientStop, RepeatMode};
+use crate::image_io::{self, ExportOptions};
+use crate::input::{InputEvent, InputPipeline, PointerEvent, PressureCurve};
+use crate::layers::{BlendMode, Layer};
+use crate::packages::LoadedTool;
+use crate::scripting::{LuaEngine, ToolEvent};
+use crate::selection::{SelectionMode, SelectionShape};
+use crate::stabilizer::{StabilizerSettings, StrokePoint};
+use crate::stroke::{StrokeContext, ToolStroke};
+use crate::text::FontLibrary;
+
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+// Rounding in blending may differ by one between platforms; anything more is a change
+const TOLERANCE: u8 = 1;
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+/// Compares the flattened document with tests/golden/<name>.png
+fn check_golden(name: &str, document: &Document) {
+    let pixels = document.flatten();
+    let (width, height) = (document.width, document.height);
+    let path = golden_path(name);
+    let save = |path: &Path| {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image_io::save_image(path, width, height, &pixels, &ExportOptions::default()).unwrap();
+    };
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        save(&path);
+        return;
+    }
+    let expected = image_io::load_image(&path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {} (run with UPDATE_GOLDEN=1 to create it)",
+            path.display(),
+            e
+        )
+    });
+    assert_eq!(
+        (expected.width, expected.height),
+        (width, height),
+        "{}: size changed",
+        name
+    );
+    let wrong = pixels
+        .chunks(4)
+        .zip(expected.pixels.chunks(4))
+        .filter(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .any(|(a, b)| a.abs_diff(*b) > TOLERANCE)
+        })
+        .count();
+    if wrong > 0 {
+        let actual = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target/golden-failures")
+            .join(format!("{}.png", name));
+        save(&actual);
+        panic!(
+            "{}: {} pixels differ from the golden image, got {}",
+            name,
+            wrong,
+            actual.display()
+        );
+    }
+}
+
+fn load_tool(name: &str) -> LuaEngine {
+    let package_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("packages/default");
+    let script = package_path.join("tools").join(format!("{}.lua", name));
+    let mut lua = LuaEngine::new(Rc::new(FontLibrary::scan(&[])));
+    lua.load_tool(&LoadedTool {
+        name: name.to_string(),
+        script_content: fs::read_to_string(&script).unwrap(),
+        package_path,
+    });
+    lua
+}
+
+fn pen(phase: TouchPhase, x: f64, y: f64, pressure: f64) -> InputEvent {
+    InputEvent::Touch {
+        id: 1,
+        phase,
+        x,
+        y,
+        force: Some(Force::Normalized(pressure)),
+    }
+}
+
+/// A pen stroke from `from` to `to` in `steps` moves, pressing harder towards the
+/// middle and wobbling up and down so smoothing has something to do
+fn pen_stroke(from: (f64, f64), to: (f64, f64), steps: usize) -> Vec<InputEvent> {
+    (0..=steps)
+        .map(|i| {
+            let t = i as f64 / steps as f64;
+            let wobble = if i % 2 == 0 { 3.0 } else { -3.0 };
+            let x = from.0 + (to.0 - from.0) * t;
+            let y = from.1 + (to.1 - from.1) * t + wobble;
+            let pressure = 0.2 + 0.8 * (t * std::f64::consts::PI).sin();
+            let phase = match i {
+                0 => TouchPhase::Started,
+                i if i == steps => TouchPhase::Ended,
+                _ => TouchPhase::Moved,
+            };
+            pen(phase, x, y, pressure)
+        })
+        .collect()
+}
+
+/// Feeds events to a `ToolStroke` the way the app's pen handler does (move, then press
+/// or release), painting after every event as if each were a frame. Window and canvas
+/// coordinates are the same.
+fn draw(
+    lua: &mut LuaEngine,
+    document: &mut Document,
+    colors: &ColorPair,
+    smoothing: StabilizerSettings,
+    events: &[InputEvent],
+) {
+    let mut input = InputPipeline::new(PressureCurve::default());
+    let mut stroke = ToolStroke::new(smoothing);
+    let to_canvas = |x, y| (x, y);
+    for event in events {
+        let Some(pointer) = input.handle(event) else {
+            continue;
+        };
+        let (PointerEvent::Down(sample) | PointerEvent::Move(sample) | PointerEvent::Up(sample)) =
+            pointer;
+        let mut ctx = StrokeContext {
+            lua: &mut *lua,
+            document: &mut *document,
+            colors,
+            modifiers: ModifiersState::empty(),
+            event: ToolEvent {
+                x: sample.x,
+                y: sample.y,
+                // The app has let go of the button by the time it handles Up
+                button: (!matches!(pointer, PointerEvent::Up(_))).then_some(ColorTarget::Primary),
+                pressure: sample.pressure,
+                ..Default::default()
+            },
+            to_canvas: &to_canvas,
+        };
+        let point = StrokePoint {
+            x: sample.x,
+            y: sample.y,
+            pressure: sample.pressure,
+        };
+        stroke.push(&ctx, point);
+        match pointer {
+            PointerEvent::Down(_) => {
+                stroke.press(&mut ctx, ColorTarget::Primary, point);
+            }
+            PointerEvent::Move(_) => {}
+            PointerEvent::Up(_) => stroke.release(&mut ctx),
+        }
+        stroke.paint(&mut ctx);
+    }
+}
+
+#[test]
+fn shapes() {
+    let mut document = Document::new(64, 64, WHITE);
+    let commands = [
+        PaintCommand::FillRect {
+            x: 4.5,
+            y: 4.25,
+            width: 20.0,
+            height: 12.5,
+            color: [220, 40, 40, 255],
+            antialias: true,
+        },
+        PaintCommand::FillCircle {
+            cx: 46.0,
+            cy: 14.0,
+            radius: 10.3,
+            color: [40, 90, 220, 200],
+            antialias: true,
+        },
+        PaintCommand::DrawLine {
+            x1: 4.0,
+            y1: 60.0,
+            x2: 60.0,
+            y2: 30.0,
+            width: 3.5,
+            color: [20, 20, 20, 255],
+            antialias: true,
+        },
+        PaintCommand::FillPolygon {
+            points: vec![(8.0, 28.0), (28.0, 24.0), (20.0, 48.0)],
+            color: [40, 160, 60, 255],
+            antialias: true,
+        },
+        PaintCommand::Shape {
+            kind: ShapeKind::RoundedRect { radius: 6.0 },
+            start: (34.0, 34.0),
+            end: (60.0, 58.0),
+            width: 2.0,
+            fill: Some([250, 200, 0, 128]),
+            outline: Some([120, 60, 0, 255]),
+            antialias: true,
+        },
+    ];
+    for cmd in &commands {
+        document.apply_command(cmd);
+        // Separate commits so shapes overlap instead of sharing the max-alpha stroke
+        document.commit_stroke();
+    }
+    check_golden("shapes", &document);
+}
+
+#[test]
+fn brush_pressure_ramp() {
+    let mut document = Document::new(96, 32, WHITE);
+    let settings = BrushSettings {
+        size: 16.0,
+        hardness: 0.3,
+        pressure_size: 1.0,
+        pressure_opacity: 0.5,
+        ..Default::default()
+    };
+    let mut brush = BrushEngine::new();
+    brush.begin_stroke();
+    for cmd in brush.stroke(
+        &settings,
+        (8.0, 16.0),
+        (88.0, 16.0),
+        (0.1, 1.0),
+        [30, 30, 120],
+    ) {
+        document.apply_command(&cmd);
+    }
+    document.commit_stroke();
+    check_golden("brush_pressure_ramp", &document);
+}
+
+#[test]
+fn layer_blend_modes() {
+    let (width, height) = (48, 16);
+    let mut layers = vec![Layer::new("Background", width, height, [200, 120, 60, 255])];
+    // One band per mode, each on its own half-transparent layer
+    for (i, mode) in [
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Difference,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut layer = Layer::new(mode.name(), width, height, [0, 0, 0, 0]);
+        for y in 0..height {
+            for x in (i as u32 * 16)..(i as u32 + 1) * 16 {
+                let idx = ((y * width + x) * 4) as usize;
+                let shade = (x % 16 * 16) as u8;
+                layer.pixel_buffer[idx..idx + 4].copy_from_slice(&[shade, 90, 255 - shade, 255]);
+            }
+        }
+        layer.blend_mode = mode;
+        layer.opacity = 0.75;
+        layers.push(layer);
+    }
+    let document = Document::from_layers(width, height, layers);
+    check_golden("layer_blend_modes", &document);
+}
+
+#[test]
+fn radial_gradient_in_selection() {
+    let mut document = Document::new(48, 48, [0, 0, 0, 0]);
+    document.select(
+        &SelectionShape::ellipse_between((4.0, 4.0), (44.0, 44.0)),
+        SelectionMode::Replace,
+        true,
+    );
+    document.apply_command(&PaintCommand::Gradient(Gradient {
+        start: (24.0, 24.0),
+        end: (24.0, 4.0),
+        stops: vec![
+            GradientStop {
+                position: 0.0,
+                color: [255, 240, 200, 255],
+            },
+            GradientStop {
+                position: 1.0,
+                color: [120, 0, 80, 0],
+            },
+        ],
+        shape: GradientShape::Radial,
+        repeat: RepeatMode::None,
+        dither: true,
+    }));
+    document.commit_stroke();
+    check_golden("radial_gradient_in_selection", &document);
+}
+
+#[test]
+fn bucket_fill_inside_outline() {
+    let mut document = Document::new(32, 32, WHITE);
+    document.apply_command(&PaintCommand::Shape {
+        kind: ShapeKind::Ellipse,
+        start: (4.0, 4.0),
+        end: (28.0, 28.0),
+        width: 2.0,
+        fill: None,
+        outline: Some([0, 0, 0, 255]),
+        antialias: true,
+    });
+    document.commit_stroke();
+    let mut lua = load_tool("bucket");
+    let colors = ColorPair {
+        primary: [0.1, 0.6, 0.9, 1.0],
+        ..Default::default()
+    };
+    let click = [
+        pen(TouchPhase::Started, 16.0, 16.0, 1.0),
+        pen(TouchPhase::Ended, 16.0, 16.0, 1.0),
+    ];
+    let smoothing = StabilizerSettings::default();
+    draw(&mut lua, &mut document, &colors, smoothing, &click);
+    assert!(
+        document.history.can_undo(),
+        "the bucket should fill on press"
+    );
+    check_golden("bucket_fill_inside_outline", &document);
+}
+
+#[test]
+fn pencil_pen_stroke() {
+    let mut document = Document::new(96, 48, WHITE);
+    let mut lua = load_tool("pencil");
+    draw(
+        &mut lua,
+        &mut document,
+        &ColorPair::default(),
+        StabilizerSettings::default(),
+        &pen_stroke((8.0, 16.0), (88.0, 16.0), 16),
+    );
+    check_golden("pencil_pen_stroke", &document);
+}
+
+#[test]
+fn brush_smoothed_pen_stroke() {
+    let mut document = Document::new(96, 48, WHITE);
+    let mut lua = load_tool("brush");
+    let smoothing = StabilizerSettings {
+        string: 0.1,
+        average: 0.5,
+        spline: 1.0,
+    };
+    draw(
+        &mut lua,
+        &mut document,
+        &ColorPair::default(),
+        smoothing,
+        &pen_stroke((8.0, 24.0), (88.0, 24.0), 16),
+    );
+    check_golden("brush_smoothed_pen_stroke", &document);
+}
+
+#[test]
+fn shapes_tool_ignores_smoothing() {
+    // Shapes opts out of the stabilizer, so the rectangle ends exactly under the pen
+    let mut lua = load_tool("shapes");
+    assert!(!lua.wants_stabilizer());
+    let mut document = Document::new(32, 32, WHITE);
+    let smoothing = StabilizerSettings {
+        string: 1.0,
+        average: 1.0,
+        spline: 1.0,
+    };
+    let events = [
+        pen(TouchPhase::Started, 4.0, 4.0, 1.0),
+        pen(TouchPhase::Moved, 20.0, 10.0, 1.0),
+        pen(TouchPhase::Ended, 28.0, 28.0, 1.0),
+    ];
+    draw(
+        &mut lua,
+        &mut document,
+        &ColorPair::default(),
+        smoothing,
+        &events,
+    );
+    check_golden("shapes_tool_ignores_smoothing", &document);
+}
@@ -0,0 +1,156 @@
+use std::f64::consts::TAU;
+
+// 4x4 ordered dither thresholds (0..16)
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GradientShape {
+    // Bands perpendicular to the drag
+    Linear,
+    // Circles around the start point
+    Radial,
+    // Sweeps around the start point, starting at the drag direction
+    Conical,
+    // Squares (rotated with the drag) around the start point
+    Diamond,
+    // Linear, mirrored on both sides of the start point
+    Reflected,
+}
+
+impl GradientShape {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(GradientShape::Linear),
+            "radial" => Some(GradientShape::Radial),
+            "conical" => Some(GradientShape::Conical),
+            "diamond" => Some(GradientShape::Diamond),
+            "reflected" => Some(GradientShape::Reflected),
+            _ => None,
+        }
+    }
+}
+
+/// What happens past the end point
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepeatMode {
+    // Keep the end colors
+    None,
+    // Start over (sawtooth)
+    Repeat,
+    // Go back and forth (triangle)
+    Mirror,
+}
+
+impl RepeatMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(RepeatMode::None),
+            "repeat" => Some(RepeatMode::Repeat),
+            "mirror" => Some(RepeatMode::Mirror),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, t: f64) -> f64 {
+        match self {
+            RepeatMode::None => t.clamp(0.0, 1.0),
+            RepeatMode::Repeat => t.rem_euclid(1.0),
+            RepeatMode::Mirror => {
+                let t = t.rem_euclid(2.0);
+                if t > 1.0 { 2.0 - t } else { t }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GradientStop {
+    // 0..1 along the gradient
+    pub position: f64,
+    pub color: [u8; 4],
+}
+
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+    // Sorted by position
+    pub stops: Vec<GradientStop>,
+    pub shape: GradientShape,
+    pub repeat: RepeatMode,
+    pub dither: bool,
+}
+
+impl Gradient {
+    /// Color of pixel (x, y), sampled at its center
+    pub fn color_at(&self, x: u32, y: u32) -> [u8; 4] {
+        let t = self
+            .repeat
+            .apply(self.position((x as f64 + 0.5, y as f64 + 0.5)));
+        let color = self.sample(t);
+
+        // Spread the rounding error in a fixed pattern so smooth ramps don't band
+        let offset = if self.dither {
+            (BAYER_4X4[(y % 4) as usize][(x % 4) as usize] as f64 + 0.5) / 16.0 - 0.5
+        } else {
+            0.0
+        };
+        color.map(|c| (c * 255.0 + offset).round().clamp(0.0, 255.0) as u8)
+    }
+
+    /// Where `p` falls along the gradient, before repeating (0 at start, 1 at end)
+    fn position(&self, p: (f64, f64)) -> f64 {
+        let (ax, ay) = (self.end.0 - self.start.0, self.end.1 - self.start.1);
+        let length = (ax * ax + ay * ay).sqrt();
+        if length == 0.0 {
+            return 0.0;
+        }
+        let (dx, dy) = (p.0 - self.start.0, p.1 - self.start.1);
+        // Coordinates in the gradient's own frame: along the drag and across it
+        let along = (dx * ax + dy * ay) / length;
+        let across = (dy * ax - dx * ay) / length;
+
+        match self.shape {
+            GradientShape::Linear => along / length,
+            GradientShape::Reflected => along.abs() / length,
+            GradientShape::Radial => (dx * dx + dy * dy).sqrt() / length,
+            GradientShape::Diamond => (along.abs() + across.abs()) / length,
+            GradientShape::Conical => across.atan2(along).rem_euclid(TAU) / TAU,
+        }
+    }
+
+    /// Interpolated straight RGBA (0..1) at t
+    fn sample(&self, t: f64) -> [f64; 4] {
+        let to_unit = |c: [u8; 4]| c.map(|v| v as f64 / 255.0);
+        let (Some(first), Some(last)) = (self.stops.first(), self.stops.last()) else {
+            return [0.0; 4];
+        };
+        if t <= first.position {
+            return to_unit(first.color);
+        }
+        for pair in self.stops.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if t <= b.position {
+                let span = b.position - a.position;
+                let f = if span > 0.0 {
+                    (t - a.position) / span
+                } else {
+                    1.0
+                };
+                // Mix premultiplied so fading to transparent doesn't drag in the
+                // transparent stop's (invisible) color
+                let (ca, cb) = (to_unit(a.color), to_unit(b.color));
+                let alpha = ca[3] + (cb[3] - ca[3]) * f;
+                if alpha <= 0.0 {
+                    return [0.0; 4];
+                }
+                let mut out = [0.0, 0.0, 0.0, alpha];
+                for i in 0..3 {
+                    out[i] = (ca[i] * ca[3] + (cb[i] * cb[3] - ca[i] * ca[3]) * f) / alpha;
+                }
+                return out;
+            }
+        }
+        to_unit(last.color)
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::VecDeque;
+
+use crate::layers::{BlendMode, Layer};
+
+/// Default memory budget for the undo stack (256 MB)
+pub const DEFAULT_HISTORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// A rectangular area of the canvas, in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Grows the region so it also covers the pixel (x, y)
+    pub fn include(self, x: u32, y: u32) -> Self {
+        let min_x = self.x.min(x);
+        let min_y = self.y.min(y);
+        let max_x = (self.x + self.width).max(x + 1);
+        let max_y = (self.y + self.height).max(y + 1);
+        Self::new(min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+
+    /// Number of bytes an RGBA copy of this region takes
+    pub fn byte_len(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+}
+
+/// One undoable step
+pub struct HistoryEntry {
+    pub label: String,
+    pub change: Change,
+}
+
+pub enum Change {
+    /// The pixels of a region of one layer before and after the edit
+    Pixels {
+        layer: usize,
+        region: Region,
+        before: Vec<u8>,
+        after: Vec<u8>,
+    },
+    /// Structural edits (add, delete, reorder, merge...) keep the whole stack
+    Layers {
+        before: LayerStack,
+        after: LayerStack,
+    },
+    /// Property edits (name, visibility, opacity...) of one layer; no pixels involved
+    LayerProps {
+        layer: usize,
+        before: LayerProps,
+        after: LayerProps,
+    },
+    /// Selection masks before and after (None = nothing selected)
+    Selection {
+        before: Option<Vec<u8>>,
+        after: Option<Vec<u8>>,
+    },
+}
+
+/// A copy of every layer plus which one was active
+#[derive(Clone)]
+pub struct LayerStack {
+    pub layers: Vec<Layer>,
+    pub active_layer: usize,
+}
+
+/// Everything about a layer except its pixels
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerProps {
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+}
+
+impl LayerProps {
+    pub fn of(layer: &Layer) -> Self {
+        Self {
+            name: layer.name.clone(),
+            visible: layer.visible,
+            locked: layer.locked,
+            opacity: layer.opacity,
+            blend_mode: layer.blend_mode,
+        }
+    }
+
+    pub fn apply_to(&self, layer: &mut Layer) {
+        layer.name = self.name.clone();
+        layer.visible = self.visible;
+        layer.locked = self.locked;
+        layer.opacity = self.opacity;
+        layer.blend_mode = self.blend_mode;
+    }
+}
+
+impl HistoryEntry {
+    fn memory_size(&self) -> usize {
+        match &self.change {
+            Change::Pixels { before, after, .. } => before.len() + after.len(),
+            Change::Layers { before, after } => {
+                let stack_size =
+                    |s: &LayerStack| s.layers.iter().map(|l| l.pixel_buffer.len()).sum::<usize>();
+                stack_size(before) + stack_size(after)
+            }
+            Change::LayerProps { before, after, .. } => {
+                2 * std::mem::size_of::<LayerProps>() + before.name.len() + after.name.len()
+            }
+            Change::Selection { before, after } => {
+                before.as_ref().map_or(0, Vec::len) + after.as_ref().map_or(0, Vec::len)
+            }
+        }
+    }
+}
+
+pub struct History {
+    undo_stack: VecDeque<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    memory_budget: usize,
+    memory_used: usize,
+}
+
+impl History {
+    pub fn new(memory_budget: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            memory_budget,
+            memory_used: 0,
+        }
+    }
+
+    /// Records a new step. Anything that was undone is gone after this.
+    pub fn push(&mut self, entry: HistoryEntry) {
+        for dropped in self.redo_stack.drain(..) {
+            self.memory_used -= dropped.memory_size();
+        }
+        self.memory_used += entry.memory_size();
+        self.undo_stack.push_back(entry);
+        self.enforce_budget();
+    }
+
+    /// Moves the latest step onto the redo stack; the caller restores its `before` pixels
+    pub fn undo(&mut self) -> Option<&HistoryEntry> {
+        let entry = self.undo_stack.pop_back()?;
+        self.redo_stack.push(entry);
+        self.redo_stack.last()
+    }
+
+    /// Moves the last undone step back; the caller restores its `after` pixels
+    pub fn redo(&mut self) -> Option<&HistoryEntry> {
+        let entry = self.redo_stack.pop()?;
+        self.undo_stack.push_back(entry);
+        self.undo_stack.back()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo_label(&self) -> Option<&str> {
+        self.undo_stack.back().map(|e| e.label.as_str())
+    }
+
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo_stack.last().map(|e| e.label.as_str())
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    pub fn set_memory_budget(&mut self, bytes: usize) {
+        self.memory_budget = bytes;
+        self.enforce_budget();
+    }
+
+    /// Drops the oldest steps until we fit in the budget.
+    /// The most recent step is always kept, even if it alone is over budget.
+    fn enforce_budget(&mut self) {
+        while self.memory_used > self.memory_budget && self.undo_stack.len() > 1 {
+            if let Some(oldest) = self.undo_stack.pop_front() {
+                self.memory_used -= oldest.memory_size();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(label: &str, bytes: usize) -> HistoryEntry {
+        HistoryEntry {
+            label: label.to_string(),
+            change: Change::Pixels {
+                layer: 0,
+                region: Region::new(0, 0, 1, 1),
+                before: vec![0; bytes],
+                after: vec![0; bytes],
+            },
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_stacks() {
+        let mut history = History::new(DEFAULT_HISTORY_BUDGET);
+        history.push(pixels("One", 4));
+        history.push(pixels("Two", 4));
+        assert_eq!(history.undo().map(|e| e.label.clone()), Some("Two".into()));
+        assert_eq!(history.undo_label(), Some("One"));
+        assert_eq!(history.redo_label(), Some("Two"));
+        assert_eq!(history.redo().map(|e| e.label.clone()), Some("Two".into()));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn new_change_clears_redo() {
+        let mut history = History::new(DEFAULT_HISTORY_BUDGET);
+        history.push(pixels("One", 4));
+        history.push(pixels("Two", 4));
+        history.undo();
+        assert!(history.can_redo());
+        history.push(pixels("Three", 4));
+        assert!(!history.can_redo());
+        assert_eq!(history.undo_label(), Some("Three"));
+        // The dropped redo step no longer counts against the budget
+        assert_eq!(history.memory_used(), 16);
+    }
+
+    #[test]
+    fn oldest_steps_are_evicted_over_budget() {
+        let mut history = History::new(50);
+        history.push(pixels("One", 10));
+        history.push(pixels("Two", 10));
+        assert_eq!(history.memory_used(), 40);
+        history.push(pixels("Three", 10));
+        assert_eq!(history.memory_used(), 40);
+        assert_eq!(
+            history.undo().map(|e| e.label.clone()),
+            Some("Three".into())
+        );
+        assert_eq!(history.undo().map(|e| e.label.clone()), Some("Two".into()));
+        assert!(!history.can_undo(), "One should have been evicted");
+    }
+
+    #[test]
+    fn lowering_the_budget_evicts_right_away() {
+        let mut history = History::new(DEFAULT_HISTORY_BUDGET);
+        for label in ["One", "Two", "Three"] {
+            history.push(pixels(label, 10));
+        }
+        history.set_memory_budget(20);
+        assert_eq!(history.memory_used(), 20);
+        assert_eq!(history.undo_label(), Some("Three"));
+        history.undo();
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn latest_step_is_kept_even_over_budget() {
+        let mut history = History::new(10);
+        history.push(pixels("One", 4));
+        history.push(pixels("Huge", 100));
+        assert_eq!(history.undo_label(), Some("Huge"));
+        assert_eq!(history.memory_used(), 200);
+        history.undo();
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn region_include_grows_to_cover() {
+        let region = Region::new(5, 5, 1, 1).include(2, 8).include(6, 3);
+        assert_eq!(region, Region::new(2, 3, 5, 6));
+        assert_eq!(region.byte_len(), 5 * 6 * 4);
+    }
+}
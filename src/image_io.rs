@@ -0,0 +1,165 @@
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::codecs::tga::TgaEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::error::{ImageFormatHint, UnsupportedError};
+use image::io::Limits;
+use image::{ColorType, ImageEncoder, ImageError, ImageFormat, ImageResult};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::new_document::MAX_DOCUMENT_SIZE;
+
+/// Extensions offered in the Open dialog hint (anything `image` can decode works)
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "webp", "tga"];
+
+/// Format-specific settings used when writing a flat image
+#[derive(Clone, Copy, Debug)]
+pub struct ExportOptions {
+    pub jpeg_quality: u8,
+    pub png_compression: CompressionType,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            jpeg_quality: 90,
+            png_compression: CompressionType::Default,
+        }
+    }
+}
+
+pub fn png_compression_name(compression: CompressionType) -> &'static str {
+    match compression {
+        CompressionType::Fast => "Fast",
+        CompressionType::Best => "Best",
+        _ => "Default",
+    }
+}
+
+/// A decoded image, always converted to straight RGBA8
+pub struct LoadedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Images wider or taller than `MAX_DOCUMENT_SIZE` fail with `ImageError::Limits`
+/// before anything is decoded.
+pub fn load_image(path: &Path) -> ImageResult<LoadedImage> {
+    let mut reader = image::io::Reader::open(path)?.with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DOCUMENT_SIZE);
+    limits.max_image_height = Some(MAX_DOCUMENT_SIZE);
+    reader.limits(limits);
+    let img = reader.decode()?.to_rgba8();
+    Ok(LoadedImage {
+        width: img.width(),
+        height: img.height(),
+        pixels: img.into_raw(),
+    })
+}
+
+/// Writes RGBA pixels to disk, picking the format from the file extension
+pub fn save_image(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    options: &ExportOptions,
+) -> ImageResult<()> {
+    let format = ImageFormat::from_path(path)?;
+    // Opened only for formats we can write, so an unsupported one leaves an existing file alone
+    let create = || -> ImageResult<BufWriter<File>> { Ok(BufWriter::new(File::create(path)?)) };
+
+    match format {
+        ImageFormat::Png => {
+            PngEncoder::new_with_quality(create()?, options.png_compression, FilterType::Adaptive)
+                .write_image(pixels, width, height, ColorType::Rgba8)
+        }
+        ImageFormat::Jpeg => {
+            // JPEG has no alpha: flatten onto white so transparent areas don't turn black
+            let rgb = flatten_to_rgb(pixels, [255, 255, 255]);
+            JpegEncoder::new_with_quality(create()?, options.jpeg_quality).write_image(
+                &rgb,
+                width,
+                height,
+                ColorType::Rgb8,
+            )
+        }
+        ImageFormat::Bmp => {
+            let mut writer = create()?;
+            BmpEncoder::new(&mut writer).write_image(pixels, width, height, ColorType::Rgba8)
+        }
+        ImageFormat::WebP => WebPEncoder::new_lossless(create()?).write_image(
+            pixels,
+            width,
+            height,
+            ColorType::Rgba8,
+        ),
+        ImageFormat::Tga => {
+            TgaEncoder::new(create()?).write_image(pixels, width, height, ColorType::Rgba8)
+        }
+        other => Err(ImageError::Unsupported(UnsupportedError::from(
+            ImageFormatHint::Exact(other),
+        ))),
+    }
+}
+
+fn flatten_to_rgb(pixels: &[u8], background: [u8; 3]) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(pixels.len() / 4 * 3);
+    for px in pixels.chunks_exact(4) {
+        let alpha = px[3] as f32 / 255.0;
+        for c in 0..3 {
+            let value = px[c] as f32 * alpha + background[c] as f32 * (1.0 - alpha);
+            rgb.push(value.round() as u8);
+        }
+    }
+    rgb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn unsupported_formats_leave_existing_files_alone() {
+        let path = std::env::temp_dir().join(format!("pixle-test-{}.gif", std::process::id()));
+        fs::write(&path, b"keep me").unwrap();
+        let result = save_image(&path, 1, 1, &[0, 0, 0, 255], &ExportOptions::default());
+        let contents = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ImageError::Unsupported(_))));
+        assert_eq!(contents, b"keep me");
+    }
+
+    #[test]
+    fn oversized_images_are_refused() {
+        let path = std::env::temp_dir().join(format!("pixle-test-{}-wide.png", std::process::id()));
+        let width = MAX_DOCUMENT_SIZE + 1;
+        save_image(
+            &path,
+            width,
+            1,
+            &vec![0; width as usize * 4],
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        let loaded = load_image(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(ImageError::Limits(_))));
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let path = std::env::temp_dir().join(format!("pixle-test-{}.png", std::process::id()));
+        let pixels = [255, 0, 0, 255, 0, 255, 0, 128];
+        save_image(&path, 2, 1, &pixels, &ExportOptions::default()).unwrap();
+        let loaded = load_image(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().pixels, pixels);
+    }
+}
@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use winit::event::{Force, TouchPhase};
+
+use crate::config;
+
+const PRESSURE_FILE: &str = "pressure.toml";
+
+/// Maps raw pen pressure to what tools get to see
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PressureCurve {
+    // Raw pressure below this counts as none (a pen resting on the tablet)
+    pub threshold: f32,
+    // Below 1 light strokes come out heavier, above 1 they need a firmer hand
+    pub gamma: f32,
+    // Output range
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for PressureCurve {
+    fn default() -> Self {
+        Self {
+            threshold: 0.0,
+            gamma: 1.0,
+            min: 0.0,
+            max: 1.0,
+        }
+    }
+}
+
+impl PressureCurve {
+    pub fn apply(&self, raw: f32) -> f32 {
+        let span = (1.0 - self.threshold).max(f32::EPSILON);
+        let t = ((raw - self.threshold) / span).clamp(0.0, 1.0);
+        self.min + (self.max - self.min) * t.powf(self.gamma.max(0.01))
+    }
+
+    /// The user's curve, or the linear default
+    pub fn load() -> Self {
+        let path = config::config_file(PRESSURE_FILE);
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        toml::from_str(&text).unwrap_or_else(|e| {
+            println!("Ignoring {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = config::config_file(PRESSURE_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(&path, text).map_err(|e| e.to_string())
+    }
+}
+
+/// One point of pointer input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    // Window position (physical pixels)
+    pub x: f64,
+    pub y: f64,
+    // 0..1 after the pressure curve; the mouse always presses fully
+    pub pressure: f32,
+    // Pen altitude in radians (pi/2 = upright), where the platform reports it
+    pub tilt: Option<f64>,
+}
+
+/// The parts of winit's events the pipeline cares about, so it can be fed by hand
+#[derive(Clone, Copy, Debug)]
+pub enum InputEvent {
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        x: f64,
+        y: f64,
+        force: Option<Force>,
+    },
+}
+
+impl From<&winit::event::Touch> for InputEvent {
+    fn from(touch: &winit::event::Touch) -> Self {
+        InputEvent::Touch {
+            id: touch.id,
+            phase: touch.phase,
+            x: touch.location.x,
+            y: touch.location.y,
+            force: touch.force,
+        }
+    }
+}
+
+/// What an input event means for drawing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointerEvent {
+    // A pen or finger touched down
+    Down(Sample),
+    Move(Sample),
+    // The pen or finger was lifted (or the platform cancelled it)
+    Up(Sample),
+}
+
+/// Turns mouse and touch/pen events into samples with pressure.
+/// Mouse buttons stay with the app; touches act as the left button.
+pub struct InputPipeline {
+    pub curve: PressureCurve,
+    last: Sample,
+    // The finger or pen that is drawing; other touches are ignored until it lifts
+    touch: Option<u64>,
+}
+
+impl InputPipeline {
+    pub fn new(curve: PressureCurve) -> Self {
+        Self {
+            curve,
+            last: Sample {
+                x: 0.0,
+                y: 0.0,
+                pressure: 1.0,
+                tilt: None,
+            },
+            touch: None,
+        }
+    }
+
+    /// The most recent sample
+    pub fn last(&self) -> Sample {
+        self.last
+    }
+
+    pub fn touch_active(&self) -> bool {
+        self.touch.is_some()
+    }
+
+    pub fn handle(&mut self, event: &InputEvent) -> Option<PointerEvent> {
+        match *event {
+            InputEvent::CursorMoved { x, y } => {
+                // Platforms also move the cursor for touches; the touch events are better
+                if self.touch.is_some() {
+                    return None;
+                }
+                self.last = Sample {
+                    x,
+                    y,
+                    pressure: 1.0,
+                    tilt: None,
+                };
+                Some(PointerEvent::Move(self.last))
+            }
+            InputEvent::Touch {
+                id,
+                phase,
+                x,
+                y,
+                force,
+            } => {
+                let started = phase == TouchPhase::Started && self.touch.is_none();
+                if !started && self.touch != Some(id) {
+                    return None;
+                }
+                let (pressure, tilt) = match force {
+                    Some(force) => {
+                        let tilt = match force {
+                            Force::Calibrated { altitude_angle, .. } => altitude_angle,
+                            Force::Normalized(_) => None,
+                        };
+                        let raw = force.normalized().clamp(0.0, 1.0) as f32;
+                        (self.curve.apply(raw), tilt)
+                    }
+                    // No pressure support: like a mouse
+                    None => (1.0, None),
+                };
+                self.last = Sample {
+                    x,
+                    y,
+                    pressure,
+                    tilt,
+                };
+                Some(match phase {
+                    TouchPhase::Started => {
+                        self.touch = Some(id);
+                        PointerEvent::Down(self.last)
+                    }
+                    TouchPhase::Moved => PointerEvent::Move(self.last),
+                    TouchPhase::Ended | TouchPhase::Cancelled => {
+                        self.touch = None;
+                        PointerEvent::Up(self.last)
+                    }
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(id: u64, phase: TouchPhase, x: f64, force: Option<f64>) -> InputEvent {
+        InputEvent::Touch {
+            id,
+            phase,
+            x,
+            y: 0.0,
+            force: force.map(Force::Normalized),
+        }
+    }
+
+    #[test]
+    fn default_curve_is_linear() {
+        let curve = PressureCurve::default();
+        assert_eq!(curve.apply(0.0), 0.0);
+        assert_eq!(curve.apply(0.25), 0.25);
+        assert_eq!(curve.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn curve_threshold_and_range() {
+        let curve = PressureCurve {
+            threshold: 0.2,
+            gamma: 2.0,
+            min: 0.1,
+            max: 0.9,
+        };
+        assert_eq!(curve.apply(0.1), 0.1);
+        assert!((curve.apply(0.6) - 0.3).abs() < 1e-6);
+        assert_eq!(curve.apply(1.0), 0.9);
+    }
+
+    #[test]
+    fn mouse_presses_fully() {
+        let mut pipeline = InputPipeline::new(PressureCurve::default());
+        let event = pipeline.handle(&InputEvent::CursorMoved { x: 4.0, y: 5.0 });
+        let Some(PointerEvent::Move(sample)) = event else {
+            panic!("expected a move, got {:?}", event);
+        };
+        assert_eq!((sample.x, sample.y, sample.pressure), (4.0, 5.0, 1.0));
+    }
+
+    #[test]
+    fn touch_reports_pressure_and_hides_the_cursor() {
+        let mut pipeline = InputPipeline::new(PressureCurve::default());
+        let down = pipeline.handle(&touch(7, TouchPhase::Started, 1.0, Some(0.5)));
+        assert!(matches!(down, Some(PointerEvent::Down(s)) if s.pressure == 0.5));
+        assert!(pipeline.touch_active());
+
+        // The emulated cursor and other fingers are ignored while the pen is down
+        assert!(
+            pipeline
+                .handle(&InputEvent::CursorMoved { x: 9.0, y: 9.0 })
+                .is_none()
+        );
+        assert!(
+            pipeline
+                .handle(&touch(8, TouchPhase::Started, 2.0, None))
+                .is_none()
+        );
+
+        let up = pipeline.handle(&touch(7, TouchPhase::Ended, 3.0, None));
+        assert!(matches!(up, Some(PointerEvent::Up(s)) if s.x == 3.0 && s.pressure == 1.0));
+        assert!(!pipeline.touch_active());
+    }
+}
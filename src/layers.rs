@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Add,
+    Difference,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 6] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Add,
+        BlendMode::Difference,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Screen => "Screen",
+            BlendMode::Overlay => "Overlay",
+            BlendMode::Add => "Add",
+            BlendMode::Difference => "Difference",
+        }
+    }
+
+    /// Mixes one channel (0..1). `cb` is the backdrop, `cs` the layer on top.
+    fn mix(&self, cb: f32, cs: f32) -> f32 {
+        match self {
+            BlendMode::Normal => cs,
+            BlendMode::Multiply => cb * cs,
+            BlendMode::Screen => cb + cs - cb * cs,
+            BlendMode::Overlay => {
+                if cb <= 0.5 {
+                    2.0 * cb * cs
+                } else {
+                    let cb2 = 2.0 * cb - 1.0;
+                    cs + cb2 - cs * cb2
+                }
+            }
+            BlendMode::Add => (cb + cs).min(1.0),
+            BlendMode::Difference => (cb - cs).abs(),
+        }
+    }
+}
+
+/// How a finished stroke is combined with the layer it was painted on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompositeOp {
+    // Paint on top (the default)
+    Over,
+    // Remove alpha where the stroke is (destination-out)
+    Erase,
+    // Overwrite color and alpha with the stroke's
+    Replace,
+    // Paint only shows through where the layer is transparent
+    Behind,
+}
+
+impl CompositeOp {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "over" => Some(CompositeOp::Over),
+            "erase" => Some(CompositeOp::Erase),
+            "replace" => Some(CompositeOp::Replace),
+            "behind" => Some(CompositeOp::Behind),
+            _ => None,
+        }
+    }
+
+    /// Applies a stroke pixel to a layer pixel
+    pub fn apply(&self, dst: [u8; 4], src: [u8; 4]) -> [u8; 4] {
+        match self {
+            CompositeOp::Over => blend_pixel(dst, src, 1.0, BlendMode::Normal),
+            CompositeOp::Erase => {
+                let keep = 1.0 - src[3] as f32 / 255.0;
+                [dst[0], dst[1], dst[2], (dst[3] as f32 * keep).round() as u8]
+            }
+            CompositeOp::Replace => src,
+            CompositeOp::Behind => blend_pixel(src, dst, 1.0, BlendMode::Normal),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    // Straight (non-premultiplied) RGBA
+    pub pixel_buffer: Vec<u8>,
+}
+
+impl Layer {
+    pub fn new(name: &str, width: u32, height: u32, fill: [u8; 4]) -> Self {
+        let pixel_count = (width * height) as usize;
+        let mut pixel_buffer = Vec::with_capacity(pixel_count * 4);
+        for _ in 0..pixel_count {
+            pixel_buffer.extend_from_slice(&fill);
+        }
+        Self::from_pixels(name, pixel_buffer)
+    }
+
+    /// Wraps existing RGBA pixels (e.g. a decoded image file)
+    pub fn from_pixels(name: &str, pixel_buffer: Vec<u8>) -> Self {
+        Self {
+            name: name.to_string(),
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            pixel_buffer,
+        }
+    }
+}
+
+/// Composites `src` on top of `dst` (both straight RGBA) using the W3C separable blend formula.
+/// `opacity` scales the source alpha.
+pub fn blend_pixel(dst: [u8; 4], src: [u8; 4], opacity: f32, mode: BlendMode) -> [u8; 4] {
+    let sa = src[3] as f32 / 255.0 * opacity;
+    if sa <= 0.0 {
+        return dst;
+    }
+    let da = dst[3] as f32 / 255.0;
+    let out_a = sa + da * (1.0 - sa);
+
+    let mut out = [0u8; 4];
+    for c in 0..3 {
+        let cs = src[c] as f32 / 255.0;
+        let cb = dst[c] as f32 / 255.0;
+        // Where the backdrop is transparent the blend mode has nothing to act on
+        let mixed = (1.0 - da) * cs + da * mode.mix(cb, cs);
+        let value = (sa * mixed + da * cb * (1.0 - sa)) / out_a;
+        out[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    out[3] = (out_a * 255.0).round().clamp(0.0, 255.0) as u8;
+    out
+}
+
+/// The in-progress stroke: which layer it is on, its pixels and how it will be committed
+#[derive(Clone, Copy)]
+pub struct StrokePreview<'a> {
+    pub layer: usize,
+    pub pixels: &'a [u8],
+    pub op: CompositeOp,
+}
+
+/// Flattens the visible layers into `out` (which must be width * height * 4 bytes).
+/// The result keeps real alpha; transparent areas stay transparent.
+pub fn composite(layers: &[Layer], stroke: Option<StrokePreview>, out: &mut [u8]) {
+    out.fill(0);
+
+    for (index, layer) in layers.iter().enumerate() {
+        if !layer.visible || layer.opacity <= 0.0 {
+            continue;
+        }
+        let stroke = stroke.filter(|s| s.layer == index);
+
+        for i in (0..out.len()).step_by(4) {
+            let mut src = pixel_at(&layer.pixel_buffer, i);
+            if let Some(stroke) = stroke {
+                let stroke_px = pixel_at(stroke.pixels, i);
+                if stroke_px[3] > 0 {
+                    src = stroke.op.apply(src, stroke_px);
+                }
+            }
+            if src[3] == 0 {
+                continue;
+            }
+            let dst = pixel_at(out, i);
+            out[i..i + 4].copy_from_slice(&blend_pixel(dst, src, layer.opacity, layer.blend_mode));
+        }
+    }
+}
+
+/// One pixel (at byte offset `i`) of the merged image, same as `composite` without a stroke
+pub fn merged_pixel(layers: &[Layer], i: usize) -> [u8; 4] {
+    let mut out = [0; 4];
+    for layer in layers.iter().filter(|l| l.visible && l.opacity > 0.0) {
+        let src = pixel_at(&layer.pixel_buffer, i);
+        if src[3] > 0 {
+            out = blend_pixel(out, src, layer.opacity, layer.blend_mode);
+        }
+    }
+    out
+}
+
+pub fn pixel_at(buffer: &[u8], i: usize) -> [u8; 4] {
+    [buffer[i], buffer[i + 1], buffer[i + 2], buffer[i + 3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAY: [u8; 4] = [128, 128, 128, 255];
+
+    #[test]
+    fn normal_blend_over_opaque() {
+        let out = blend_pixel([0, 0, 255, 255], [255, 0, 0, 255], 0.5, BlendMode::Normal);
+        assert_eq!(out, [128, 0, 128, 255]);
+        assert_eq!(
+            blend_pixel(GRAY, [0, 0, 0, 0], 1.0, BlendMode::Multiply),
+            GRAY
+        );
+    }
+
+    #[test]
+    fn blend_modes_on_gray() {
+        let white = [255, 255, 255, 255];
+        assert_eq!(blend_pixel(GRAY, white, 1.0, BlendMode::Multiply), GRAY);
+        assert_eq!(blend_pixel(GRAY, white, 1.0, BlendMode::Screen), white);
+        assert_eq!(blend_pixel(GRAY, GRAY, 1.0, BlendMode::Add), white);
+        assert_eq!(
+            blend_pixel(GRAY, GRAY, 1.0, BlendMode::Difference),
+            [0, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn blend_modes_need_a_backdrop() {
+        // On a transparent pixel every mode just shows the source
+        let src = [200, 50, 10, 255];
+        for mode in BlendMode::ALL {
+            assert_eq!(blend_pixel([0, 0, 0, 0], src, 1.0, mode), src);
+        }
+    }
+
+    #[test]
+    fn composite_skips_hidden_layers_and_previews_the_stroke() {
+        let bottom = Layer::new("Bottom", 1, 1, GRAY);
+        let mut top = Layer::new("Top", 1, 1, [255, 0, 0, 255]);
+        top.visible = false;
+        let layers = [bottom, top];
+        let mut out = [0; 4];
+        composite(&layers, None, &mut out);
+        assert_eq!(out, GRAY);
+
+        let stroke = [0, 0, 0, 255];
+        composite(
+            &layers,
+            Some(StrokePreview {
+                layer: 0,
+                pixels: &stroke,
+                op: CompositeOp::Erase,
+            }),
+            &mut out,
+        );
+        assert_eq!(out, [0, 0, 0, 0]);
+        assert_eq!(merged_pixel(&layers, 0), GRAY);
+    }
+}
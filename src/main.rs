@@ -1,8 +1,31 @@
 mod app;
+mod brush;
 mod canvas;
+mod colors;
 mod commands;
+mod config;
+mod document;
+mod eyedropper;
+mod fill;
+#[cfg(test)]
+mod golden_tests;
+mod gradient;
+mod history;
+mod image_io;
+mod input;
+mod layers;
+mod new_document;
 mod packages;
+mod palette;
+mod project;
+mod raster;
 mod scripting; // <--- ADDED
+mod selection;
+mod shortcuts;
+mod stabilizer;
+mod stroke;
+mod text;
+mod viewport;
 
 use app::AppState;
 use winit::{event::*, event_loop::EventLoop, window::WindowBuilder};
@@ -12,12 +35,10 @@ fn main() {
     // ... Copy the main function from the previous response ...
     env_logger::init();
     let event_loop = EventLoop::new().unwrap();
-    let window = WindowBuilder::new()
-        .with_title("Untitled - Pixle 0.1.2")
-        .build(&event_loop)
-        .unwrap();
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
 
     let mut state = pollster::block_on(AppState::new(&window));
+    state.update_title(&window);
 
     let _ = event_loop.run(move |event, target| match event {
         Event::WindowEvent {
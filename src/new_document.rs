@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::config;
+use crate::document::DEFAULT_DPI;
+
+const PRESETS_FILE: &str = "document_presets.toml";
+
+// Keeps texture sizes within what wgpu guarantees on every backend
+pub const MAX_DOCUMENT_SIZE: u32 = 8192;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Background {
+    White,
+    Black,
+    Transparent,
+    Custom,
+}
+
+impl Background {
+    pub const ALL: [Background; 4] = [
+        Background::White,
+        Background::Black,
+        Background::Transparent,
+        Background::Custom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Background::White => "White",
+            Background::Black => "Black",
+            Background::Transparent => "Transparent",
+            Background::Custom => "Custom",
+        }
+    }
+}
+
+/// A named size and resolution
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub dpi: f64,
+}
+
+impl Preset {
+    fn new(name: &str, width: u32, height: u32, dpi: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            width,
+            height,
+            dpi,
+        }
+    }
+}
+
+pub fn builtin_presets() -> Vec<Preset> {
+    vec![
+        Preset::new("Default (800 x 600)", 800, 600, DEFAULT_DPI),
+        Preset::new("Pixel Art (64 x 64)", 64, 64, DEFAULT_DPI),
+        Preset::new("Icon (256 x 256)", 256, 256, DEFAULT_DPI),
+        Preset::new("HD (1280 x 720)", 1280, 720, DEFAULT_DPI),
+        Preset::new("Full HD (1920 x 1080)", 1920, 1080, DEFAULT_DPI),
+        Preset::new("A4 Portrait (300 DPI)", 2480, 3508, 300.0),
+        Preset::new("US Letter (300 DPI)", 2550, 3300, 300.0),
+    ]
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PresetFile {
+    #[serde(default)]
+    presets: Vec<Preset>,
+}
+
+/// Presets the user saved from the New dialog; a missing or broken file means none
+pub fn load_user_presets() -> Vec<Preset> {
+    let path = config::config_file(PRESETS_FILE);
+    let Ok(text) = fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+    match toml::from_str::<PresetFile>(&text) {
+        Ok(file) => file.presets,
+        Err(e) => {
+            println!("Ignoring {}: {}", path.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+pub fn save_user_presets(presets: &[Preset]) -> Result<(), String> {
+    let path = config::config_file(PRESETS_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let file = PresetFile {
+        presets: presets.to_vec(),
+    };
+    let text = toml::to_string(&file).map_err(|e| e.to_string())?;
+    fs::write(&path, text).map_err(|e| e.to_string())
+}
+
+/// Everything the File > New dialog asks for
+#[derive(Clone, Debug)]
+pub struct NewDocument {
+    pub width: u32,
+    pub height: u32,
+    pub dpi: f64,
+    pub background: Background,
+    // Only used with Background::Custom
+    pub custom_color: [u8; 4],
+}
+
+impl Default for NewDocument {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            dpi: DEFAULT_DPI,
+            background: Background::White,
+            custom_color: [128, 128, 128, 255],
+        }
+    }
+}
+
+impl NewDocument {
+    pub fn apply_preset(&mut self, preset: &Preset) {
+        self.width = preset.width;
+        self.height = preset.height;
+        self.dpi = preset.dpi;
+    }
+
+    pub fn fill_color(&self) -> [u8; 4] {
+        match self.background {
+            Background::White => [255, 255, 255, 255],
+            Background::Black => [0, 0, 0, 255],
+            Background::Transparent => [0, 0, 0, 0],
+            Background::Custom => self.custom_color,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let range = 1..=MAX_DOCUMENT_SIZE;
+        if !range.contains(&self.width) || !range.contains(&self.height) {
+            return Err(format!(
+                "Width and height must be between 1 and {}",
+                MAX_DOCUMENT_SIZE
+            ));
+        }
+        if self.dpi <= 0.0 {
+            return Err("Resolution must be positive".to_string());
+        }
+        Ok(())
+    }
+}
@@ -3,10 +3,14 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+use crate::palette::{self, Palette};
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct PackageManifest {
     pub name: String,
     pub version: String,
+    // Part of the manifest format, nothing shows it yet
+    #[allow(dead_code)]
     pub description: Option<String>,
 }
 
@@ -19,11 +23,16 @@ pub struct LoadedTool {
 
 pub struct PackageManager {
     pub tools: Vec<LoadedTool>,
+    // Shipped in each package's `palettes` folder
+    pub palettes: Vec<Palette>,
 }
 
 impl PackageManager {
     pub fn new() -> Self {
-        Self { tools: Vec::new() }
+        Self {
+            tools: Vec::new(),
+            palettes: Vec::new(),
+        }
     }
 
     pub fn load_packages(&mut self) {
@@ -57,6 +66,9 @@ impl PackageManager {
             println!("Found Package: {} v{}", manifest.name, manifest.version);
         }
 
+        self.palettes
+            .extend(palette::load_dir(&path.join("palettes")));
+
         let tools_path = path.join("tools");
         if tools_path.exists() {
             for entry in WalkDir::new(tools_path) {
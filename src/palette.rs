@@ -0,0 +1,666 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+// Saved palettes live here, one .gpl file each
+const PALETTES_DIR: &str = "palettes";
+const RECENT_LIMIT: usize = 16;
+
+pub const SUPPORTED_EXTENSIONS: [&str; 5] = ["gpl", "ase", "txt", "pal", "hex"];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Swatch {
+    // Straight RGBA
+    pub color: [u8; 4],
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Palette {
+    pub name: String,
+    pub swatches: Vec<Swatch>,
+}
+
+impl Palette {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            swatches: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, color: [u8; 4]) {
+        self.swatches.push(Swatch {
+            color,
+            name: String::new(),
+        });
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.swatches.len() {
+            self.swatches.remove(index);
+        }
+    }
+
+    /// Drag and drop: `from` ends up at position `to`
+    pub fn move_swatch(&mut self, from: usize, to: usize) {
+        if from < self.swatches.len() && to < self.swatches.len() {
+            let swatch = self.swatches.remove(from);
+            self.swatches.insert(to, swatch);
+        }
+    }
+}
+
+/// The last few colors painted with, most recent first
+#[derive(Default)]
+pub struct RecentColors {
+    colors: Vec<[u8; 4]>,
+}
+
+impl RecentColors {
+    pub fn push(&mut self, color: [u8; 4]) {
+        self.colors.retain(|c| *c != color);
+        self.colors.insert(0, color);
+        self.colors.truncate(RECENT_LIMIT);
+    }
+
+    pub fn colors(&self) -> &[[u8; 4]] {
+        &self.colors
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteFormat {
+    // GIMP / Inkscape / Krita
+    Gpl,
+    // Adobe Swatch Exchange
+    Ase,
+    PaintNet,
+    Jasc,
+    // One RRGGBB per line (Lospec)
+    Hex,
+}
+
+impl PaletteFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "gpl" => Some(PaletteFormat::Gpl),
+            "ase" => Some(PaletteFormat::Ase),
+            "txt" => Some(PaletteFormat::PaintNet),
+            "pal" => Some(PaletteFormat::Jasc),
+            "hex" => Some(PaletteFormat::Hex),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PaletteFormat::Gpl => "GIMP Palette",
+            PaletteFormat::Ase => "Adobe Swatch Exchange",
+            PaletteFormat::PaintNet => "Paint.NET Palette",
+            PaletteFormat::Jasc => "JASC Palette",
+            PaletteFormat::Hex => "Hex List",
+        }
+    }
+
+    /// Paint.NET and hex files keep alpha; everything else stores opaque colors
+    pub fn has_alpha(&self) -> bool {
+        matches!(self, PaletteFormat::PaintNet | PaletteFormat::Hex)
+    }
+}
+
+pub fn load_palette(path: &Path) -> Result<Palette, String> {
+    let format = PaletteFormat::from_path(path).ok_or_else(|| unsupported(path))?;
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut palette = decode(format, &data)?;
+    if palette.name.is_empty() {
+        palette.name = stem;
+    }
+    Ok(palette)
+}
+
+pub fn save_palette(path: &Path, palette: &Palette) -> Result<(), String> {
+    let format = PaletteFormat::from_path(path).ok_or_else(|| unsupported(path))?;
+    fs::write(path, encode(format, palette)).map_err(|e| e.to_string())
+}
+
+fn decode(format: PaletteFormat, data: &[u8]) -> Result<Palette, String> {
+    if format == PaletteFormat::Ase {
+        return parse_ase(data);
+    }
+    let text = String::from_utf8_lossy(data);
+    match format {
+        PaletteFormat::Gpl => parse_gpl(&text),
+        PaletteFormat::PaintNet => parse_paint_net(&text),
+        PaletteFormat::Jasc => parse_jasc(&text),
+        _ => parse_hex_list(&text),
+    }
+}
+
+fn encode(format: PaletteFormat, palette: &Palette) -> Vec<u8> {
+    match format {
+        PaletteFormat::Gpl => write_gpl(palette).into_bytes(),
+        PaletteFormat::Ase => write_ase(palette),
+        PaletteFormat::PaintNet => write_paint_net(palette).into_bytes(),
+        PaletteFormat::Jasc => write_jasc(palette).into_bytes(),
+        PaletteFormat::Hex => write_hex_list(palette).into_bytes(),
+    }
+}
+
+/// Every palette file directly inside `dir` (unreadable ones are skipped)
+pub fn load_dir(dir: &Path) -> Vec<Palette> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| PaletteFormat::from_path(p).is_some())
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| match load_palette(path) {
+            Ok(palette) => Some(palette),
+            Err(e) => {
+                println!("Ignoring palette {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn load_saved_palettes() -> Vec<Palette> {
+    load_dir(&config::config_file(PALETTES_DIR))
+}
+
+/// Stores a palette in the user's palette folder (as .gpl, named after the palette)
+pub fn save_to_library(palette: &Palette) -> Result<(), String> {
+    let dir = config::config_file(PALETTES_DIR);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    save_palette(&library_path(&dir, &palette.name), palette)
+}
+
+pub fn delete_from_library(name: &str) -> Result<(), String> {
+    let path = library_path(&config::config_file(PALETTES_DIR), name);
+    fs::remove_file(path).map_err(|e| e.to_string())
+}
+
+fn library_path(dir: &Path, name: &str) -> PathBuf {
+    // Keep the name usable as a file name on every platform
+    let file_name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || " -_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.join(format!("{}.gpl", file_name.trim()))
+}
+
+fn unsupported(path: &Path) -> String {
+    format!(
+        "unsupported palette file '{}' (use {})",
+        path.display(),
+        SUPPORTED_EXTENSIONS.join(", ")
+    )
+}
+
+// --- GIMP .gpl ---
+
+fn parse_gpl(text: &str) -> Result<Palette, String> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err("missing 'GIMP Palette' header".to_string());
+    }
+    let mut palette = Palette::default();
+    for line in lines {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("Name:") {
+            palette.name = name.trim().to_string();
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        // "r g b<whitespace>optional name"
+        let mut parts = line.split_whitespace();
+        let mut channel = || parts.next().and_then(|v| v.parse::<u8>().ok());
+        let (Some(r), Some(g), Some(b)) = (channel(), channel(), channel()) else {
+            return Err(format!("bad color line '{}'", line));
+        };
+        let name = parts.collect::<Vec<_>>().join(" ");
+        palette.swatches.push(Swatch {
+            color: [r, g, b, 255],
+            name,
+        });
+    }
+    Ok(palette)
+}
+
+fn write_gpl(palette: &Palette) -> String {
+    let mut out = format!("GIMP Palette\nName: {}\nColumns: 16\n#\n", palette.name);
+    for swatch in &palette.swatches {
+        let [r, g, b, _] = swatch.color;
+        out.push_str(&format!("{:3} {:3} {:3}\t{}\n", r, g, b, swatch.name));
+    }
+    out
+}
+
+// --- Paint.NET .txt: one AARRGGBB per line, ';' starts a comment ---
+
+fn parse_paint_net(text: &str) -> Result<Palette, String> {
+    let mut palette = Palette::default();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let value = u32::from_str_radix(line, 16)
+            .ok()
+            .filter(|_| line.len() == 8)
+            .ok_or_else(|| format!("bad color '{}' (expected AARRGGBB)", line))?;
+        let [a, r, g, b] = value.to_be_bytes();
+        palette.add([r, g, b, a]);
+    }
+    Ok(palette)
+}
+
+fn write_paint_net(palette: &Palette) -> String {
+    let mut out = String::from("; paint.net Palette File\n; Colors are written as AARRGGBB\n");
+    out.push_str(&format!("; {}\n", palette.name));
+    for swatch in &palette.swatches {
+        let [r, g, b, a] = swatch.color;
+        out.push_str(&format!("{:02X}{:02X}{:02X}{:02X}\n", a, r, g, b));
+    }
+    out
+}
+
+// --- JASC .pal (Paint Shop Pro) ---
+
+fn parse_jasc(text: &str) -> Result<Palette, String> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("JASC-PAL") {
+        return Err("missing 'JASC-PAL' header".to_string());
+    }
+    // Version ("0100") and color count; the count is only a hint
+    lines.next();
+    lines.next();
+    let mut palette = Palette::default();
+    for line in lines.filter(|l| !l.is_empty()) {
+        let channels: Vec<u8> = line
+            .split_whitespace()
+            .map(|v| v.parse::<u8>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("bad color line '{}'", line))?;
+        if channels.len() < 3 {
+            return Err(format!("bad color line '{}'", line));
+        }
+        palette.add([channels[0], channels[1], channels[2], 255]);
+    }
+    Ok(palette)
+}
+
+fn write_jasc(palette: &Palette) -> String {
+    let mut out = format!("JASC-PAL\r\n0100\r\n{}\r\n", palette.swatches.len());
+    for swatch in &palette.swatches {
+        let [r, g, b, _] = swatch.color;
+        out.push_str(&format!("{} {} {}\r\n", r, g, b));
+    }
+    out
+}
+
+// --- .hex: RRGGBB (or RRGGBBAA) per line ---
+
+fn parse_hex_list(text: &str) -> Result<Palette, String> {
+    let mut palette = Palette::default();
+    for line in text.lines().map(str::trim) {
+        let hex = line.trim_start_matches('#');
+        if hex.is_empty() {
+            continue;
+        }
+        let value = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6 || hex.len() == 8)
+            .ok_or_else(|| format!("bad color '{}' (expected RRGGBB)", line))?;
+        let color = if hex.len() == 6 {
+            let [_, r, g, b] = value.to_be_bytes();
+            [r, g, b, 255]
+        } else {
+            value.to_be_bytes()
+        };
+        palette.add(color);
+    }
+    Ok(palette)
+}
+
+fn write_hex_list(palette: &Palette) -> String {
+    let mut out = String::new();
+    for swatch in &palette.swatches {
+        let [r, g, b, a] = swatch.color;
+        out.push_str(&format!("{:02x}{:02x}{:02x}", r, g, b));
+        if a < 255 {
+            out.push_str(&format!("{:02x}", a));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// --- Adobe .ase (big-endian, UTF-16 names) ---
+
+const ASE_COLOR: u16 = 0x0001;
+const ASE_GROUP_START: u16 = 0xC001;
+const ASE_GROUP_END: u16 = 0xC002;
+
+fn parse_ase(data: &[u8]) -> Result<Palette, String> {
+    let truncated = || "file is truncated".to_string();
+    if data.get(..4) != Some(b"ASEF") {
+        return Err("not an Adobe Swatch Exchange file".to_string());
+    }
+    let u16_at = |pos: usize| -> Result<u16, String> {
+        let b = data.get(pos..pos + 2).ok_or_else(truncated)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    };
+    let u32_at = |pos: usize| -> Result<u32, String> {
+        let b = data.get(pos..pos + 4).ok_or_else(truncated)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let f32_at = |pos: usize| -> Result<f32, String> { Ok(f32::from_bits(u32_at(pos)?)) };
+    // Returns the name and the offset just past it
+    let name_at = |pos: usize| -> Result<(String, usize), String> {
+        let units = u16_at(pos)? as usize;
+        let mut chars = Vec::with_capacity(units);
+        for i in 0..units {
+            chars.push(u16_at(pos + 2 + i * 2)?);
+        }
+        // Drop the terminating NUL
+        let name = String::from_utf16_lossy(&chars)
+            .trim_end_matches('\0')
+            .to_string();
+        Ok((name, pos + 2 + units * 2))
+    };
+
+    let blocks = u32_at(8)?;
+    let mut palette = Palette::default();
+    let mut pos = 12;
+    for _ in 0..blocks {
+        let kind = u16_at(pos)?;
+        let length = u32_at(pos + 2)? as usize;
+        let body = pos + 6;
+        match kind {
+            ASE_COLOR => {
+                let (name, model_pos) = name_at(body)?;
+                let model = data.get(model_pos..model_pos + 4).ok_or_else(truncated)?;
+                let v = model_pos + 4;
+                let rgb = match model {
+                    b"RGB " => [f32_at(v)?, f32_at(v + 4)?, f32_at(v + 8)?],
+                    b"Gray" => [f32_at(v)?; 3],
+                    b"CMYK" => {
+                        let (c, m, y, k) =
+                            (f32_at(v)?, f32_at(v + 4)?, f32_at(v + 8)?, f32_at(v + 12)?);
+                        [
+                            (1.0 - c) * (1.0 - k),
+                            (1.0 - m) * (1.0 - k),
+                            (1.0 - y) * (1.0 - k),
+                        ]
+                    }
+                    b"LAB " => lab_to_rgb(f32_at(v)? * 100.0, f32_at(v + 4)?, f32_at(v + 8)?),
+                    _ => {
+                        println!("Skipping swatch '{}' in unknown color model", name);
+                        pos = body + length;
+                        continue;
+                    }
+                };
+                let [r, g, b] = rgb.map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8);
+                palette.swatches.push(Swatch {
+                    color: [r, g, b, 255],
+                    name,
+                });
+            }
+            // Groups are flattened; the first group name doubles as the palette name
+            ASE_GROUP_START if palette.name.is_empty() => {
+                palette.name = name_at(body)?.0;
+            }
+            _ => {}
+        }
+        pos = body + length;
+    }
+    Ok(palette)
+}
+
+fn write_ase(palette: &Palette) -> Vec<u8> {
+    let ase_name = |name: &str, block: &mut Vec<u8>| {
+        let units: Vec<u16> = name.encode_utf16().chain([0]).collect();
+        block.extend_from_slice(&(units.len() as u16).to_be_bytes());
+        for unit in &units {
+            block.extend_from_slice(&unit.to_be_bytes());
+        }
+    };
+    let write_block = |out: &mut Vec<u8>, kind: u16, block: &[u8]| {
+        out.extend_from_slice(&kind.to_be_bytes());
+        out.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        out.extend_from_slice(block);
+    };
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"ASEF");
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    // The swatches sit in one group carrying the palette name
+    out.extend_from_slice(&(palette.swatches.len() as u32 + 2).to_be_bytes());
+    let mut group = Vec::new();
+    ase_name(&palette.name, &mut group);
+    write_block(&mut out, ASE_GROUP_START, &group);
+
+    for swatch in &palette.swatches {
+        let mut block = Vec::new();
+        ase_name(&swatch.name, &mut block);
+        block.extend_from_slice(b"RGB ");
+        for channel in &swatch.color[..3] {
+            block.extend_from_slice(&(*channel as f32 / 255.0).to_be_bytes());
+        }
+        // Color type: normal (not global or spot)
+        block.extend_from_slice(&2u16.to_be_bytes());
+        write_block(&mut out, ASE_COLOR, &block);
+    }
+    write_block(&mut out, ASE_GROUP_END, &[]);
+    out
+}
+
+/// CIE L*a*b* (D65 white, close enough for swatches) to sRGB 0..1
+fn lab_to_rgb(l: f32, a: f32, b: f32) -> [f32; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let inverse = |t: f32| {
+        if t > 6.0 / 29.0 {
+            t * t * t
+        } else {
+            3.0 * (6.0f32 / 29.0).powi(2) * (t - 4.0 / 29.0)
+        }
+    };
+    let (x, y, z) = (0.95047 * inverse(fx), inverse(fy), 1.08883 * inverse(fz));
+    let linear = [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ];
+    linear.map(|c| {
+        if c <= 0.0031308 {
+            12.92 * c
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_FORMATS: [PaletteFormat; 5] = [
+        PaletteFormat::Gpl,
+        PaletteFormat::Ase,
+        PaletteFormat::PaintNet,
+        PaletteFormat::Jasc,
+        PaletteFormat::Hex,
+    ];
+
+    fn sample_palette() -> Palette {
+        Palette {
+            name: "Sunset Glow".to_string(),
+            swatches: vec![
+                Swatch {
+                    color: [255, 0, 0, 255],
+                    name: "Red".to_string(),
+                },
+                Swatch {
+                    color: [12, 200, 99, 128],
+                    name: "Half Green".to_string(),
+                },
+                Swatch {
+                    color: [0, 0, 0, 255],
+                    name: String::new(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let palette = sample_palette();
+        for format in ALL_FORMATS {
+            let loaded = decode(format, &encode(format, &palette))
+                .unwrap_or_else(|e| panic!("{}: {}", format.name(), e));
+            let colors: Vec<[u8; 4]> = loaded.swatches.iter().map(|s| s.color).collect();
+            let expected: Vec<[u8; 4]> = palette
+                .swatches
+                .iter()
+                .map(|s| match format.has_alpha() {
+                    true => s.color,
+                    false => [s.color[0], s.color[1], s.color[2], 255],
+                })
+                .collect();
+            assert_eq!(colors, expected, "{}", format.name());
+
+            // Only GIMP and Adobe files carry names
+            if matches!(format, PaletteFormat::Gpl | PaletteFormat::Ase) {
+                assert_eq!(loaded.name, palette.name, "{}", format.name());
+                assert_eq!(loaded.swatches[1].name, "Half Green", "{}", format.name());
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_ase_is_an_error() {
+        let data = encode(PaletteFormat::Ase, &sample_palette());
+        for len in 0..data.len() {
+            assert!(parse_ase(&data[..len]).is_err(), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn garbage_ase_is_an_error() {
+        assert!(parse_ase(b"not a palette at all").is_err());
+        // Valid header, absurd block count and lengths
+        let mut data = b"ASEF\x00\x01\x00\x00\xff\xff\xff\xff".to_vec();
+        data.extend_from_slice(&[0x00, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert!(parse_ase(&data).is_err());
+        let mut data = b"ASEF\x00\x01\x00\x00\x00\x00\x10\x00".to_vec();
+        data.extend(std::iter::repeat_n(0x42, 64));
+        assert!(parse_ase(&data).is_err());
+    }
+
+    /// An ASE file with one swatch in `model` with the given values
+    fn ase_with(model: &[u8; 4], values: &[f32]) -> Vec<u8> {
+        let mut block = vec![0x00, 0x02, 0x00, b'x', 0x00, 0x00];
+        block.extend_from_slice(model);
+        for v in values {
+            block.extend_from_slice(&v.to_be_bytes());
+        }
+        block.extend_from_slice(&2u16.to_be_bytes());
+        let mut data = b"ASEF\x00\x01\x00\x00\x00\x00\x00\x01".to_vec();
+        data.extend_from_slice(&ASE_COLOR.to_be_bytes());
+        data.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        data.extend_from_slice(&block);
+        data
+    }
+
+    #[test]
+    fn ase_color_models_convert_to_rgb() {
+        let color = |data: Vec<u8>| parse_ase(&data).unwrap().swatches[0].color;
+        assert_eq!(color(ase_with(b"Gray", &[0.5])), [128, 128, 128, 255]);
+        assert_eq!(
+            color(ase_with(b"CMYK", &[0.0, 1.0, 1.0, 0.0])),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(
+            color(ase_with(b"CMYK", &[0.0, 0.0, 0.0, 0.5])),
+            [128, 128, 128, 255]
+        );
+        // L is stored as 0..1
+        assert_eq!(
+            color(ase_with(b"LAB ", &[1.0, 0.0, 0.0])),
+            [255, 255, 255, 255]
+        );
+        assert_eq!(color(ase_with(b"LAB ", &[0.0, 0.0, 0.0])), [0, 0, 0, 255]);
+        let [r, g, b, _] = color(ase_with(b"LAB ", &[0.5323, 80.11, 67.22]));
+        assert!(r >= 250 && g <= 5 && b <= 5, "{:?}", [r, g, b]);
+        // Unknown models are skipped, not errors
+        assert!(
+            parse_ase(&ase_with(b"XYZ ", &[0.0, 0.0, 0.0]))
+                .unwrap()
+                .swatches
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn bad_color_lines_are_rejected() {
+        let cases = [
+            (PaletteFormat::Gpl, "GIMP Palette\nName: x\n255 0\n"),
+            (PaletteFormat::Gpl, "GIMP Palette\n300 0 0 Too bright\n"),
+            (PaletteFormat::Gpl, "255 0 0\n"),
+            (PaletteFormat::PaintNet, "; comment\nFFFF00\n"),
+            (PaletteFormat::PaintNet, "GGFF0000\n"),
+            (PaletteFormat::Jasc, "JASC-PAL\n0100\n1\n255 0\n"),
+            (PaletteFormat::Jasc, "JASC-PAL\n0100\n1\n255 0 -1\n"),
+            (PaletteFormat::Jasc, "255 0 0\n"),
+            (PaletteFormat::Hex, "ff0000\nnot-hex\n"),
+            (PaletteFormat::Hex, "#ff00\n"),
+        ];
+        for (format, text) in cases {
+            assert!(
+                decode(format, text.as_bytes()).is_err(),
+                "{}: {:?}",
+                format.name(),
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn text_formats_skip_comments_and_blank_lines() {
+        let gpl = decode(
+            PaletteFormat::Gpl,
+            b"GIMP Palette\nColumns: 4\n# comment\n\n  1   2   3\tDark Gray\n",
+        )
+        .unwrap();
+        assert_eq!(gpl.swatches[0].color, [1, 2, 3, 255]);
+        assert_eq!(gpl.swatches[0].name, "Dark Gray");
+        let hex = decode(PaletteFormat::Hex, b"#FF8000\n\n11223344\n").unwrap();
+        assert_eq!(hex.swatches[0].color, [255, 128, 0, 255]);
+        assert_eq!(hex.swatches[1].color, [0x11, 0x22, 0x33, 0x44]);
+    }
+}
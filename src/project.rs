@@ -0,0 +1,556 @@
+//! The native `.pixle` project format.
+//!
+//! Layout (all integers little-endian):
+//!
+//! ```text
+//! magic         8 bytes  "PIXLEDOC"
+//! version       u16 major, u16 minor
+//! chunks...     tag [u8; 4], length u64, data [u8; length], crc32 u32 (of tag + data)
+//! ```
+//!
+//! Chunks: `MNFT` (TOML manifest), one `LAYR` per layer (PNG encoded RGBA, bottom first),
+//! an optional `SELM` selection mask (PNG encoded grayscale, 1.3+) and a final `END `
+//! marker. Readers skip chunks they don't know, and the manifest ignores unknown keys,
+//! so minor version bumps stay readable. A newer major version is rejected.
+
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ColorType, ImageEncoder, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::document::DEFAULT_DPI;
+use crate::layers::{BlendMode, Layer};
+use crate::new_document::MAX_DOCUMENT_SIZE;
+use crate::palette::Swatch;
+
+pub const EXTENSION: &str = "pixle";
+
+const MAGIC: &[u8; 8] = b"PIXLEDOC";
+const VERSION_MAJOR: u16 = 1;
+const VERSION_MINOR: u16 = 3;
+
+const TAG_MANIFEST: [u8; 4] = *b"MNFT";
+const TAG_LAYER: [u8; 4] = *b"LAYR";
+const TAG_SELECTION: [u8; 4] = *b"SELM";
+const TAG_END: [u8; 4] = *b"END ";
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(std::io::Error),
+    NotAPixleFile,
+    NewerVersion { major: u16, minor: u16 },
+    Corrupt(String),
+    Manifest(String),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::Io(e) => write!(f, "{}", e),
+            ProjectError::NotAPixleFile => write!(f, "not a .pixle project file"),
+            ProjectError::NewerVersion { major, minor } => write!(
+                f,
+                "file format {}.{} was written by a newer Pixle (this build reads {}.x)",
+                major, minor, VERSION_MAJOR
+            ),
+            ProjectError::Corrupt(msg) => write!(f, "corrupt project file: {}", msg),
+            ProjectError::Manifest(msg) => write!(f, "invalid project manifest: {}", msg),
+            ProjectError::Image(e) => write!(f, "invalid layer data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {}
+
+impl From<std::io::Error> for ProjectError {
+    fn from(e: std::io::Error) -> Self {
+        ProjectError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for ProjectError {
+    fn from(e: image::ImageError) -> Self {
+        ProjectError::Image(e)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    app_version: String,
+    width: u32,
+    height: u32,
+    active_layer: usize,
+    // Added in 1.1
+    #[serde(default = "default_dpi")]
+    dpi: f64,
+    layers: Vec<LayerInfo>,
+    // Added in 1.2: the document palette
+    #[serde(default)]
+    palette: Vec<Swatch>,
+}
+
+fn default_dpi() -> f64 {
+    DEFAULT_DPI
+}
+
+#[derive(Serialize, Deserialize)]
+struct LayerInfo {
+    name: String,
+    visible: bool,
+    locked: bool,
+    opacity: f32,
+    blend_mode: BlendMode,
+}
+
+/// Everything stored in a project file
+pub struct Project {
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<Layer>,
+    pub active_layer: usize,
+    pub dpi: f64,
+    pub palette: Vec<Swatch>,
+    /// One coverage byte per pixel; None when nothing is selected
+    pub selection: Option<Vec<u8>>,
+}
+
+pub fn is_project_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(EXTENSION))
+}
+
+pub fn save_project(path: &Path, project: &Project) -> Result<(), ProjectError> {
+    let out = encode_project(project)?;
+    // Write next to the target first so a failed save never destroys the old file
+    let tmp_path = path.with_extension("pixle.tmp");
+    fs::write(&tmp_path, &out)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+pub fn load_project(path: &Path) -> Result<Project, ProjectError> {
+    decode_project(&fs::read(path)?)
+}
+
+fn encode_project(project: &Project) -> Result<Vec<u8>, ProjectError> {
+    let manifest = Manifest {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        width: project.width,
+        height: project.height,
+        active_layer: project.active_layer,
+        dpi: project.dpi,
+        layers: project
+            .layers
+            .iter()
+            .map(|l| LayerInfo {
+                name: l.name.clone(),
+                visible: l.visible,
+                locked: l.locked,
+                opacity: l.opacity,
+                blend_mode: l.blend_mode,
+            })
+            .collect(),
+        palette: project.palette.clone(),
+    };
+    let manifest = toml::to_string(&manifest).map_err(|e| ProjectError::Manifest(e.to_string()))?;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+    out.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+    write_chunk(&mut out, TAG_MANIFEST, manifest.as_bytes());
+
+    for layer in &project.layers {
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Default, FilterType::Adaptive)
+            .write_image(
+                &layer.pixel_buffer,
+                project.width,
+                project.height,
+                ColorType::Rgba8,
+            )?;
+        write_chunk(&mut out, TAG_LAYER, &png);
+    }
+    if let Some(mask) = &project.selection {
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Default, FilterType::Adaptive)
+            .write_image(mask, project.width, project.height, ColorType::L8)?;
+        write_chunk(&mut out, TAG_SELECTION, &png);
+    }
+    write_chunk(&mut out, TAG_END, &[]);
+    Ok(out)
+}
+
+fn decode_project(data: &[u8]) -> Result<Project, ProjectError> {
+    if data.len() < MAGIC.len() + 4 || &data[..MAGIC.len()] != MAGIC {
+        return Err(ProjectError::NotAPixleFile);
+    }
+    let major = u16::from_le_bytes([data[8], data[9]]);
+    let minor = u16::from_le_bytes([data[10], data[11]]);
+    if major > VERSION_MAJOR {
+        return Err(ProjectError::NewerVersion { major, minor });
+    }
+
+    let mut manifest: Option<Manifest> = None;
+    let mut layer_chunks: Vec<&[u8]> = Vec::new();
+    let mut selection_chunk: Option<&[u8]> = None;
+    let mut reached_end = false;
+
+    let mut pos = MAGIC.len() + 4;
+    while pos < data.len() {
+        let (tag, body, next) = read_chunk(data, pos)?;
+        pos = next;
+        match tag {
+            TAG_MANIFEST => {
+                let text = std::str::from_utf8(body)
+                    .map_err(|_| ProjectError::Manifest("not valid UTF-8".to_string()))?;
+                manifest =
+                    Some(toml::from_str(text).map_err(|e| ProjectError::Manifest(e.to_string()))?);
+            }
+            TAG_LAYER => layer_chunks.push(body),
+            TAG_SELECTION => selection_chunk = Some(body),
+            TAG_END => {
+                reached_end = true;
+                break;
+            }
+            // Written by a newer minor version; safe to ignore
+            _ => {}
+        }
+    }
+
+    if !reached_end {
+        return Err(ProjectError::Corrupt("file is truncated".to_string()));
+    }
+    let manifest = manifest.ok_or_else(|| ProjectError::Corrupt("missing manifest".to_string()))?;
+    let (width, height) = (manifest.width, manifest.height);
+    // Also guards against absurd sizes in damaged headers before we try to allocate them
+    if width == 0 || height == 0 || width > MAX_DOCUMENT_SIZE || height > MAX_DOCUMENT_SIZE {
+        return Err(ProjectError::Manifest(format!(
+            "bad canvas size {}x{}",
+            width, height
+        )));
+    }
+    if manifest.layers.is_empty() || manifest.layers.len() != layer_chunks.len() {
+        return Err(ProjectError::Corrupt(format!(
+            "manifest lists {} layers but the file holds {}",
+            manifest.layers.len(),
+            layer_chunks.len()
+        )));
+    }
+
+    let mut layers = Vec::with_capacity(layer_chunks.len());
+    for (info, chunk) in manifest.layers.into_iter().zip(layer_chunks) {
+        let img = image::load_from_memory_with_format(chunk, ImageFormat::Png)?.to_rgba8();
+        if img.width() != width || img.height() != height {
+            return Err(ProjectError::Corrupt(format!(
+                "layer '{}' is {}x{}, expected {}x{}",
+                info.name,
+                img.width(),
+                img.height(),
+                width,
+                height
+            )));
+        }
+        let mut layer = Layer::from_pixels(&info.name, img.into_raw());
+        layer.visible = info.visible;
+        layer.locked = info.locked;
+        layer.opacity = info.opacity.clamp(0.0, 1.0);
+        layer.blend_mode = info.blend_mode;
+        layers.push(layer);
+    }
+
+    // Files older than 1.3 have no selection chunk and open with nothing selected
+    let selection = match selection_chunk {
+        Some(chunk) => {
+            let img = image::load_from_memory_with_format(chunk, ImageFormat::Png)?.to_luma8();
+            if img.width() != width || img.height() != height {
+                return Err(ProjectError::Corrupt(format!(
+                    "selection is {}x{}, expected {}x{}",
+                    img.width(),
+                    img.height(),
+                    width,
+                    height
+                )));
+            }
+            Some(img.into_raw())
+        }
+        None => None,
+    };
+
+    Ok(Project {
+        width,
+        height,
+        active_layer: manifest.active_layer.min(layers.len() - 1),
+        dpi: manifest.dpi,
+        palette: manifest.palette,
+        layers,
+        selection,
+    })
+}
+
+fn write_chunk(out: &mut Vec<u8>, tag: [u8; 4], body: &[u8]) {
+    out.extend_from_slice(&tag);
+    out.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    out.extend_from_slice(body);
+    out.extend_from_slice(&chunk_crc(tag, body).to_le_bytes());
+}
+
+/// Returns (tag, body, offset of the next chunk)
+fn read_chunk(data: &[u8], pos: usize) -> Result<([u8; 4], &[u8], usize), ProjectError> {
+    let truncated = || ProjectError::Corrupt("file is truncated".to_string());
+
+    let header = data.get(pos..pos + 12).ok_or_else(truncated)?;
+    let tag = [header[0], header[1], header[2], header[3]];
+    let len = u64::from_le_bytes(header[4..12].try_into().unwrap());
+    let len = usize::try_from(len).map_err(|_| truncated())?;
+
+    let body_start = pos + 12;
+    let body_end = body_start.checked_add(len).ok_or_else(truncated)?;
+    let body = data.get(body_start..body_end).ok_or_else(truncated)?;
+    let crc_bytes = data.get(body_end..body_end + 4).ok_or_else(truncated)?;
+    let crc = u32::from_le_bytes(crc_bytes.try_into().unwrap());
+
+    if crc != chunk_crc(tag, body) {
+        return Err(ProjectError::Corrupt(format!(
+            "checksum mismatch in '{}' chunk",
+            String::from_utf8_lossy(&tag)
+        )));
+    }
+    Ok((tag, body, body_end + 4))
+}
+
+fn chunk_crc(tag: [u8; 4], body: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&tag);
+    hasher.update(body);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_project() -> Project {
+        let mut background = Layer::new("Background", 3, 2, [255, 255, 255, 255]);
+        background.pixel_buffer[0..4].copy_from_slice(&[10, 20, 30, 255]);
+        let mut top = Layer::new("Shading", 3, 2, [0, 0, 0, 0]);
+        top.pixel_buffer[20..24].copy_from_slice(&[200, 0, 100, 77]);
+        top.opacity = 0.4;
+        top.blend_mode = BlendMode::Multiply;
+        top.visible = false;
+        top.locked = true;
+        Project {
+            width: 3,
+            height: 2,
+            layers: vec![background, top],
+            active_layer: 0,
+            dpi: 300.0,
+            palette: vec![Swatch {
+                color: [1, 2, 3, 4],
+                name: "Ink".to_string(),
+            }],
+            selection: Some(vec![0, 64, 255, 255, 128, 0]),
+        }
+    }
+
+    /// A file with the current header and the given chunks
+    fn file_with(chunks: &[([u8; 4], &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        out.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+        for (tag, body) in chunks {
+            write_chunk(&mut out, *tag, body);
+        }
+        out
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(
+                &vec![0; width as usize * height as usize * 4],
+                width,
+                height,
+                ColorType::Rgba8,
+            )
+            .unwrap();
+        png
+    }
+
+    fn corrupt_message(result: Result<Project, ProjectError>) -> String {
+        match result {
+            Err(ProjectError::Corrupt(msg)) => msg,
+            Err(e) => panic!("expected a corrupt file error, got {}", e),
+            Ok(_) => panic!("expected a corrupt file error, got a project"),
+        }
+    }
+
+    #[test]
+    fn round_trips_everything() {
+        let project = sample_project();
+        let loaded = decode_project(&encode_project(&project).unwrap()).unwrap();
+        assert_eq!((loaded.width, loaded.height), (3, 2));
+        assert_eq!(loaded.active_layer, 0);
+        assert_eq!(loaded.dpi, 300.0);
+        assert_eq!(loaded.palette, project.palette);
+        assert_eq!(loaded.selection, project.selection);
+        assert_eq!(loaded.layers.len(), 2);
+        for (a, b) in loaded.layers.iter().zip(&project.layers) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.pixel_buffer, b.pixel_buffer);
+            assert_eq!(a.opacity, b.opacity);
+            assert_eq!(a.blend_mode, b.blend_mode);
+            assert_eq!((a.visible, a.locked), (b.visible, b.locked));
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_files() {
+        let path = std::env::temp_dir().join(format!("pixle-test-{}.pixle", std::process::id()));
+        save_project(&path, &sample_project()).unwrap();
+        let loaded = load_project(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().layers[1].name, "Shading");
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = encode_project(&sample_project()).unwrap();
+        data[0] = b'X';
+        assert!(matches!(
+            decode_project(&data),
+            Err(ProjectError::NotAPixleFile)
+        ));
+        assert!(matches!(
+            decode_project(b"PIX"),
+            Err(ProjectError::NotAPixleFile)
+        ));
+    }
+
+    #[test]
+    fn rejects_flipped_crc() {
+        let mut data = encode_project(&sample_project()).unwrap();
+        // Last byte of the manifest chunk's data, just before its crc
+        let manifest_len = u64::from_le_bytes(data[16..24].try_into().unwrap()) as usize;
+        data[24 + manifest_len - 1] ^= 0x01;
+        assert!(corrupt_message(decode_project(&data)).contains("checksum"));
+    }
+
+    #[test]
+    fn rejects_truncated_chunks() {
+        let data = encode_project(&sample_project()).unwrap();
+        for len in [13, 30, data.len() / 2, data.len() - 1] {
+            let msg = corrupt_message(decode_project(&data[..len]));
+            assert!(msg.contains("truncated"), "length {}: {}", len, msg);
+        }
+        // A length field pointing far past the end
+        let mut data = data;
+        data[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(corrupt_message(decode_project(&data)).contains("truncated"));
+    }
+
+    #[test]
+    fn rejects_newer_major_version() {
+        let mut data = encode_project(&sample_project()).unwrap();
+        data[8..10].copy_from_slice(&(VERSION_MAJOR + 1).to_le_bytes());
+        data[10..12].copy_from_slice(&7u16.to_le_bytes());
+        match decode_project(&data) {
+            Err(ProjectError::NewerVersion { major, minor }) => {
+                assert_eq!((major, minor), (VERSION_MAJOR + 1, 7))
+            }
+            _ => panic!("expected a newer version error"),
+        }
+    }
+
+    #[test]
+    fn rejects_missing_end() {
+        let data = encode_project(&sample_project()).unwrap();
+        // The END chunk is 16 bytes: tag, zero length, crc
+        let msg = corrupt_message(decode_project(&data[..data.len() - 16]));
+        assert!(msg.contains("truncated"), "{}", msg);
+    }
+
+    #[test]
+    fn skips_unknown_chunks_and_manifest_keys() {
+        let manifest = b"app_version = \"9.9\"\nwidth = 2\nheight = 1\nactive_layer = 0\n\
+                         future_setting = true\n\n[[layers]]\nname = \"Only\"\nvisible = true\n\
+                         locked = false\nopacity = 1.0\nblend_mode = \"Normal\"\nglow = 3\n";
+        let layer = png(2, 1);
+        let data = file_with(&[
+            (TAG_MANIFEST, manifest),
+            (*b"FUTR", b"something from a newer version"),
+            (TAG_LAYER, &layer),
+            (TAG_END, &[]),
+        ]);
+        let project = decode_project(&data).unwrap();
+        assert_eq!(project.layers.len(), 1);
+        assert_eq!(project.layers[0].name, "Only");
+        // Fields added after 1.0 get their defaults
+        assert_eq!(project.dpi, DEFAULT_DPI);
+        assert!(project.palette.is_empty());
+        assert!(project.selection.is_none());
+    }
+
+    #[test]
+    fn rejects_oversized_canvas() {
+        let manifest = format!(
+            "app_version = \"1\"\nwidth = {}\nheight = 1\nactive_layer = 0\n\n\
+             [[layers]]\nname = \"A\"\nvisible = true\nlocked = false\n\
+             opacity = 1.0\nblend_mode = \"Normal\"\n",
+            MAX_DOCUMENT_SIZE + 1
+        );
+        let data = file_with(&[
+            (TAG_MANIFEST, manifest.as_bytes()),
+            (TAG_LAYER, &png(1, 1)),
+            (TAG_END, &[]),
+        ]);
+        match decode_project(&data) {
+            Err(ProjectError::Manifest(msg)) => assert!(msg.contains("canvas size"), "{}", msg),
+            _ => panic!("expected the canvas size to be rejected"),
+        }
+    }
+
+    #[test]
+    fn rejects_layers_that_dont_match_the_manifest() {
+        let manifest = b"app_version = \"1\"\nwidth = 2\nheight = 2\nactive_layer = 0\n\n\
+                         [[layers]]\nname = \"A\"\nvisible = true\nlocked = false\n\
+                         opacity = 1.0\nblend_mode = \"Normal\"\n";
+        let wrong_size = png(3, 2);
+        let data = file_with(&[
+            (TAG_MANIFEST, manifest),
+            (TAG_LAYER, &wrong_size),
+            (TAG_END, &[]),
+        ]);
+        assert!(corrupt_message(decode_project(&data)).contains("expected 2x2"));
+        let data = file_with(&[(TAG_MANIFEST, manifest), (TAG_END, &[])]);
+        assert!(corrupt_message(decode_project(&data)).contains("layers"));
+    }
+
+    #[test]
+    fn no_selection_writes_no_chunk() {
+        let mut project = sample_project();
+        project.selection = None;
+        let data = encode_project(&project).unwrap();
+        assert!(!data.windows(4).any(|w| w == TAG_SELECTION));
+        assert!(decode_project(&data).unwrap().selection.is_none());
+    }
+
+    #[test]
+    fn rejects_selection_of_the_wrong_size() {
+        let manifest = b"app_version = \"1\"\nwidth = 2\nheight = 1\nactive_layer = 0\n\n\
+                         [[layers]]\nname = \"A\"\nvisible = true\nlocked = false\n\
+                         opacity = 1.0\nblend_mode = \"Normal\"\n";
+        let layer = png(2, 1);
+        let data = file_with(&[
+            (TAG_MANIFEST, manifest),
+            (TAG_LAYER, &layer),
+            (TAG_SELECTION, &png(3, 1)),
+            (TAG_END, &[]),
+        ]);
+        assert!(corrupt_message(decode_project(&data)).contains("selection"));
+    }
+}
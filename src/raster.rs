@@ -0,0 +1,421 @@
+use crate::commands::{PaintCommand, ShapeKind};
+use crate::layers::{self, BlendMode};
+use crate::text;
+use std::ops::Range;
+
+// Vertical samples per pixel row when antialiasing polygons
+const POLYGON_SUBSAMPLES: usize = 4;
+
+/// Turns a paint command into pixels, calling `plot(x, y, rgba)` for every pixel it touches
+/// inside a `width` x `height` canvas. Antialiased edges come out as reduced alpha.
+pub fn rasterize(
+    cmd: &PaintCommand,
+    width: u32,
+    height: u32,
+    mut plot: impl FnMut(u32, u32, [u8; 4]),
+) {
+    match cmd {
+        PaintCommand::DrawPixel { x, y, r, g, b, a } => {
+            if *x < width && *y < height {
+                plot(*x, *y, [*r, *g, *b, *a]);
+            }
+        }
+        PaintCommand::FillRect {
+            x,
+            y,
+            width: w,
+            height: h,
+            color,
+            antialias,
+        } => {
+            // Allow negative sizes (dragging up/left)
+            let (x0, x1) = (x.min(x + w), x.max(x + w));
+            let (y0, y1) = (y.min(y + h), y.max(y + h));
+            for py in pixel_range(y0, y1, height) {
+                let cov_y = span_coverage(py, y0, y1, *antialias);
+                if cov_y <= 0.0 {
+                    continue;
+                }
+                for px in pixel_range(x0, x1, width) {
+                    let coverage = cov_y * span_coverage(px, x0, x1, *antialias);
+                    plot_covered(&mut plot, px, py, *color, coverage);
+                }
+            }
+        }
+        PaintCommand::FillCircle {
+            cx,
+            cy,
+            radius,
+            color,
+            antialias,
+        } => {
+            for py in pixel_range(cy - radius - 1.0, cy + radius + 1.0, height) {
+                for px in pixel_range(cx - radius - 1.0, cx + radius + 1.0, width) {
+                    let dx = px as f64 + 0.5 - cx;
+                    let dy = py as f64 + 0.5 - cy;
+                    let dist = (dx * dx + dy * dy).sqrt();
+                    plot_covered(
+                        &mut plot,
+                        px,
+                        py,
+                        *color,
+                        edge_coverage(dist, *radius, *antialias),
+                    );
+                }
+            }
+        }
+        PaintCommand::DrawLine {
+            x1,
+            y1,
+            x2,
+            y2,
+            width: line_width,
+            color,
+            antialias,
+        } => {
+            let half = line_width / 2.0;
+            let pad = half + 1.0;
+            for py in pixel_range(y1.min(*y2) - pad, y1.max(*y2) + pad, height) {
+                for px in pixel_range(x1.min(*x2) - pad, x1.max(*x2) + pad, width) {
+                    let dist =
+                        dist_to_segment((px as f64 + 0.5, py as f64 + 0.5), (*x1, *y1), (*x2, *y2));
+                    plot_covered(
+                        &mut plot,
+                        px,
+                        py,
+                        *color,
+                        edge_coverage(dist, half, *antialias),
+                    );
+                }
+            }
+        }
+        PaintCommand::FillPolygon {
+            points,
+            color,
+            antialias,
+        } => fill_polygon(points, *color, *antialias, width, height, &mut plot),
+        PaintCommand::DrawSpan { x, y, len, color } => {
+            if *y < 0 || *y as u32 >= height {
+                return;
+            }
+            let start = (*x).max(0) as i64;
+            let end = (*x as i64 + *len as i64).min(width as i64);
+            for px in start..end {
+                plot(px as u32, *y as u32, *color);
+            }
+        }
+        PaintCommand::Stamp {
+            x,
+            y,
+            width: stamp_w,
+            height: stamp_h,
+            pixels,
+            opacity,
+        } => {
+            for j in 0..*stamp_h {
+                let py = *y as i64 + j as i64;
+                if py < 0 || py >= height as i64 {
+                    continue;
+                }
+                for i in 0..*stamp_w {
+                    let px = *x as i64 + i as i64;
+                    if px < 0 || px >= width as i64 {
+                        continue;
+                    }
+                    let s = (j as usize * *stamp_w as usize + i as usize) * 4;
+                    let Some(src) = pixels.get(s..s + 4) else {
+                        return;
+                    };
+                    let alpha = (src[3] as f32 * opacity).round().clamp(0.0, 255.0) as u8;
+                    if alpha > 0 {
+                        plot(px as u32, py as u32, [src[0], src[1], src[2], alpha]);
+                    }
+                }
+            }
+        }
+        PaintCommand::Dab {
+            x,
+            y,
+            size,
+            hardness,
+            angle,
+            roundness,
+            color,
+            alpha,
+            tip,
+        } => {
+            let radius = size / 2.0;
+            let roundness = roundness.clamp(0.01, 1.0);
+            let (sin, cos) = angle.to_radians().sin_cos();
+            for py in pixel_range(y - radius - 1.0, y + radius + 1.0, height) {
+                for px in pixel_range(x - radius - 1.0, x + radius + 1.0, width) {
+                    // Into the tip's own frame: undo the rotation, then the squash
+                    let (dx, dy) = (px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                    let lx = dx * cos + dy * sin;
+                    let ly = (-dx * sin + dy * cos) / roundness;
+
+                    let coverage = match tip {
+                        Some(tip) => tip.sample(
+                            (lx + radius) / (2.0 * radius),
+                            (ly + radius) / (2.0 * radius),
+                        ),
+                        None => dab_coverage((lx * lx + ly * ly).sqrt(), radius, *hardness),
+                    };
+                    plot_covered(
+                        &mut plot,
+                        px,
+                        py,
+                        [color[0], color[1], color[2], 255],
+                        coverage * alpha,
+                    );
+                }
+            }
+        }
+        PaintCommand::Shape {
+            kind,
+            start,
+            end,
+            width: stroke_width,
+            fill,
+            outline,
+            antialias,
+        } => {
+            let half = stroke_width.max(0.0) / 2.0;
+            let pad = half + 1.0;
+            let (min_x, max_x) = (start.0.min(end.0), start.0.max(end.0));
+            let (min_y, max_y) = (start.1.min(end.1), start.1.max(end.1));
+            for py in pixel_range(min_y - pad, max_y + pad, height) {
+                for px in pixel_range(min_x - pad, max_x + pad, width) {
+                    let p = (px as f64 + 0.5, py as f64 + 0.5);
+                    let dist = shape_distance(*kind, *start, *end, p);
+                    let coverage = |d: f64| {
+                        if *antialias {
+                            (0.5 - d).clamp(0.0, 1.0)
+                        } else if d <= 0.0 {
+                            1.0
+                        } else {
+                            0.0
+                        }
+                    };
+
+                    // Fill first, outline on top, merged into one pixel
+                    let mut color = [0u8; 4];
+                    if let Some(fill) = fill
+                        && *kind != ShapeKind::Line
+                    {
+                        color = with_coverage(*fill, coverage(dist));
+                    }
+                    if let Some(outline) = outline {
+                        let edge = with_coverage(*outline, coverage(dist.abs() - half));
+                        color = layers::blend_pixel(color, edge, 1.0, BlendMode::Normal);
+                    }
+                    if color[3] > 0 {
+                        plot(px, py, color);
+                    }
+                }
+            }
+        }
+        PaintCommand::Text {
+            x,
+            y,
+            text,
+            font,
+            style,
+            color,
+        } => text::render_text(
+            font,
+            text,
+            style,
+            (*x, *y),
+            (width, height),
+            |px, py, coverage| {
+                if px >= 0 && py >= 0 && px < width as i64 && py < height as i64 {
+                    plot_covered(&mut plot, px as u32, py as u32, *color, coverage as f64);
+                }
+            },
+        ),
+        PaintCommand::Gradient(gradient) => {
+            for py in 0..height {
+                for px in 0..width {
+                    let color = gradient.color_at(px, py);
+                    if color[3] > 0 {
+                        plot(px, py, color);
+                    }
+                }
+            }
+        }
+        // Not shapes: Document::apply_command handles these itself
+        PaintCommand::FloodFill { .. }
+        | PaintCommand::ClearStroke
+        | PaintCommand::CommitStroke { .. }
+        | PaintCommand::SetComposite { .. } => {}
+    }
+}
+
+/// Round tip falloff: solid out to `hardness * radius`, then a smooth ramp to the edge
+fn dab_coverage(dist: f64, radius: f64, hardness: f64) -> f64 {
+    let solid = radius * hardness.clamp(0.0, 1.0);
+    if radius - solid < 1.0 {
+        // Too thin to see a ramp; just antialias the edge
+        return edge_coverage(dist, radius, true);
+    }
+    let t = ((radius - dist) / (radius - solid)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn fill_polygon(
+    points: &[(f64, f64)],
+    color: [u8; 4],
+    antialias: bool,
+    width: u32,
+    height: u32,
+    plot: &mut impl FnMut(u32, u32, [u8; 4]),
+) {
+    if points.len() < 3 {
+        return;
+    }
+    let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+
+    let columns = pixel_range(min_x, max_x, width);
+    if columns.is_empty() {
+        return;
+    }
+    let samples = if antialias { POLYGON_SUBSAMPLES } else { 1 };
+    let mut row_coverage = vec![0.0f64; columns.len()];
+    let mut crossings = Vec::new();
+
+    for py in pixel_range(min_y, max_y, height) {
+        row_coverage.fill(0.0);
+
+        for sample in 0..samples {
+            let sy = py as f64 + (sample as f64 + 0.5) / samples as f64;
+            crossings.clear();
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                if (a.1 <= sy && sy < b.1) || (b.1 <= sy && sy < a.1) {
+                    crossings.push(a.0 + (sy - a.1) * (b.0 - a.0) / (b.1 - a.1));
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+
+            // Even-odd: every pair of crossings is an inside span
+            for pair in crossings.chunks_exact(2) {
+                for px in pixel_range(pair[0], pair[1], width) {
+                    let coverage = span_coverage(px, pair[0], pair[1], antialias);
+                    row_coverage[(px - columns.start) as usize] += coverage / samples as f64;
+                }
+            }
+        }
+
+        for (i, coverage) in row_coverage.iter().enumerate() {
+            plot_covered(plot, columns.start + i as u32, py, color, *coverage);
+        }
+    }
+}
+
+/// Pixels overlapping `min..max` along one axis, clipped to `0..limit`
+fn pixel_range(min: f64, max: f64, limit: u32) -> Range<u32> {
+    let start = min.floor().max(0.0).min(limit as f64) as u32;
+    let end = max.ceil().max(0.0).min(limit as f64) as u32;
+    start..end
+}
+
+/// How much of pixel `p` (which spans p..p+1) lies inside `min..max`.
+/// Without antialiasing a pixel is either in (center inside) or out.
+fn span_coverage(p: u32, min: f64, max: f64, antialias: bool) -> f64 {
+    let (p0, p1) = (p as f64, p as f64 + 1.0);
+    if antialias {
+        (p1.min(max) - p0.max(min)).clamp(0.0, 1.0)
+    } else if (min..max).contains(&(p0 + 0.5)) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Coverage of a pixel whose center is `dist` away from a shape with the given radius
+fn edge_coverage(dist: f64, radius: f64, antialias: bool) -> f64 {
+    if antialias {
+        (radius + 0.5 - dist).clamp(0.0, 1.0)
+    } else if dist <= radius {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Signed distance from `p` to the edge of a shape (negative inside).
+/// Lines have no inside: it's the plain distance to the segment.
+fn shape_distance(kind: ShapeKind, start: (f64, f64), end: (f64, f64), p: (f64, f64)) -> f64 {
+    let center = ((start.0 + end.0) / 2.0, (start.1 + end.1) / 2.0);
+    let half = ((end.0 - start.0).abs() / 2.0, (end.1 - start.1).abs() / 2.0);
+    let local = ((p.0 - center.0).abs(), (p.1 - center.1).abs());
+    match kind {
+        ShapeKind::Line => dist_to_segment(p, start, end),
+        ShapeKind::Rect => rect_distance(local, half, 0.0),
+        ShapeKind::RoundedRect { radius } => {
+            rect_distance(local, half, radius.clamp(0.0, half.0.min(half.1)))
+        }
+        ShapeKind::Ellipse => {
+            if half.0 < 1e-6 || half.1 < 1e-6 {
+                // Flat ellipse: just a line
+                return rect_distance(local, half, 0.0);
+            }
+            // Cheap approximation of the true ellipse distance (good near the edge)
+            let k0 = ((local.0 / half.0).powi(2) + (local.1 / half.1).powi(2)).sqrt();
+            let k1 = ((local.0 / (half.0 * half.0)).powi(2)
+                + (local.1 / (half.1 * half.1)).powi(2))
+            .sqrt();
+            if k1 == 0.0 {
+                -half.0.min(half.1)
+            } else {
+                k0 * (k0 - 1.0) / k1
+            }
+        }
+    }
+}
+
+/// Signed distance to a rectangle with rounded corners; `p` is relative to its center
+/// (folded into the first quadrant) and `half` is half its size
+fn rect_distance(p: (f64, f64), half: (f64, f64), radius: f64) -> f64 {
+    let q = (p.0 - half.0 + radius, p.1 - half.1 + radius);
+    let outside = (q.0.max(0.0).powi(2) + q.1.max(0.0).powi(2)).sqrt();
+    outside + q.0.max(q.1).min(0.0) - radius
+}
+
+fn with_coverage(color: [u8; 4], coverage: f64) -> [u8; 4] {
+    [
+        color[0],
+        color[1],
+        color[2],
+        (color[3] as f64 * coverage).round() as u8,
+    ]
+}
+
+fn dist_to_segment(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len_sq).clamp(0.0, 1.0)
+    };
+    let (proj_x, proj_y) = (a.0 + t * dx, a.1 + t * dy);
+    ((p.0 - proj_x).powi(2) + (p.1 - proj_y).powi(2)).sqrt()
+}
+
+fn plot_covered(
+    plot: &mut impl FnMut(u32, u32, [u8; 4]),
+    x: u32,
+    y: u32,
+    color: [u8; 4],
+    coverage: f64,
+) {
+    let alpha = (color[3] as f64 * coverage).round() as u8;
+    if alpha > 0 {
+        plot(x, y, [color[0], color[1], color[2], alpha]);
+    }
+}
@@ -1,13 +1,74 @@
-use crate::commands::PaintCommand;
+use crate::brush::{BrushEngine, BrushSettings};
+use crate::colors::{self, ColorPair, ColorTarget};
+use crate::commands::{PaintCommand, ShapeKind};
+use crate::document::Document;
+use crate::gradient::{Gradient, GradientShape, GradientStop, RepeatMode};
+use crate::layers::CompositeOp;
 use crate::packages::LoadedTool;
+use crate::text::{
+    DEFAULT_FAMILY, FontLibrary, MAX_TEXT_SIZE, MIN_TEXT_SIZE, TextAlign, TextStyle,
+};
+use mlua::Variadic;
 use mlua::prelude::*;
 use std::cell::RefCell; // Needed for borrowing UI
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use winit::keyboard::ModifiersState;
+
+// (x1, y1, x2, y2, width, r, g, b, [a], [aa])
+type DrawLineArgs = (
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    u8,
+    u8,
+    u8,
+    Option<u8>,
+    Option<bool>,
+);
 
 pub struct LuaEngine {
     lua: Lua,
+    fonts: Rc<FontLibrary>,
     current_package_path: PathBuf,
+    brush: BrushEngine,
+    // True until the first process_input call of a stroke
+    stroke_start: bool,
+}
+
+/// What tool callbacks can see besides the event itself
+pub struct ToolContext<'a> {
+    pub document: &'a Document,
+    pub colors: &'a ColorPair,
+    // Color of the current stroke (secondary for the right mouse button)
+    pub paint_with: ColorTarget,
+    pub modifiers: ModifiersState,
+}
+
+/// Becomes the `event` table of on_press, on_drag, on_key, ...
+#[derive(Clone, Debug, Default)]
+pub struct ToolEvent {
+    // Canvas position of the cursor
+    pub x: f64,
+    pub y: f64,
+    // Where the previous drag/hover event was
+    pub prev: Option<(f64, f64)>,
+    // Button held down, if any
+    pub button: Option<ColorTarget>,
+    // 0..1; always 1 for the mouse
+    pub pressure: f32,
+    // Pen altitude in radians (pi/2 = upright), where the platform reports it
+    pub tilt: Option<f64>,
+    // Seconds since the app started
+    pub time: f64,
+    // on_key only: key name as in shortcuts ("a", "Enter", "Up") and whether it went down
+    pub key: Option<String>,
+    pub pressed: bool,
+    // on_scroll only: wheel steps
+    pub scroll: (f64, f64),
 }
 
 #[derive(Clone)]
@@ -17,43 +78,69 @@ pub enum CursorType {
 }
 
 impl LuaEngine {
-    pub fn new() -> Self {
+    pub fn new(fonts: Rc<FontLibrary>) -> Self {
         Self {
             lua: Lua::new(),
+            fonts,
             current_package_path: PathBuf::new(),
+            brush: BrushEngine::new(),
+            stroke_start: false,
         }
     }
 
+    /// Resets per-stroke state (brush dab spacing) when the mouse goes down
+    pub fn begin_stroke(&mut self) {
+        self.brush.begin_stroke();
+        self.stroke_start = true;
+    }
+
     pub fn load_tool(&mut self, tool: &LoadedTool) {
         self.current_package_path = tool.package_path.clone();
         let tool_table: LuaTable = self
             .lua
             .load(&tool.script_content)
             .eval()
-            .expect(&format!("Failed to load tool: {}", tool.name));
+            .unwrap_or_else(|_| panic!("Failed to load tool: {}", tool.name));
         self.lua.globals().set("current_tool", tool_table).unwrap();
     }
 
     pub fn get_current_cursor(&self) -> CursorType {
-        if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool") {
-            if let Ok(cursor_val) = tool.get::<_, String>("cursor") {
-                if cursor_val == "circle" {
-                    return CursorType::SystemCircle;
-                } else {
-                    let full_path = self.current_package_path.join(cursor_val);
-                    return CursorType::CustomImage(full_path.to_string_lossy().to_string());
-                }
+        if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool")
+            && let Ok(cursor_val) = tool.get::<_, String>("cursor")
+        {
+            if cursor_val == "circle" {
+                return CursorType::SystemCircle;
+            } else {
+                let full_path = self.current_package_path.join(cursor_val);
+                return CursorType::CustomImage(full_path.to_string_lossy().to_string());
             }
         }
         CursorType::SystemCircle
     }
 
-    // Helper to read "size" from Lua so Rust can draw the cursor ring
+    /// Whether strokes go through the stabilizer; tools that want the raw cursor
+    /// (shapes, fills) set `Tool.stabilize = false`
+    pub fn wants_stabilizer(&self) -> bool {
+        self.lua
+            .globals()
+            .get::<_, LuaTable>("current_tool")
+            .and_then(|tool| tool.get::<_, Option<bool>>("stabilize"))
+            .ok()
+            .flatten()
+            .unwrap_or(true)
+    }
+
+    // Helper to read "size" (or "brush.size") from Lua so Rust can draw the cursor ring
     pub fn get_tool_size(&self) -> f32 {
         if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool") {
             if let Ok(size) = tool.get::<_, f32>("size") {
                 return size;
             }
+            if let Ok(brush) = tool.get::<_, LuaTable>("brush")
+                && let Ok(size) = brush.get::<_, f32>("size")
+            {
+                return size;
+            }
         }
         10.0 // Default fallback
     }
@@ -63,7 +150,7 @@ impl LuaEngine {
         self.lua
             .scope(|scope| {
                 // Fix: Wrap the UI reference in Rc + RefCell so we can share it
-                let ui_handle = Arc::new(RefCell::new(ui));
+                let ui_handle = Rc::new(RefCell::new(ui));
 
                 let api = self.lua.create_table()?;
 
@@ -114,6 +201,24 @@ impl LuaEngine {
                     })?;
                 api.set("checkbox", checkbox)?;
 
+                // choice = ui.combo("Label", choice, {"first", "second", ...})
+                // (choice is the 1-based index of the selected option)
+                let ui = ui_handle.clone();
+                let combo = scope.create_function_mut(
+                    move |_, (label, mut choice, options): (String, usize, Vec<String>)| {
+                        let selected = options.get(choice.wrapping_sub(1)).cloned();
+                        egui::ComboBox::from_label(label)
+                            .selected_text(selected.unwrap_or_default())
+                            .show_ui(*ui.borrow_mut(), |ui| {
+                                for (i, option) in options.iter().enumerate() {
+                                    ui.selectable_value(&mut choice, i + 1, option);
+                                }
+                            });
+                        Ok(choice)
+                    },
+                )?;
+                api.set("combo", combo)?;
+
                 // clicked = ui.button("Label")
                 let ui = ui_handle.clone();
                 let button = scope.create_function_mut(move |_, label: String| {
@@ -122,71 +227,1106 @@ impl LuaEngine {
                 api.set("button", button)?;
 
                 // Call Tool.on_ui(api)
-                if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool") {
-                    if let Ok(on_ui) = tool.get::<_, LuaFunction>("on_ui") {
-                        let _: () = on_ui.call(api)?;
-                    }
+                if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool")
+                    && let Ok(on_ui) = tool.get::<_, LuaFunction>("on_ui")
+                {
+                    let _: () = on_ui.call(api)?;
                 }
                 Ok(())
             })
             .unwrap_or_else(|e| println!("Lua UI Error: {:?}", e));
     }
 
-    // --- UPDATED: No longer takes size/aa arguments ---
-    // UPDATE this function signature
+    // Points are canvas-space floats: (10.5, 3.25) is inside pixel (10, 3),
+    // and they can be negative or past the edge when the stroke leaves the image.
+    // `pressure` is the pen pressure (0..1) at start and end.
     pub fn process_input(
-        &self,
-        start_x: u32,
-        start_y: u32,
-        end_x: u32,
-        end_y: u32,
-        color: [f32; 3],
+        &mut self,
+        start: (f64, f64),
+        end: (f64, f64),
+        pressure: (f32, f32),
+        ctx: &ToolContext,
     ) -> Vec<PaintCommand> {
         let commands = Arc::new(Mutex::new(Vec::new()));
-        let commands_clone = commands.clone();
+        let api = create_api(&self.lua, &self.fonts, self.stroke_start, &commands, ctx);
+        self.stroke_start = false;
+        let [r, g, b, a] = colors::to_rgba8(ctx.colors.get(ctx.paint_with));
 
-        let api = self.lua.create_table().unwrap();
+        self.push_composite(&commands);
 
-        let func = self
-            .lua
-            .create_function_mut(
-                move |_, (x, y, r, g, b, a): (i32, i32, u8, u8, u8, Option<u8>)| {
-                    if x >= 0 && y >= 0 {
-                        let alpha = a.unwrap_or(255);
-                        commands_clone
-                            .lock()
-                            .unwrap()
-                            .push(PaintCommand::DrawPixel {
-                                x: x as u32,
-                                y: y as u32,
-                                r,
-                                g,
-                                b,
-                                a: alpha,
-                            });
-                    }
-                    Ok(())
-                },
+        // Tools with a `brush` table get dabs from the Rust brush engine
+        if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool")
+            && let Ok(brush) = tool.get::<_, LuaTable>("brush")
+        {
+            let mut settings =
+                read_brush_settings(&brush, &self.current_package_path, &mut self.brush);
+            // A translucent color is just a less opaque brush
+            settings.opacity *= a as f64 / 255.0;
+            let dabs = self
+                .brush
+                .stroke(&settings, start, end, pressure, [r, g, b]);
+            commands.lock().unwrap().extend(dabs);
+        }
+
+        if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool")
+            && let Ok(on_paint) = tool.get::<_, LuaFunction>("on_paint")
+        {
+            // PASS BOTH COORDINATES TO LUA
+            // (api, start_x, start_y, end_x, end_y, r, g, b, a, start_pressure, end_pressure)
+            let (p1, p2) = pressure;
+            let args = (
+                api.clone(),
+                start.0,
+                start.1,
+                end.0,
+                end.1,
+                r,
+                g,
+                b,
+                a,
+                p1,
+                p2,
+            );
+            let result: LuaResult<()> =
+                call_with_document(&self.lua, on_paint, api, ctx.document, args);
+            if let Err(e) = result {
+                println!("Lua Runtime Error: {:?}", e);
+            }
+        }
+
+        std::mem::take(&mut *commands.lock().unwrap())
+    }
+
+    /// Calls `Tool.<callback>(api, event)` if the current tool has it.
+    /// Returns what it painted, and whether it returned true to say the event was handled
+    /// (so keys and the wheel don't also trigger shortcuts or zoom).
+    pub fn dispatch(
+        &mut self,
+        callback: &str,
+        event: &ToolEvent,
+        ctx: &ToolContext,
+    ) -> (Vec<PaintCommand>, bool) {
+        let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool") else {
+            return (Vec::new(), false);
+        };
+        let Ok(func) = tool.get::<_, LuaFunction>(callback) else {
+            return (Vec::new(), false);
+        };
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let api = create_api(&self.lua, &self.fonts, self.stroke_start, &commands, ctx);
+        self.push_composite(&commands);
+        let result = event_table(&self.lua, event, ctx.modifiers).and_then(|event| {
+            call_with_document::<_, Option<bool>>(
+                &self.lua,
+                func,
+                api.clone(),
+                ctx.document,
+                (api, event),
             )
-            .unwrap();
-        api.set("draw_pixel", func).unwrap();
+        });
+        let handled = match result {
+            Ok(handled) => handled.unwrap_or(false),
+            Err(e) => {
+                println!("Lua Runtime Error in {}: {:?}", callback, e);
+                false
+            }
+        };
+        (std::mem::take(&mut *commands.lock().unwrap()), handled)
+    }
 
-        let r = (color[0] * 255.0) as u8;
-        let g = (color[1] * 255.0) as u8;
-        let b = (color[2] * 255.0) as u8;
+    /// Runs a Lua chunk in the engine's state, for tests to read what a tool recorded
+    #[cfg(test)]
+    pub fn eval<T: for<'lua> FromLuaMulti<'lua>>(&self, chunk: &str) -> T {
+        self.lua.load(chunk).eval().unwrap()
+    }
 
-        if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool") {
-            if let Ok(on_paint) = tool.get::<_, LuaFunction>("on_paint") {
-                // PASS BOTH COORDINATES TO LUA
-                // (api, start_x, start_y, end_x, end_y, r, g, b)
-                if let Err(e) =
-                    on_paint.call::<_, ()>((api, start_x, start_y, end_x, end_y, r, g, b))
-                {
-                    println!("Lua Runtime Error: {:?}", e);
+    // `Tool.composite = "erase"` etc. applies to everything the tool paints
+    fn push_composite(&self, commands: &Arc<Mutex<Vec<PaintCommand>>>) {
+        if let Ok(tool) = self.lua.globals().get::<_, LuaTable>("current_tool")
+            && let Ok(name) = tool.get::<_, String>("composite")
+        {
+            match CompositeOp::from_name(&name) {
+                Some(op) => commands
+                    .lock()
+                    .unwrap()
+                    .push(PaintCommand::SetComposite { op }),
+                None => println!("Unknown composite operation '{}'", name),
+            }
+        }
+    }
+}
+
+/// The `api` table every tool callback gets: drawing functions (which queue up
+/// `commands`), the document, colors and modifiers.
+fn create_api<'lua>(
+    lua: &'lua Lua,
+    fonts: &Rc<FontLibrary>,
+    stroke_start: bool,
+    commands: &Arc<Mutex<Vec<PaintCommand>>>,
+    ctx: &ToolContext,
+) -> LuaTable<'lua> {
+    let commands_clone = commands.clone();
+
+    let api = lua.create_table().unwrap();
+
+    let func = lua
+        .create_function_mut(
+            move |_, (x, y, r, g, b, a): (i32, i32, u8, u8, u8, Option<u8>)| {
+                if x >= 0 && y >= 0 {
+                    let alpha = a.unwrap_or(255);
+                    commands_clone
+                        .lock()
+                        .unwrap()
+                        .push(PaintCommand::DrawPixel {
+                            x: x as u32,
+                            y: y as u32,
+                            r,
+                            g,
+                            b,
+                            a: alpha,
+                        });
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("draw_pixel", func).unwrap();
+
+    // --- Native shapes: one command each instead of a pixel loop in Lua ---
+
+    // api.fill_rect(x, y, w, h, r, g, b, [a], [aa])
+    let sink = commands.clone();
+    let fill_rect = lua
+        .create_function(
+            move |_,
+                  (x, y, width, height, r, g, b, a, aa): (
+                f64,
+                f64,
+                f64,
+                f64,
+                u8,
+                u8,
+                u8,
+                Option<u8>,
+                Option<bool>,
+            )| {
+                sink.lock().unwrap().push(PaintCommand::FillRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                    color: [r, g, b, a.unwrap_or(255)],
+                    antialias: aa.unwrap_or(true),
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("fill_rect", fill_rect).unwrap();
+
+    // api.fill_circle(cx, cy, radius, r, g, b, [a], [aa])
+    let sink = commands.clone();
+    let fill_circle = lua
+        .create_function(
+            move |_,
+                  (cx, cy, radius, r, g, b, a, aa): (
+                f64,
+                f64,
+                f64,
+                u8,
+                u8,
+                u8,
+                Option<u8>,
+                Option<bool>,
+            )| {
+                sink.lock().unwrap().push(PaintCommand::FillCircle {
+                    cx,
+                    cy,
+                    radius,
+                    color: [r, g, b, a.unwrap_or(255)],
+                    antialias: aa.unwrap_or(true),
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("fill_circle", fill_circle).unwrap();
+
+    // api.draw_line(x1, y1, x2, y2, width, r, g, b, [a], [aa])
+    let sink = commands.clone();
+    let draw_line = lua
+        .create_function(
+            move |_, (x1, y1, x2, y2, width, r, g, b, a, aa): DrawLineArgs| {
+                sink.lock().unwrap().push(PaintCommand::DrawLine {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    width,
+                    color: [r, g, b, a.unwrap_or(255)],
+                    antialias: aa.unwrap_or(true),
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("draw_line", draw_line).unwrap();
+
+    // api.fill_polygon({x1, y1, x2, y2, ...}, r, g, b, [a], [aa])
+    let sink = commands.clone();
+    let fill_polygon = lua
+        .create_function(
+            move |_, (coords, r, g, b, a, aa): (Vec<f64>, u8, u8, u8, Option<u8>, Option<bool>)| {
+                if coords.len() % 2 != 0 {
+                    return Err(LuaError::RuntimeError(
+                        "fill_polygon expects a flat list of x, y pairs".to_string(),
+                    ));
+                }
+                let points = coords.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+                sink.lock().unwrap().push(PaintCommand::FillPolygon {
+                    points,
+                    color: [r, g, b, a.unwrap_or(255)],
+                    antialias: aa.unwrap_or(true),
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("fill_polygon", fill_polygon).unwrap();
+
+    // api.draw_span(x, y, len, r, g, b, [a])
+    let sink = commands.clone();
+    let draw_span = lua
+        .create_function(
+            move |_, (x, y, len, r, g, b, a): (i32, i32, u32, u8, u8, u8, Option<u8>)| {
+                sink.lock().unwrap().push(PaintCommand::DrawSpan {
+                    x,
+                    y,
+                    len,
+                    color: [r, g, b, a.unwrap_or(255)],
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("draw_span", draw_span).unwrap();
+
+    // api.stamp(x, y, w, h, rgba, [opacity]) where rgba is a byte string or a table of numbers
+    let sink = commands.clone();
+    let stamp = lua
+        .create_function(
+            move |_,
+                  (x, y, width, height, data, opacity): (
+                i32,
+                i32,
+                u32,
+                u32,
+                LuaValue,
+                Option<f32>,
+            )| {
+                let pixels = match data {
+                    LuaValue::String(s) => s.as_bytes().to_vec(),
+                    LuaValue::Table(t) => {
+                        t.sequence_values::<u8>().collect::<LuaResult<Vec<u8>>>()?
+                    }
+                    _ => {
+                        return Err(LuaError::RuntimeError(
+                            "stamp expects RGBA data as a string or table".to_string(),
+                        ));
+                    }
+                };
+                // In u64 so huge sizes can't wrap around to match a short buffer
+                let expected = width as u64 * height as u64 * 4;
+                if pixels.len() as u64 != expected {
+                    return Err(LuaError::RuntimeError(format!(
+                        "stamp expects {} bytes of RGBA data, got {}",
+                        expected,
+                        pixels.len()
+                    )));
+                }
+                sink.lock().unwrap().push(PaintCommand::Stamp {
+                    x,
+                    y,
+                    width,
+                    height,
+                    pixels,
+                    opacity: opacity.unwrap_or(1.0),
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("stamp", stamp).unwrap();
+
+    // api.flood_fill(x, y, {r, g, b, [a]}, [tolerance], [contiguous], [aa])
+    let sink = commands.clone();
+    let flood_fill = lua
+        .create_function(
+            move |_,
+                  (x, y, color, tolerance, contiguous, aa): (
+                f64,
+                f64,
+                Vec<u8>,
+                Option<u8>,
+                Option<bool>,
+                Option<bool>,
+            )| {
+                if color.len() < 3 {
+                    return Err(LuaError::RuntimeError(
+                        "flood_fill expects a color table {r, g, b, [a]}".to_string(),
+                    ));
+                }
+                if x >= 0.0 && y >= 0.0 {
+                    sink.lock().unwrap().push(PaintCommand::FloodFill {
+                        x: x as u32,
+                        y: y as u32,
+                        color: [color[0], color[1], color[2], *color.get(3).unwrap_or(&255)],
+                        tolerance: tolerance.unwrap_or(32),
+                        contiguous: contiguous.unwrap_or(true),
+                        antialias: aa.unwrap_or(true),
+                    });
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("flood_fill", flood_fill).unwrap();
+
+    // api.draw_text(x, y, text, {font = "Ubuntu", size = 24, bold = false,
+    //               italic = false, align = "left", line_spacing = 1.0,
+    //               color = {r, g, b, [a]}, antialias = true})
+    // All options are optional; the defaults are shown (font is DEFAULT_FAMILY, and
+    // size is clamped to the text panel's range).
+    let sink = commands.clone();
+    let fonts = fonts.clone();
+    let paint_color = colors::to_rgba8(ctx.colors.get(ctx.paint_with));
+    let draw_text = lua
+        .create_function(
+            move |_, (x, y, text, options): (f64, f64, String, Option<LuaTable>)| {
+                let mut family = DEFAULT_FAMILY.to_string();
+                let (mut bold, mut italic) = (false, false);
+                let mut style = TextStyle {
+                    size: 24.0,
+                    align: TextAlign::Left,
+                    line_spacing: 1.0,
+                    fake_bold: false,
+                    fake_italic: false,
+                    antialias: true,
+                };
+                let mut color = paint_color;
+                if let Some(o) = &options {
+                    family = o.get::<_, Option<String>>("font")?.unwrap_or(family);
+                    bold = o.get::<_, Option<bool>>("bold")?.unwrap_or(false);
+                    italic = o.get::<_, Option<bool>>("italic")?.unwrap_or(false);
+                    style.size = o
+                        .get::<_, Option<f32>>("size")?
+                        .unwrap_or(style.size)
+                        .clamp(MIN_TEXT_SIZE, MAX_TEXT_SIZE);
+                    style.line_spacing = o
+                        .get::<_, Option<f32>>("line_spacing")?
+                        .unwrap_or(style.line_spacing);
+                    style.antialias = o.get::<_, Option<bool>>("antialias")?.unwrap_or(true);
+                    if let Some(name) = o.get::<_, Option<String>>("align")? {
+                        style.align = TextAlign::from_name(&name).ok_or_else(|| {
+                            LuaError::RuntimeError(format!("unknown alignment '{}'", name))
+                        })?;
+                    }
+                    color = read_color(o.get("color")?)?.unwrap_or(color);
+                }
+                let (font, fake_bold, fake_italic) = fonts
+                    .resolve(&family, bold, italic)
+                    .ok_or_else(|| LuaError::RuntimeError(format!("unknown font '{}'", family)))?;
+                style.fake_bold = fake_bold;
+                style.fake_italic = fake_italic;
+                sink.lock().unwrap().push(PaintCommand::Text {
+                    x,
+                    y,
+                    text,
+                    font,
+                    style,
+                    color,
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("draw_text", draw_text).unwrap();
+
+    // api.clear_stroke(): drop what this stroke painted so far (for live previews)
+    let sink = commands.clone();
+    let clear_stroke = lua
+        .create_function(move |_, ()| {
+            sink.lock().unwrap().push(PaintCommand::ClearStroke);
+            Ok(())
+        })
+        .unwrap();
+    api.set("clear_stroke", clear_stroke).unwrap();
+
+    // api.commit([label]): bake what has been painted so far into the layer as one undo
+    // step, for tools that paint outside of a stroke (e.g. from on_key)
+    let sink = commands.clone();
+    let commit = lua
+        .create_function(move |_, label: Option<String>| {
+            sink.lock().unwrap().push(PaintCommand::CommitStroke {
+                label: label.unwrap_or_else(|| "Tool".to_string()),
+            });
+            Ok(())
+        })
+        .unwrap();
+    api.set("commit", commit).unwrap();
+
+    // api.fill_gradient(x1, y1, x2, y2, {{pos, r, g, b, [a]}, ...},
+    //                   [{shape = "linear", repeat_mode = "none", dither = true}])
+    let sink = commands.clone();
+    let fill_gradient = lua
+        .create_function(
+            move |_,
+                  (x1, y1, x2, y2, stops, options): (
+                f64,
+                f64,
+                f64,
+                f64,
+                Vec<Vec<f64>>,
+                Option<LuaTable>,
+            )| {
+                let gradient = read_gradient((x1, y1), (x2, y2), stops, options)?;
+                sink.lock().unwrap().push(PaintCommand::Gradient(gradient));
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("fill_gradient", fill_gradient).unwrap();
+
+    // api.set_composite("over" | "erase" | "replace" | "behind")
+    let sink = commands.clone();
+    let set_composite = lua
+        .create_function(move |_, name: String| {
+            let op = CompositeOp::from_name(&name).ok_or_else(|| {
+                LuaError::RuntimeError(format!("unknown composite operation '{}'", name))
+            })?;
+            sink.lock().unwrap().push(PaintCommand::SetComposite { op });
+            Ok(())
+        })
+        .unwrap();
+    api.set("set_composite", set_composite).unwrap();
+
+    // api.draw_shape("line" | "rect" | "ellipse" | "rounded_rect", x1, y1, x2, y2,
+    //                {width = 1, fill = {r, g, b, [a]}, outline = {r, g, b, [a]},
+    //                 radius = 0, antialias = true})
+    let sink = commands.clone();
+    let draw_shape = lua
+        .create_function(
+            move |_,
+                  (kind, x1, y1, x2, y2, options): (
+                String,
+                f64,
+                f64,
+                f64,
+                f64,
+                Option<LuaTable>,
+            )| {
+                let radius = match &options {
+                    Some(o) => o.get::<_, Option<f64>>("radius")?.unwrap_or(0.0),
+                    None => 0.0,
+                };
+                let kind = match kind.as_str() {
+                    "line" => ShapeKind::Line,
+                    "rect" => ShapeKind::Rect,
+                    "ellipse" => ShapeKind::Ellipse,
+                    "rounded_rect" => ShapeKind::RoundedRect { radius },
+                    _ => {
+                        return Err(LuaError::RuntimeError(format!("unknown shape '{}'", kind)));
+                    }
+                };
+                let (mut width, mut fill, mut outline, mut antialias) =
+                    (1.0, None, Some([0, 0, 0, 255]), true);
+                if let Some(o) = &options {
+                    width = o.get::<_, Option<f64>>("width")?.unwrap_or(width);
+                    fill = read_color(o.get("fill")?)?;
+                    outline = read_color(o.get("outline")?)?;
+                    antialias = o.get::<_, Option<bool>>("antialias")?.unwrap_or(true);
                 }
+                sink.lock().unwrap().push(PaintCommand::Shape {
+                    kind,
+                    start: (x1, y1),
+                    end: (x2, y2),
+                    width,
+                    fill,
+                    outline,
+                    antialias,
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    api.set("draw_shape", draw_shape).unwrap();
+
+    // The document, read-only
+    let document = ctx.document;
+    api.set("width", document.width).unwrap();
+    api.set("height", document.height).unwrap();
+    api.set("has_selection", document.selection.is_active())
+        .unwrap();
+    let active = &document.layers[document.active_layer];
+    let layer = lua.create_table().unwrap();
+    // 1-based like everything else in Lua
+    layer.set("index", document.active_layer + 1).unwrap();
+    layer.set("count", document.layers.len()).unwrap();
+    layer.set("name", active.name.clone()).unwrap();
+    layer.set("opacity", active.opacity).unwrap();
+    layer.set("visible", active.visible).unwrap();
+    layer.set("locked", active.locked).unwrap();
+    layer.set("blend_mode", active.blend_mode.name()).unwrap();
+    api.set("layer", layer).unwrap();
+
+    // Lets click-once tools (like the bucket) ignore the rest of the drag
+    api.set("stroke_start", stroke_start).unwrap();
+    // Held modifier keys (e.g. Shift to constrain shapes)
+    api.set("shift", ctx.modifiers.shift_key()).unwrap();
+    api.set("ctrl", ctx.modifiers.control_key()).unwrap();
+    api.set("alt", ctx.modifiers.alt_key()).unwrap();
+
+    // Same as the r, g, b, a passed to on_paint, but ready to hand to `color = ...` options
+    api.set("color", paint_color).unwrap();
+    // Both colors, whichever button is down, e.g. for two-color gradients
+    api.set("primary", colors::to_rgba8(ctx.colors.primary))
+        .unwrap();
+    api.set("secondary", colors::to_rgba8(ctx.colors.secondary))
+        .unwrap();
+    api.set("button", button_name(ctx.paint_with)).unwrap();
+
+    api
+}
+
+fn button_name(button: ColorTarget) -> &'static str {
+    match button {
+        ColorTarget::Primary => "left",
+        ColorTarget::Secondary => "right",
+    }
+}
+
+/// The `event` table passed to tool callbacks
+fn event_table<'lua>(
+    lua: &'lua Lua,
+    event: &ToolEvent,
+    modifiers: ModifiersState,
+) -> LuaResult<LuaTable<'lua>> {
+    let table = lua.create_table()?;
+    table.set("x", event.x)?;
+    table.set("y", event.y)?;
+    if let Some((x, y)) = event.prev {
+        table.set("prev_x", x)?;
+        table.set("prev_y", y)?;
+    }
+    table.set("button", event.button.map(button_name))?;
+    table.set("shift", modifiers.shift_key())?;
+    table.set("ctrl", modifiers.control_key())?;
+    table.set("alt", modifiers.alt_key())?;
+    table.set("pressure", event.pressure)?;
+    table.set("tilt", event.tilt)?;
+    table.set("time", event.time)?;
+    if let Some(key) = &event.key {
+        table.set("key", key.as_str())?;
+        table.set("pressed", event.pressed)?;
+    }
+    if event.scroll != (0.0, 0.0) {
+        table.set("scroll_x", event.scroll.0)?;
+        table.set("scroll_y", event.scroll.1)?;
+    }
+    Ok(table)
+}
+
+/// Calls a tool function with `api` able to read `document` for the duration of the call
+fn call_with_document<'lua, A, R>(
+    lua: &'lua Lua,
+    func: LuaFunction<'lua>,
+    api: LuaTable<'lua>,
+    document: &Document,
+    args: A,
+) -> LuaResult<R>
+where
+    A: IntoLuaMulti<'lua>,
+    R: FromLuaMulti<'lua>,
+{
+    lua.scope(|scope| {
+        // Reads see the image as it was before this stroke; the merged variants
+        // look at all visible layers instead of just the active one
+
+        // r, g, b, a = api.get_pixel(x, y, [merged]) (nothing outside the canvas)
+        // Exact values (like get_region), not the eyedropper's average
+        let get_pixel = scope.create_function(|_, (x, y, merged): (f64, f64, Option<bool>)| {
+            let pixel = (x >= 0.0 && y >= 0.0)
+                .then(|| document.pixel(x as u32, y as u32, merged.unwrap_or(false)))
+                .flatten();
+            Ok(Variadic::from_iter(pixel.into_iter().flatten()))
+        })?;
+        api.set("get_pixel", get_pixel)?;
+
+        // bytes = api.get_region(x, y, w, h, [merged]): RGBA rows as a string
+        // (pixel i of row j starts at byte (j * w + i) * 4 + 1, see string.byte)
+        let get_region = scope.create_function(
+            |lua, (x, y, width, height, merged): (i64, i64, u32, u32, Option<bool>)| {
+                check_region_size(document, width, height)?;
+                let bytes = document.copy_region(x, y, width, height, merged.unwrap_or(false));
+                lua.create_string(bytes)
+            },
+        )?;
+        api.set("get_region", get_region)?;
+
+        // coverage = api.get_selection(x, y): 0..255, 255 everywhere without a selection
+        let get_selection = scope.create_function(|_, (x, y): (f64, f64)| {
+            let inside =
+                x >= 0.0 && y >= 0.0 && (x as u32) < document.width && (y as u32) < document.height;
+            Ok(if inside {
+                document.selection.coverage(x as u32, y as u32)
+            } else {
+                0
+            })
+        })?;
+        api.set("get_selection", get_selection)?;
+
+        // bytes = api.get_selection_region(x, y, w, h): one coverage byte per pixel
+        let get_selection_region =
+            scope.create_function(|lua, (x, y, width, height): (i64, i64, u32, u32)| {
+                check_region_size(document, width, height)?;
+                lua.create_string(document.selection_region(x, y, width, height))
+            })?;
+        api.set("get_selection_region", get_selection_region)?;
+
+        func.call(args)
+    })
+}
+
+/// The tool's own default key, from `Tool.shortcut = "B"`.
+/// Runs the script in a throwaway Lua state so nothing leaks into the engine.
+pub fn declared_shortcut(tool: &LoadedTool) -> Option<String> {
+    let lua = Lua::new();
+    let table: LuaTable = lua.load(&tool.script_content).eval().ok()?;
+    table.get::<_, String>("shortcut").ok()
+}
+
+/// Region reads are capped at the size of the whole canvas
+fn check_region_size(document: &Document, width: u32, height: u32) -> LuaResult<()> {
+    if width as u64 * height as u64 > document.width as u64 * document.height as u64 {
+        return Err(LuaError::RuntimeError(format!(
+            "region {}x{} is larger than the canvas",
+            width, height
+        )));
+    }
+    Ok(())
+}
+
+/// Reads `Tool.brush`; anything missing keeps its default.
+/// `tip` is an image path relative to the tool's package folder.
+fn read_brush_settings(
+    table: &LuaTable,
+    package_path: &Path,
+    engine: &mut BrushEngine,
+) -> BrushSettings {
+    let defaults = BrushSettings::default();
+    let get = |key: &str, default: f64| table.get::<_, f64>(key).unwrap_or(default);
+    BrushSettings {
+        size: get("size", defaults.size).max(0.5),
+        hardness: get("hardness", defaults.hardness).clamp(0.0, 1.0),
+        flow: get("flow", defaults.flow).clamp(0.0, 1.0),
+        opacity: get("opacity", defaults.opacity).clamp(0.0, 1.0),
+        spacing: get("spacing", defaults.spacing).max(0.01),
+        jitter: get("jitter", defaults.jitter).max(0.0),
+        angle: get("angle", defaults.angle),
+        roundness: get("roundness", defaults.roundness).clamp(0.01, 1.0),
+        pressure_size: get("pressure_size", defaults.pressure_size).clamp(0.0, 1.0),
+        pressure_opacity: get("pressure_opacity", defaults.pressure_opacity).clamp(0.0, 1.0),
+        pressure_flow: get("pressure_flow", defaults.pressure_flow).clamp(0.0, 1.0),
+        tip: table
+            .get::<_, String>("tip")
+            .ok()
+            .and_then(|tip| engine.load_tip(&package_path.join(tip))),
+    }
+}
+
+/// Reads an optional `{r, g, b, [a]}` table
+fn read_color(value: Option<Vec<u8>>) -> LuaResult<Option<[u8; 4]>> {
+    match value {
+        None => Ok(None),
+        Some(c) if c.len() >= 3 => Ok(Some([c[0], c[1], c[2], *c.get(3).unwrap_or(&255)])),
+        Some(_) => Err(LuaError::RuntimeError(
+            "colors look like {r, g, b, [a]}".to_string(),
+        )),
+    }
+}
+
+/// Builds a gradient from `api.fill_gradient` arguments
+fn read_gradient(
+    start: (f64, f64),
+    end: (f64, f64),
+    stops: Vec<Vec<f64>>,
+    options: Option<LuaTable>,
+) -> LuaResult<Gradient> {
+    let mut gradient_stops = Vec::with_capacity(stops.len());
+    for stop in stops {
+        if stop.len() < 4 {
+            return Err(LuaError::RuntimeError(
+                "gradient stops look like {pos, r, g, b, [a]}".to_string(),
+            ));
+        }
+        let channel = |v: f64| v.round().clamp(0.0, 255.0) as u8;
+        gradient_stops.push(GradientStop {
+            position: stop[0],
+            color: [
+                channel(stop[1]),
+                channel(stop[2]),
+                channel(stop[3]),
+                stop.get(4).copied().map_or(255, channel),
+            ],
+        });
+    }
+    gradient_stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+
+    let mut gradient = Gradient {
+        start,
+        end,
+        stops: gradient_stops,
+        shape: GradientShape::Linear,
+        repeat: RepeatMode::None,
+        dither: true,
+    };
+    if let Some(options) = options {
+        if let Ok(name) = options.get::<_, String>("shape") {
+            gradient.shape = GradientShape::from_name(&name).ok_or_else(|| {
+                LuaError::RuntimeError(format!("unknown gradient shape '{}'", name))
+            })?;
+        }
+        if let Ok(name) = options.get::<_, String>("repeat_mode") {
+            gradient.repeat = RepeatMode::from_name(&name)
+                .ok_or_else(|| LuaError::RuntimeError(format!("unknown repeat mode '{}'", name)))?;
+        }
+        if let Ok(dither) = options.get::<_, bool>("dither") {
+            gradient.dither = dither;
+        }
+    }
+    Ok(gradient)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selection::{SelectionMode, SelectionShape};
+
+    fn engine(script: &str) -> LuaEngine {
+        let mut engine = LuaEngine::new(Rc::new(FontLibrary::scan(&[])));
+        engine.load_tool(&LoadedTool {
+            name: "Test".to_string(),
+            script_content: script.to_string(),
+            package_path: PathBuf::new(),
+        });
+        engine
+    }
+
+    fn dispatch(
+        engine: &mut LuaEngine,
+        callback: &str,
+        event: &ToolEvent,
+        document: &Document,
+    ) -> (Vec<PaintCommand>, bool) {
+        dispatch_with(engine, callback, event, document, ModifiersState::default())
+    }
+
+    fn dispatch_with(
+        engine: &mut LuaEngine,
+        callback: &str,
+        event: &ToolEvent,
+        document: &Document,
+        modifiers: ModifiersState,
+    ) -> (Vec<PaintCommand>, bool) {
+        let colors = ColorPair::default();
+        let ctx = ToolContext {
+            document,
+            colors: &colors,
+            paint_with: event.button.unwrap_or(ColorTarget::Primary),
+            modifiers,
+        };
+        engine.dispatch(callback, event, &ctx)
+    }
+
+    /// Evaluates a Lua expression after the tool ran, e.g. "current_tool.seen.x"
+    fn eval<T: for<'lua> FromLuaMulti<'lua>>(engine: &LuaEngine, expr: &str) -> T {
+        engine.eval(&format!("return {}", expr))
+    }
+
+    /// A 3x2 document: transparent background, one opaque and one clear-but-colored
+    /// pixel on a second layer, and the right column selected
+    fn sample_document() -> Document {
+        let mut doc = Document::new(3, 2, [0, 0, 0, 0]);
+        doc.add_layer();
+        doc.layers[1].name = "Ink".to_string();
+        doc.layers[1].pixel_buffer[0..4].copy_from_slice(&[10, 20, 30, 255]);
+        doc.layers[1].pixel_buffer[4..8].copy_from_slice(&[200, 100, 50, 0]);
+        doc.select(
+            &SelectionShape::rect_between((2.0, 0.0), (3.0, 2.0)),
+            SelectionMode::Replace,
+            false,
+        );
+        doc
+    }
+
+    const READER: &str = r#"
+        local Tool = {}
+        function Tool.on_press(api, event)
+            Tool.seen = {
+                width = api.width, height = api.height,
+                layer = api.layer, has_selection = api.has_selection,
+                opaque = {api.get_pixel(0, 0)},
+                clear = {api.get_pixel(1, 0)},
+                outside = {api.get_pixel(3, 0)},
+                negative = {api.get_pixel(-1, 0)},
+                region = {string.byte(api.get_region(-1, 0, 3, 1), 1, -1)},
+                selection = {api.get_selection(2, 1), api.get_selection(0, 0),
+                             api.get_selection(5, 5)},
+                selection_region = {string.byte(api.get_selection_region(1, 1, 3, 1), 1, -1)},
             }
+            Tool.too_big = select(2, pcall(api.get_region, 0, 0, 4, 2))
+            Tool.too_big_selection = select(2, pcall(api.get_selection_region, 0, 0, 7, 1))
+        end
+        return Tool
+    "#;
+
+    #[test]
+    fn reads_the_document() {
+        let mut engine = engine(READER);
+        dispatch(
+            &mut engine,
+            "on_press",
+            &ToolEvent::default(),
+            &sample_document(),
+        );
+
+        assert_eq!(
+            eval::<(u32, u32)>(&engine, "current_tool.seen.width, current_tool.seen.height"),
+            (3, 2)
+        );
+        let layer: (usize, usize, String, bool) = eval(
+            &engine,
+            "current_tool.seen.layer.index, current_tool.seen.layer.count, \
+             current_tool.seen.layer.name, current_tool.seen.has_selection",
+        );
+        assert_eq!(layer, (2, 2, "Ink".to_string(), true));
+    }
+
+    #[test]
+    fn get_pixel_matches_get_region() {
+        let mut engine = engine(READER);
+        dispatch(
+            &mut engine,
+            "on_press",
+            &ToolEvent::default(),
+            &sample_document(),
+        );
+
+        assert_eq!(
+            eval::<Vec<u8>>(&engine, "current_tool.seen.opaque"),
+            [10, 20, 30, 255]
+        );
+        // Fully transparent pixels keep their color
+        assert_eq!(
+            eval::<Vec<u8>>(&engine, "current_tool.seen.clear"),
+            [200, 100, 50, 0]
+        );
+        assert!(eval::<Vec<u8>>(&engine, "current_tool.seen.outside").is_empty());
+        assert!(eval::<Vec<u8>>(&engine, "current_tool.seen.negative").is_empty());
+        assert_eq!(
+            eval::<Vec<u8>>(&engine, "current_tool.seen.region"),
+            [0, 0, 0, 0, 10, 20, 30, 255, 200, 100, 50, 0]
+        );
+    }
+
+    #[test]
+    fn reads_the_selection() {
+        let mut engine = engine(READER);
+        dispatch(
+            &mut engine,
+            "on_press",
+            &ToolEvent::default(),
+            &sample_document(),
+        );
+
+        assert_eq!(
+            eval::<Vec<u8>>(&engine, "current_tool.seen.selection"),
+            [255, 0, 0]
+        );
+        assert_eq!(
+            eval::<Vec<u8>>(&engine, "current_tool.seen.selection_region"),
+            [0, 255, 0]
+        );
+    }
+
+    #[test]
+    fn regions_larger_than_the_canvas_are_errors() {
+        let mut engine = engine(READER);
+        dispatch(
+            &mut engine,
+            "on_press",
+            &ToolEvent::default(),
+            &sample_document(),
+        );
+
+        let message: String = eval(&engine, "tostring(current_tool.too_big)");
+        assert!(
+            message.contains("region 4x2 is larger than the canvas"),
+            "{}",
+            message
+        );
+        let message: String = eval(&engine, "tostring(current_tool.too_big_selection)");
+        assert!(message.contains("region 7x1"), "{}", message);
+        // Same area as the canvas, different shape
+        assert!(check_region_size(&sample_document(), 6, 1).is_ok());
+    }
+
+    // Logs every callback and keeps the last event table it got
+    const RECORDER: &str = r#"
+        local Tool = {log = {}}
+        local function record(name)
+            return function(api, event)
+                table.insert(Tool.log, name)
+                Tool.last = event
+                if name == "on_press" then
+                    api.fill_rect(0, 0, 1, 1, 255, 0, 0)
+                end
+                -- Keys are swallowed, the wheel is not
+                return name == "on_key"
+            end
+        end
+        for _, name in ipairs({"on_activate", "on_deactivate", "on_press", "on_drag",
+                               "on_release", "on_hover", "on_key", "on_scroll"}) do
+            Tool[name] = record(name)
+        end
+        return Tool
+    "#;
+
+    #[test]
+    fn every_callback_is_dispatched() {
+        let mut engine = engine(RECORDER);
+        let doc = Document::new(4, 4, [255, 255, 255, 255]);
+        let callbacks = [
+            "on_activate",
+            "on_hover",
+            "on_press",
+            "on_drag",
+            "on_release",
+            "on_scroll",
+            "on_key",
+            "on_deactivate",
+        ];
+        for callback in callbacks {
+            let (commands, handled) = dispatch(&mut engine, callback, &ToolEvent::default(), &doc);
+            assert_eq!(handled, callback == "on_key", "{}", callback);
+            // Only on_press paints
+            assert_eq!(
+                commands
+                    .iter()
+                    .any(|c| matches!(c, PaintCommand::FillRect { .. })),
+                callback == "on_press",
+                "{}",
+                callback
+            );
         }
+        assert_eq!(eval::<Vec<String>>(&engine, "current_tool.log"), callbacks);
+    }
+
+    #[test]
+    fn missing_callbacks_do_nothing() {
+        let mut engine = engine("return {}");
+        let doc = Document::new(2, 2, [0, 0, 0, 0]);
+        let (commands, handled) = dispatch(&mut engine, "on_key", &ToolEvent::default(), &doc);
+        assert!(commands.is_empty());
+        assert!(!handled);
+    }
+
+    #[test]
+    fn errors_are_not_handled() {
+        let mut engine = engine("return {on_key = function() error('boom') end}");
+        let doc = Document::new(2, 2, [0, 0, 0, 0]);
+        let (_, handled) = dispatch(&mut engine, "on_key", &ToolEvent::default(), &doc);
+        assert!(!handled);
+    }
+
+    #[test]
+    fn drag_events_carry_position_button_and_modifiers() {
+        let mut engine = engine(RECORDER);
+        let doc = Document::new(4, 4, [0, 0, 0, 0]);
+        let event = ToolEvent {
+            x: 2.5,
+            y: 1.25,
+            prev: Some((1.0, 0.5)),
+            button: Some(ColorTarget::Secondary),
+            pressure: 0.5,
+            tilt: Some(1.0),
+            time: 12.5,
+            ..Default::default()
+        };
+        let modifiers = ModifiersState::SHIFT | ModifiersState::ALT;
+        dispatch_with(&mut engine, "on_drag", &event, &doc, modifiers);
+
+        let e = "current_tool.last";
+        let position: (f64, f64, f64, f64) = eval(
+            &engine,
+            &format!("{e}.x, {e}.y, {e}.prev_x, {e}.prev_y", e = e),
+        );
+        assert_eq!(position, (2.5, 1.25, 1.0, 0.5));
+        let state: (String, bool, bool, bool) = eval(
+            &engine,
+            &format!("{e}.button, {e}.shift, {e}.ctrl, {e}.alt", e = e),
+        );
+        assert_eq!(state, ("right".to_string(), true, false, true));
+        let pen: (f32, f64, f64) =
+            eval(&engine, &format!("{e}.pressure, {e}.tilt, {e}.time", e = e));
+        assert_eq!(pen, (0.5, 1.0, 12.5));
+        // Only key and scroll events have those fields
+        let absent: (bool, bool, bool) = eval(
+            &engine,
+            &format!(
+                "{e}.key == nil, {e}.pressed == nil, {e}.scroll_x == nil",
+                e = e
+            ),
+        );
+        assert_eq!(absent, (true, true, true));
+    }
+
+    #[test]
+    fn hover_has_no_button_or_prev_on_first_move() {
+        let mut engine = engine(RECORDER);
+        let doc = Document::new(4, 4, [0, 0, 0, 0]);
+        dispatch(&mut engine, "on_hover", &ToolEvent::default(), &doc);
+        let absent: (bool, bool, bool) = eval(
+            &engine,
+            "current_tool.last.button == nil, current_tool.last.prev_x == nil, \
+             current_tool.last.tilt == nil",
+        );
+        assert_eq!(absent, (true, true, true));
+    }
+
+    #[test]
+    fn key_and_scroll_events_have_their_fields() {
+        let mut engine = engine(RECORDER);
+        let doc = Document::new(4, 4, [0, 0, 0, 0]);
+        let key = ToolEvent {
+            key: Some("Enter".to_string()),
+            pressed: true,
+            ..Default::default()
+        };
+        dispatch(&mut engine, "on_key", &key, &doc);
+        let fields: (String, bool) =
+            eval(&engine, "current_tool.last.key, current_tool.last.pressed");
+        assert_eq!(fields, ("Enter".to_string(), true));
 
-        commands.lock().unwrap().clone()
+        let scroll = ToolEvent {
+            scroll: (0.0, -2.0),
+            ..Default::default()
+        };
+        dispatch(&mut engine, "on_scroll", &scroll, &doc);
+        let fields: (f64, f64, bool) = eval(
+            &engine,
+            "current_tool.last.scroll_x, current_tool.last.scroll_y, current_tool.last.key == nil",
+        );
+        assert_eq!(fields, (0.0, -2.0, true));
     }
 }
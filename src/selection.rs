@@ -0,0 +1,478 @@
+use crate::commands::PaintCommand;
+use crate::raster;
+
+// Segments used to approximate an ellipse outline
+const ELLIPSE_SEGMENTS: usize = 128;
+
+/// How a new shape combines with the existing selection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Subtract,
+    Intersect,
+}
+
+impl SelectionMode {
+    pub const ALL: [SelectionMode; 4] = [
+        SelectionMode::Replace,
+        SelectionMode::Add,
+        SelectionMode::Subtract,
+        SelectionMode::Intersect,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SelectionMode::Replace => "Replace",
+            SelectionMode::Add => "Add",
+            SelectionMode::Subtract => "Subtract",
+            SelectionMode::Intersect => "Intersect",
+        }
+    }
+}
+
+/// Magic wand settings
+#[derive(Clone, Copy, Debug)]
+pub struct WandOptions {
+    // Largest per-channel difference from the clicked pixel that still matches
+    pub tolerance: u8,
+    // Only pixels connected to the clicked one, or every similar pixel in the image
+    pub contiguous: bool,
+    // Look at the merged image instead of just the active layer
+    pub sample_merged: bool,
+}
+
+impl Default for WandOptions {
+    fn default() -> Self {
+        Self {
+            tolerance: 32,
+            contiguous: true,
+            sample_merged: false,
+        }
+    }
+}
+
+/// An area to select, in fractional canvas coordinates
+#[derive(Clone, Debug)]
+pub enum SelectionShape {
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    // Fits inside the given rectangle
+    Ellipse {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    // Free lasso and polygon lasso both end up as a closed polygon
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl SelectionShape {
+    /// Rectangle spanned by two drag points (in either order)
+    pub fn rect_between(a: (f64, f64), b: (f64, f64)) -> Self {
+        SelectionShape::Rect {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: (a.0 - b.0).abs(),
+            height: (a.1 - b.1).abs(),
+        }
+    }
+
+    pub fn ellipse_between(a: (f64, f64), b: (f64, f64)) -> Self {
+        SelectionShape::Ellipse {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: (a.0 - b.0).abs(),
+            height: (a.1 - b.1).abs(),
+        }
+    }
+
+    /// The shape's outline as a closed polygon (also used for the drag preview)
+    pub fn outline(&self) -> Vec<(f64, f64)> {
+        match self {
+            SelectionShape::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => vec![
+                (*x, *y),
+                (x + width, *y),
+                (x + width, y + height),
+                (*x, y + height),
+            ],
+            SelectionShape::Ellipse {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let (rx, ry) = (width / 2.0, height / 2.0);
+                let (cx, cy) = (x + rx, y + ry);
+                (0..ELLIPSE_SEGMENTS)
+                    .map(|i| {
+                        let t = i as f64 / ELLIPSE_SEGMENTS as f64 * std::f64::consts::TAU;
+                        (cx + rx * t.cos(), cy + ry * t.sin())
+                    })
+                    .collect()
+            }
+            SelectionShape::Polygon(points) => points.clone(),
+        }
+    }
+
+    /// Coverage mask (0..255 per pixel) of the shape on a `width` x `height` canvas
+    pub fn rasterize(&self, width: u32, height: u32, antialias: bool) -> Vec<u8> {
+        let color = [255, 255, 255, 255];
+        let cmd = match self {
+            SelectionShape::Rect {
+                x,
+                y,
+                width: w,
+                height: h,
+            } => PaintCommand::FillRect {
+                x: *x,
+                y: *y,
+                width: *w,
+                height: *h,
+                color,
+                antialias,
+            },
+            _ => PaintCommand::FillPolygon {
+                points: self.outline(),
+                color,
+                antialias,
+            },
+        };
+        let mut mask = vec![0u8; (width * height) as usize];
+        raster::rasterize(&cmd, width, height, |x, y, [_, _, _, a]| {
+            mask[(y * width + x) as usize] = a;
+        });
+        mask
+    }
+}
+
+/// The document's selection: an 8-bit coverage mask, or nothing (everything is editable)
+#[derive(Clone)]
+pub struct Selection {
+    width: u32,
+    height: u32,
+    mask: Option<Vec<u8>>,
+    // Bumped on every change so the viewport knows when to rebuild the outline
+    version: u64,
+}
+
+impl Selection {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            mask: None,
+            version: 0,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.mask.is_some()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn mask(&self) -> Option<&[u8]> {
+        self.mask.as_deref()
+    }
+
+    /// Replaces the mask wholesale (undo/redo); None means no selection
+    pub fn set_mask(&mut self, mask: Option<Vec<u8>>) {
+        self.mask = mask;
+        self.version += 1;
+    }
+
+    /// How much pixel (x, y) is selected; 255 everywhere when there is no selection
+    pub fn coverage(&self, x: u32, y: u32) -> u8 {
+        match &self.mask {
+            Some(mask) => mask[(y * self.width + x) as usize],
+            None => 255,
+        }
+    }
+
+    pub fn select_all(&mut self) {
+        self.set_mask(Some(vec![255; (self.width * self.height) as usize]));
+    }
+
+    pub fn select_none(&mut self) {
+        self.set_mask(None);
+    }
+
+    /// Swaps selected and unselected; does nothing without a selection
+    pub fn invert(&mut self) {
+        if let Some(mask) = &mut self.mask {
+            for value in mask.iter_mut() {
+                *value = 255 - *value;
+            }
+            self.version += 1;
+        }
+    }
+
+    /// Merges a coverage mask (same size as the canvas) into the selection
+    pub fn combine(&mut self, shape: Vec<u8>, mode: SelectionMode) {
+        let mask = match (mode, self.mask.take()) {
+            (SelectionMode::Replace, _) => shape,
+            // Nothing selected yet: adding is the same as replacing...
+            (SelectionMode::Add, None) => shape,
+            // ...and the implicit "everything" minus or intersected with the shape
+            (SelectionMode::Subtract, None) => shape.iter().map(|s| 255 - s).collect(),
+            (SelectionMode::Intersect, None) => shape,
+            (mode, Some(mut mask)) => {
+                for (m, s) in mask.iter_mut().zip(&shape) {
+                    let (a, b) = (*m as u32, *s as u32);
+                    *m = match mode {
+                        SelectionMode::Add => a.max(b),
+                        SelectionMode::Subtract => a * (255 - b) / 255,
+                        _ => a * b / 255,
+                    } as u8;
+                }
+                mask
+            }
+        };
+        self.set_mask(Some(mask));
+    }
+
+    /// Edges between selected (coverage >= 50%) and unselected pixels, in canvas coordinates.
+    /// Neighbouring pixel edges are merged into longer segments.
+    pub fn outline(&self) -> Vec<[(f64, f64); 2]> {
+        let Some(mask) = &self.mask else {
+            return Vec::new();
+        };
+        let (w, h) = (self.width as i64, self.height as i64);
+        let inside = |x: i64, y: i64| {
+            x >= 0 && y >= 0 && x < w && y < h && mask[(y * w + x) as usize] >= 128
+        };
+
+        let mut segments = Vec::new();
+        // Horizontal edges: between row y - 1 and row y
+        for y in 0..=h {
+            let mut run_start: Option<i64> = None;
+            for x in 0..=w {
+                let edge = x < w && inside(x, y - 1) != inside(x, y);
+                match (edge, run_start) {
+                    (true, None) => run_start = Some(x),
+                    (false, Some(start)) => {
+                        segments.push([(start as f64, y as f64), (x as f64, y as f64)]);
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // Vertical edges: between column x - 1 and column x
+        for x in 0..=w {
+            let mut run_start: Option<i64> = None;
+            for y in 0..=h {
+                let edge = y < h && inside(x - 1, y) != inside(x, y);
+                match (edge, run_start) {
+                    (true, None) => run_start = Some(y),
+                    (false, Some(start)) => {
+                        segments.push([(x as f64, start as f64), (x as f64, y as f64)]);
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        segments
+    }
+}
+
+/// The built-in selection tools
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionTool {
+    Rect,
+    Ellipse,
+    Lasso,
+    PolygonLasso,
+    MagicWand,
+}
+
+impl SelectionTool {
+    pub const ALL: [SelectionTool; 5] = [
+        SelectionTool::Rect,
+        SelectionTool::Ellipse,
+        SelectionTool::Lasso,
+        SelectionTool::PolygonLasso,
+        SelectionTool::MagicWand,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SelectionTool::Rect => "Rectangle Select",
+            SelectionTool::Ellipse => "Ellipse Select",
+            SelectionTool::Lasso => "Lasso",
+            SelectionTool::PolygonLasso => "Polygon Lasso",
+            SelectionTool::MagicWand => "Magic Wand",
+        }
+    }
+
+    /// The shape described by the points collected while dragging (or clicking)
+    pub fn shape(&self, points: &[(f64, f64)]) -> Option<SelectionShape> {
+        match self {
+            SelectionTool::Rect | SelectionTool::Ellipse => {
+                let (start, end) = (*points.first()?, *points.last()?);
+                Some(if *self == SelectionTool::Rect {
+                    SelectionShape::rect_between(start, end)
+                } else {
+                    SelectionShape::ellipse_between(start, end)
+                })
+            }
+            SelectionTool::Lasso | SelectionTool::PolygonLasso => {
+                (points.len() >= 3).then(|| SelectionShape::Polygon(points.to_vec()))
+            }
+            // Works on a single click, see Document::select_color
+            SelectionTool::MagicWand => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_mask(mask: &[u8]) -> Selection {
+        let mut selection = Selection::new(mask.len() as u32, 1);
+        selection.set_mask(Some(mask.to_vec()));
+        selection
+    }
+
+    #[test]
+    fn combining_without_a_selection() {
+        let shape = vec![0, 128, 255];
+        for (mode, expected) in [
+            (SelectionMode::Replace, [0, 128, 255]),
+            (SelectionMode::Add, [0, 128, 255]),
+            (SelectionMode::Subtract, [255, 127, 0]),
+            (SelectionMode::Intersect, [0, 128, 255]),
+        ] {
+            let mut selection = Selection::new(3, 1);
+            selection.combine(shape.clone(), mode);
+            assert_eq!(selection.mask(), Some(&expected[..]), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn combining_with_a_selection() {
+        let shape = vec![0, 255, 255, 128];
+        for (mode, expected) in [
+            (SelectionMode::Replace, [0, 255, 255, 128]),
+            (SelectionMode::Add, [255, 255, 255, 200]),
+            (SelectionMode::Subtract, [255, 0, 0, 99]),
+            (SelectionMode::Intersect, [0, 0, 255, 100]),
+        ] {
+            let mut selection = with_mask(&[255, 0, 255, 200]);
+            selection.combine(shape.clone(), mode);
+            assert_eq!(selection.mask(), Some(&expected[..]), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn invert_flips_coverage_and_needs_a_selection() {
+        let mut selection = Selection::new(2, 1);
+        selection.invert();
+        assert!(!selection.is_active());
+
+        let mut selection = with_mask(&[0, 200]);
+        let version = selection.version();
+        selection.invert();
+        assert_eq!(selection.mask(), Some(&[255, 55][..]));
+        assert!(selection.version() > version);
+    }
+
+    #[test]
+    fn no_selection_covers_everything() {
+        let mut selection = Selection::new(2, 2);
+        assert_eq!(selection.coverage(1, 1), 255);
+        assert!(selection.outline().is_empty());
+        selection.select_all();
+        assert_eq!(selection.mask(), Some(&[255; 4][..]));
+        selection.select_none();
+        assert!(!selection.is_active());
+    }
+
+    #[test]
+    fn outline_merges_runs() {
+        // A 3x2 block selected in the top-left of a 4x3 canvas
+        let mut selection = Selection::new(4, 3);
+        selection.combine(
+            SelectionShape::rect_between((0.0, 0.0), (3.0, 2.0)).rasterize(4, 3, false),
+            SelectionMode::Replace,
+        );
+        let mut outline = selection.outline();
+        outline.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            outline,
+            vec![
+                [(0.0, 0.0), (0.0, 2.0)],
+                [(0.0, 0.0), (3.0, 0.0)],
+                [(0.0, 2.0), (3.0, 2.0)],
+                [(3.0, 0.0), (3.0, 2.0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn outline_ignores_weak_coverage() {
+        let selection = with_mask(&[127, 128]);
+        let mut outline = selection.outline();
+        outline.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            outline,
+            vec![
+                [(1.0, 0.0), (1.0, 1.0)],
+                [(1.0, 0.0), (2.0, 0.0)],
+                [(1.0, 1.0), (2.0, 1.0)],
+                [(2.0, 0.0), (2.0, 1.0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn rect_rasterizes_whole_pixels() {
+        let mask = SelectionShape::rect_between((3.0, 2.0), (1.0, 0.0)).rasterize(4, 3, false);
+        #[rustfmt::skip]
+        assert_eq!(mask, [
+            0, 255, 255, 0,
+            0, 255, 255, 0,
+            0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn antialiased_ellipse_has_soft_edges() {
+        let shape = SelectionShape::ellipse_between((0.0, 0.0), (8.0, 8.0));
+        let hard = shape.rasterize(8, 8, false);
+        let soft = shape.rasterize(8, 8, true);
+        assert!(hard.iter().all(|c| *c == 0 || *c == 255));
+        assert!(soft.iter().any(|c| *c > 0 && *c < 255));
+        // Center in, corners out
+        assert_eq!((hard[3 * 8 + 3], soft[3 * 8 + 3]), (255, 255));
+        assert_eq!((hard[0], soft[0]), (0, 0));
+    }
+
+    #[test]
+    fn lasso_needs_three_points() {
+        let points = [(0.0, 0.0), (4.0, 0.0)];
+        assert!(SelectionTool::Lasso.shape(&points).is_none());
+        let points = [(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)];
+        let mask = SelectionTool::Lasso
+            .shape(&points)
+            .unwrap()
+            .rasterize(4, 4, false);
+        assert_eq!(mask[0], 255);
+        assert_eq!(mask[15], 0);
+    }
+}
@@ -1,3 +1,11 @@
+struct View {
+    // Canvas quad size and position in clip space (see viewport.rs)
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+};
+
+@group(0) @binding(2) var<uniform> view: View;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
@@ -22,7 +30,7 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
     let index = indices[in_vertex_index];
 
     let xy = pos[index];
-    out.clip_position = vec4<f32>(xy, 0.0, 1.0);
+    out.clip_position = vec4<f32>(xy * view.scale + view.offset, 0.0, 1.0);
 
     out.tex_coords = vec2<f32>(xy.x * 0.5 + 0.5, 1.0 - (xy.y * 0.5 + 0.5));
 
@@ -32,7 +40,20 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
 @group(0) @binding(0) var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1) var s_diffuse: sampler;
 
+// Size of the transparency checkerboard squares, in screen pixels
+const CHECKER_SIZE: f32 = 8.0;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+
+    // Checkerboard behind transparent pixels (fixed to the screen so it doesn't scale with zoom)
+    let cell = floor(in.clip_position.xy / CHECKER_SIZE);
+    let odd = (i32(cell.x) + i32(cell.y)) & 1;
+    var checker = vec3<f32>(0.8, 0.8, 0.8);
+    if odd == 1 {
+        checker = vec3<f32>(0.5, 0.5, 0.5);
+    }
+
+    return vec4<f32>(mix(checker, color.rgb, color.a), 1.0);
 }
@@ -0,0 +1,449 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use crate::config;
+
+const SHORTCUTS_FILE: &str = "shortcuts.toml";
+
+// Non-character keys that can be bound, by the name used in shortcut strings
+const NAMED_KEYS: [(NamedKey, &str); 27] = [
+    (NamedKey::Enter, "Enter"),
+    (NamedKey::Tab, "Tab"),
+    (NamedKey::Space, "Space"),
+    (NamedKey::Backspace, "Backspace"),
+    (NamedKey::Delete, "Delete"),
+    (NamedKey::Insert, "Insert"),
+    (NamedKey::Home, "Home"),
+    (NamedKey::End, "End"),
+    (NamedKey::PageUp, "PageUp"),
+    (NamedKey::PageDown, "PageDown"),
+    (NamedKey::ArrowUp, "Up"),
+    (NamedKey::ArrowDown, "Down"),
+    (NamedKey::ArrowLeft, "Left"),
+    (NamedKey::ArrowRight, "Right"),
+    (NamedKey::Escape, "Escape"),
+    (NamedKey::F1, "F1"),
+    (NamedKey::F2, "F2"),
+    (NamedKey::F3, "F3"),
+    (NamedKey::F4, "F4"),
+    (NamedKey::F5, "F5"),
+    (NamedKey::F6, "F6"),
+    (NamedKey::F7, "F7"),
+    (NamedKey::F8, "F8"),
+    (NamedKey::F9, "F9"),
+    (NamedKey::F10, "F10"),
+    (NamedKey::F11, "F11"),
+    (NamedKey::F12, "F12"),
+];
+
+/// A key plus modifiers, e.g. "Ctrl+Shift+Z".
+/// Shift only counts for letters and named keys: "+" already implies it on most layouts.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Shortcut {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    // A lowercase character, or one of NAMED_KEYS
+    pub key: String,
+}
+
+impl Shortcut {
+    fn new(key: String, ctrl: bool, shift: bool, alt: bool) -> Self {
+        let is_symbol = key.chars().count() == 1 && !key.chars().all(char::is_alphabetic);
+        Self {
+            ctrl,
+            shift: shift && !is_symbol,
+            alt,
+            key,
+        }
+    }
+
+    /// The shortcut for a key press; None for keys that can't be bound (modifiers alone etc.)
+    pub fn from_key(key: &Key, modifiers: ModifiersState) -> Option<Self> {
+        let name = match key {
+            Key::Character(c) => c.to_lowercase(),
+            Key::Named(named) => NAMED_KEYS
+                .iter()
+                .find(|(k, _)| k == named)
+                .map(|(_, name)| name.to_string())?,
+            _ => return None,
+        };
+        Some(Self::new(
+            name,
+            modifiers.control_key(),
+            modifiers.shift_key(),
+            modifiers.alt_key(),
+        ))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        // The key itself can be "+", as in "Ctrl++"
+        let (mods, key) = match text.strip_suffix("++") {
+            Some(mods) => (mods, "+"),
+            None if text == "+" => ("", "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+        let (mut ctrl, mut shift, mut alt) = (false, false, false);
+        for modifier in mods.split('+').filter(|m| !m.is_empty()) {
+            match modifier.trim().to_lowercase().as_str() {
+                "ctrl" | "control" | "cmd" => ctrl = true,
+                "shift" => shift = true,
+                "alt" | "option" => alt = true,
+                other => return Err(format!("unknown modifier '{}' in '{}'", other, text)),
+            }
+        }
+        let key = if key.chars().count() == 1 {
+            key.to_lowercase()
+        } else {
+            NAMED_KEYS
+                .iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(key))
+                .map(|(_, name)| name.to_string())
+                .ok_or_else(|| format!("unknown key '{}' in '{}'", key, text))?
+        };
+        Ok(Self::new(key, ctrl, shift, alt))
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{}", self.key.to_uppercase())
+    }
+}
+
+/// Something a shortcut can trigger
+#[derive(Clone, Debug)]
+pub struct ActionDef {
+    // "edit.undo", "tool.brush", ...
+    pub id: String,
+    pub label: String,
+    pub defaults: Vec<Shortcut>,
+}
+
+impl ActionDef {
+    /// `defaults` are shortcut strings; bad ones are reported and skipped
+    pub fn new(id: &str, label: &str, defaults: &[&str]) -> Self {
+        let defaults = defaults
+            .iter()
+            .filter_map(|text| match Shortcut::parse(text) {
+                Ok(shortcut) => Some(shortcut),
+                Err(e) => {
+                    println!("Bad default shortcut for {}: {}", id, e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            id: id.to_string(),
+            label: label.to_string(),
+            defaults,
+        }
+    }
+}
+
+/// Everything the app itself can do from the keyboard (tools are added on top)
+pub fn builtin_actions() -> Vec<ActionDef> {
+    vec![
+        ActionDef::new("file.new", "New...", &["Ctrl+N"]),
+        ActionDef::new("file.open", "Open...", &["Ctrl+O"]),
+        ActionDef::new("file.save", "Save", &["Ctrl+S"]),
+        ActionDef::new("file.save_as", "Save As...", &["Ctrl+Shift+S"]),
+        ActionDef::new("file.export", "Export...", &["Ctrl+E"]),
+        ActionDef::new("edit.undo", "Undo", &["Ctrl+Z"]),
+        ActionDef::new("edit.redo", "Redo", &["Ctrl+Shift+Z", "Ctrl+Y"]),
+        ActionDef::new("select.all", "Select All", &["Ctrl+A"]),
+        ActionDef::new("select.none", "Deselect", &["Ctrl+D"]),
+        ActionDef::new("select.invert", "Invert Selection", &["Ctrl+Shift+I"]),
+        ActionDef::new("view.zoom_in", "Zoom In", &["Ctrl+=", "Ctrl++"]),
+        ActionDef::new("view.zoom_out", "Zoom Out", &["Ctrl+-"]),
+        ActionDef::new("view.fit", "Fit to Window", &["Ctrl+0"]),
+        ActionDef::new("view.actual_size", "Actual Size", &["Ctrl+1"]),
+        ActionDef::new("colors.swap", "Swap Colors", &["X"]),
+        ActionDef::new("colors.reset", "Reset Colors", &["D"]),
+    ]
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ShortcutsFile {
+    // Action id -> shortcuts; an empty list unbinds the action
+    #[serde(default)]
+    bindings: BTreeMap<String, Vec<String>>,
+}
+
+/// Which shortcuts trigger which action: the defaults, with the user's overrides on top
+pub struct Keymap {
+    actions: Vec<ActionDef>,
+    bindings: HashMap<String, Vec<Shortcut>>,
+    // Overrides for actions we don't have right now (e.g. a removed package's tool),
+    // kept so saving doesn't throw them away
+    unknown: BTreeMap<String, Vec<String>>,
+}
+
+impl Keymap {
+    /// Default bindings for `actions`, then the user's shortcuts.toml
+    pub fn new(actions: Vec<ActionDef>) -> Self {
+        let mut keymap = Self::with_defaults(actions);
+        let path = config::config_file(SHORTCUTS_FILE);
+        if let Ok(text) = fs::read_to_string(&path)
+            && let Err(e) = keymap.load_overrides(&text)
+        {
+            println!("Ignoring {}: {}", path.display(), e);
+        }
+        for (shortcut, ids) in keymap.conflicts() {
+            println!("Shortcut {} is bound to {}", shortcut, ids.join(", "));
+        }
+        keymap
+    }
+
+    fn with_defaults(actions: Vec<ActionDef>) -> Self {
+        let bindings = actions
+            .iter()
+            .map(|a| (a.id.clone(), a.defaults.clone()))
+            .collect();
+        Self {
+            actions,
+            bindings,
+            unknown: BTreeMap::new(),
+        }
+    }
+
+    /// Applies the contents of a shortcuts.toml on top of the current bindings
+    fn load_overrides(&mut self, text: &str) -> Result<(), String> {
+        let file = toml::from_str::<ShortcutsFile>(text).map_err(|e| e.to_string())?;
+        for (id, texts) in file.bindings {
+            if !self.bindings.contains_key(&id) {
+                self.unknown.insert(id, texts);
+                continue;
+            }
+            let mut shortcuts = Vec::new();
+            for text in &texts {
+                match Shortcut::parse(text) {
+                    Ok(shortcut) => shortcuts.push(shortcut),
+                    Err(e) => println!("Ignoring shortcut for {}: {}", id, e),
+                }
+            }
+            self.bindings.insert(id, shortcuts);
+        }
+        Ok(())
+    }
+
+    /// Writes every binding that differs from its default
+    pub fn save(&self) -> Result<(), String> {
+        let path = config::config_file(SHORTCUTS_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(&path, self.overrides_toml()?).map_err(|e| e.to_string())
+    }
+
+    /// The shortcuts.toml text for the current bindings
+    fn overrides_toml(&self) -> Result<String, String> {
+        let mut file = ShortcutsFile {
+            bindings: self.unknown.clone(),
+        };
+        for action in &self.actions {
+            let current = self.bindings(&action.id);
+            if current != action.defaults.as_slice() {
+                let texts = current.iter().map(|s| s.to_string()).collect();
+                file.bindings.insert(action.id.clone(), texts);
+            }
+        }
+        toml::to_string(&file).map_err(|e| e.to_string())
+    }
+
+    pub fn actions(&self) -> &[ActionDef] {
+        &self.actions
+    }
+
+    pub fn bindings(&self, id: &str) -> &[Shortcut] {
+        self.bindings.get(id).map_or(&[], |b| b.as_slice())
+    }
+
+    /// "Ctrl+Z", or "Ctrl+Shift+Z, Ctrl+Y" for several; empty when unbound
+    pub fn label(&self, id: &str) -> String {
+        let labels: Vec<String> = self.bindings(id).iter().map(|s| s.to_string()).collect();
+        labels.join(", ")
+    }
+
+    /// "Undo (Ctrl+Z)" for tooltips; just `text` when the action is unbound
+    pub fn with_shortcut(&self, text: &str, id: &str) -> String {
+        match self.label(id) {
+            label if label.is_empty() => text.to_string(),
+            label => format!("{} ({})", text, label),
+        }
+    }
+
+    /// The action a key press triggers. With conflicting bindings the first action wins.
+    pub fn action_for(&self, shortcut: &Shortcut) -> Option<&str> {
+        self.actions
+            .iter()
+            .find(|a| self.bindings(&a.id).contains(shortcut))
+            .map(|a| a.id.as_str())
+    }
+
+    pub fn set(&mut self, id: &str, shortcuts: Vec<Shortcut>) {
+        self.bindings.insert(id.to_string(), shortcuts);
+    }
+
+    pub fn reset(&mut self, id: &str) {
+        if let Some(action) = self.actions.iter().find(|a| a.id == id) {
+            self.bindings
+                .insert(id.to_string(), action.defaults.clone());
+        }
+    }
+
+    /// Shortcuts bound to more than one action, with those actions' labels
+    pub fn conflicts(&self) -> Vec<(Shortcut, Vec<String>)> {
+        let mut users: HashMap<&Shortcut, Vec<String>> = HashMap::new();
+        for action in &self.actions {
+            for shortcut in self.bindings(&action.id) {
+                users
+                    .entry(shortcut)
+                    .or_default()
+                    .push(action.label.clone());
+            }
+        }
+        let mut conflicts: Vec<(Shortcut, Vec<String>)> = users
+            .into_iter()
+            .filter(|(_, labels)| labels.len() > 1)
+            .map(|(shortcut, labels)| (shortcut.clone(), labels))
+            .collect();
+        conflicts.sort_by_key(|(shortcut, _)| shortcut.to_string());
+        conflicts
+    }
+
+    /// Other actions sharing a shortcut with `id`
+    pub fn conflicts_with(&self, id: &str) -> Vec<&str> {
+        let mine = self.bindings(id);
+        self.actions
+            .iter()
+            .filter(|a| a.id != id && self.bindings(&a.id).iter().any(|s| mine.contains(s)))
+            .map(|a| a.label.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifiers_in_any_case() {
+        let shortcut = Shortcut::parse("ctrl+Shift+z").unwrap();
+        assert!(shortcut.ctrl && shortcut.shift && !shortcut.alt);
+        assert_eq!(shortcut.key, "z");
+        assert_eq!(shortcut.to_string(), "Ctrl+Shift+Z");
+        assert_eq!(Shortcut::parse("F5").unwrap().key, "F5");
+    }
+
+    #[test]
+    fn plus_can_be_the_key() {
+        let zoom = Shortcut::parse("Ctrl++").unwrap();
+        assert!(zoom.ctrl);
+        assert_eq!(zoom.key, "+");
+        assert_eq!(Shortcut::parse("+").unwrap().key, "+");
+    }
+
+    #[test]
+    fn shift_is_dropped_for_symbols() {
+        let shortcut = Shortcut::from_key(
+            &Key::Character("+".into()),
+            ModifiersState::CONTROL | ModifiersState::SHIFT,
+        )
+        .unwrap();
+        assert_eq!(shortcut, Shortcut::parse("Ctrl++").unwrap());
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert!(Shortcut::parse("Hyper+A").is_err());
+        assert!(Shortcut::parse("Ctrl+Banana").is_err());
+    }
+
+    fn test_keymap() -> Keymap {
+        Keymap::with_defaults(vec![
+            ActionDef::new("edit.undo", "Undo", &["Ctrl+Z"]),
+            ActionDef::new("edit.redo", "Redo", &["Ctrl+Shift+Z", "Ctrl+Y"]),
+            ActionDef::new("tool.brush", "Brush", &["B"]),
+        ])
+    }
+
+    #[test]
+    fn shared_shortcuts_are_conflicts() {
+        let mut keymap = test_keymap();
+        assert!(keymap.conflicts().is_empty());
+
+        keymap.set("tool.brush", vec![Shortcut::parse("Ctrl+Y").unwrap()]);
+        let conflicts = keymap.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].0, Shortcut::parse("Ctrl+Y").unwrap());
+        assert_eq!(conflicts[0].1, ["Redo", "Brush"]);
+        assert_eq!(keymap.conflicts_with("edit.redo"), ["Brush"]);
+        assert!(keymap.conflicts_with("edit.undo").is_empty());
+        // The first action wins the key press
+        let ctrl_y = Shortcut::parse("Ctrl+Y").unwrap();
+        assert_eq!(keymap.action_for(&ctrl_y), Some("edit.redo"));
+    }
+
+    #[test]
+    fn unknown_overrides_survive_saving() {
+        let mut keymap = test_keymap();
+        keymap
+            .load_overrides(
+                "[bindings]\n\"tool.removed\" = [\"R\"]\n\"edit.undo\" = [\"Ctrl+U\"]\n",
+            )
+            .unwrap();
+        assert_eq!(keymap.label("edit.undo"), "Ctrl+U");
+
+        let text = keymap.overrides_toml().unwrap();
+        let mut reloaded = test_keymap();
+        reloaded.load_overrides(&text).unwrap();
+        assert_eq!(
+            reloaded.unknown.get("tool.removed"),
+            Some(&vec!["R".to_string()])
+        );
+        assert_eq!(reloaded.label("edit.undo"), "Ctrl+U");
+        // Defaults aren't written out
+        assert!(!text.contains("tool.brush"));
+    }
+
+    #[test]
+    fn empty_override_unbinds() {
+        let mut keymap = test_keymap();
+        keymap
+            .load_overrides("[bindings]\n\"edit.redo\" = []\n")
+            .unwrap();
+        assert!(keymap.bindings("edit.redo").is_empty());
+        assert_eq!(keymap.action_for(&Shortcut::parse("Ctrl+Y").unwrap()), None);
+
+        // Still unbound after a save and reload
+        let mut reloaded = test_keymap();
+        reloaded
+            .load_overrides(&keymap.overrides_toml().unwrap())
+            .unwrap();
+        assert!(reloaded.bindings("edit.redo").is_empty());
+
+        keymap.reset("edit.redo");
+        assert_eq!(keymap.label("edit.redo"), "Ctrl+Shift+Z, Ctrl+Y");
+    }
+
+    #[test]
+    fn broken_override_files_are_errors() {
+        let mut keymap = test_keymap();
+        assert!(keymap.load_overrides("[bindings\n").is_err());
+        assert_eq!(keymap.label("edit.undo"), "Ctrl+Z");
+    }
+}
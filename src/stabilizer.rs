@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+
+// Pulled string length (window pixels) at full strength
+const MAX_STRING_LENGTH: f64 = 60.0;
+// Moving average window (samples) at full strength
+const MAX_AVERAGE_WINDOW: usize = 16;
+// Spline segments are cut into pieces about this long (window pixels)
+const SPLINE_STEP: f64 = 2.0;
+
+/// One point of a stroke, after smoothing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokePoint {
+    pub x: f64,
+    pub y: f64,
+    pub pressure: f32,
+}
+
+impl StrokePoint {
+    fn distance(&self, other: &StrokePoint) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+
+    fn lerp(&self, other: &StrokePoint, t: f64) -> StrokePoint {
+        StrokePoint {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+            pressure: self.pressure + (other.pressure - self.pressure) * t as f32,
+        }
+    }
+}
+
+/// How much each smoothing stage does; 0 turns a stage off
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct StabilizerSettings {
+    // Pulled string / lazy mouse: the brush trails the cursor on a string
+    pub string: f32,
+    // Weighted moving average over the last few samples
+    pub average: f32,
+    // Catmull-Rom curves instead of straight lines between samples
+    pub spline: f32,
+}
+
+/// Smooths the raw cursor samples of a stroke. Samples go in as they arrive,
+/// points to draw to come out (possibly none, possibly several).
+pub struct Stabilizer {
+    pub settings: StabilizerSettings,
+    // Off for the current stroke (tools like shapes want the raw cursor)
+    bypass: bool,
+    // Where the end of the string is
+    brush: Option<StrokePoint>,
+    window: VecDeque<StrokePoint>,
+    // The last smoothed points; the spline between the middle two is drawn once
+    // the point after them is known
+    controls: Vec<StrokePoint>,
+}
+
+impl Stabilizer {
+    pub fn new(settings: StabilizerSettings) -> Self {
+        Self {
+            settings,
+            bypass: false,
+            brush: None,
+            window: VecDeque::new(),
+            controls: Vec::new(),
+        }
+    }
+
+    /// Starts a stroke at `point`, which is always drawn as is
+    pub fn begin(&mut self, point: StrokePoint, enabled: bool) -> Vec<StrokePoint> {
+        self.bypass = !enabled;
+        self.brush = Some(point);
+        self.window.clear();
+        self.window.push_back(point);
+        self.controls = vec![point, point];
+        vec![point]
+    }
+
+    pub fn push(&mut self, sample: StrokePoint) -> Vec<StrokePoint> {
+        if self.bypass {
+            return vec![sample];
+        }
+        let Some(point) = self.pull_string(sample) else {
+            return Vec::new();
+        };
+        let point = self.average(point);
+        self.interpolate(point)
+    }
+
+    /// Draws whatever the spline was still holding back
+    pub fn end(&mut self) -> Vec<StrokePoint> {
+        if self.bypass || self.settings.spline <= 0.0 || self.controls.len() < 3 {
+            return Vec::new();
+        }
+        let last = self.controls[self.controls.len() - 1];
+        self.interpolate(last)
+    }
+
+    fn pull_string(&mut self, sample: StrokePoint) -> Option<StrokePoint> {
+        let length = self.settings.string.clamp(0.0, 1.0) as f64 * MAX_STRING_LENGTH;
+        let brush = self.brush.get_or_insert(sample);
+        let distance = brush.distance(&sample);
+        if distance <= length {
+            // Slack string: only the pressure follows
+            brush.pressure = sample.pressure;
+            return None;
+        }
+        *brush = brush.lerp(&sample, (distance - length) / distance);
+        brush.pressure = sample.pressure;
+        Some(*brush)
+    }
+
+    fn average(&mut self, point: StrokePoint) -> StrokePoint {
+        let size = 1
+            + (self.settings.average.clamp(0.0, 1.0) * (MAX_AVERAGE_WINDOW - 1) as f32).round()
+                as usize;
+        self.window.push_back(point);
+        while self.window.len() > size {
+            self.window.pop_front();
+        }
+        // Newer samples weigh more so the stroke doesn't lag too far behind
+        let (mut x, mut y, mut pressure, mut total) = (0.0, 0.0, 0.0, 0.0);
+        for (i, p) in self.window.iter().enumerate() {
+            let weight = (i + 1) as f64;
+            x += p.x * weight;
+            y += p.y * weight;
+            pressure += p.pressure as f64 * weight;
+            total += weight;
+        }
+        StrokePoint {
+            x: x / total,
+            y: y / total,
+            pressure: (pressure / total) as f32,
+        }
+    }
+
+    /// Without a spline points come straight through. With one, the curve up to the
+    /// previous point is drawn now that the next control point is known.
+    fn interpolate(&mut self, point: StrokePoint) -> Vec<StrokePoint> {
+        let strength = self.settings.spline.clamp(0.0, 1.0) as f64;
+        if strength <= 0.0 {
+            return vec![point];
+        }
+        self.controls.push(point);
+        if self.controls.len() > 4 {
+            self.controls.remove(0);
+        }
+        if self.controls.len() < 4 {
+            return Vec::new();
+        }
+        let [p0, p1, p2, p3] = [
+            self.controls[0],
+            self.controls[1],
+            self.controls[2],
+            self.controls[3],
+        ];
+        let steps = (p1.distance(&p2) / SPLINE_STEP).ceil().max(1.0) as usize;
+        (1..=steps)
+            .map(|i| {
+                let t = i as f64 / steps as f64;
+                let straight = p1.lerp(&p2, t);
+                let curved = catmull_rom(p0, p1, p2, p3, t);
+                straight.lerp(&curved, strength)
+            })
+            .collect()
+    }
+}
+
+/// Uniform Catmull-Rom between p1 and p2; pressure just follows the chord
+fn catmull_rom(
+    p0: StrokePoint,
+    p1: StrokePoint,
+    p2: StrokePoint,
+    p3: StrokePoint,
+    t: f64,
+) -> StrokePoint {
+    let (t2, t3) = (t * t, t * t * t);
+    let blend = |a: f64, b: f64, c: f64, d: f64| {
+        0.5 * (2.0 * b
+            + (c - a) * t
+            + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2
+            + (3.0 * b - a - 3.0 * c + d) * t3)
+    };
+    StrokePoint {
+        x: blend(p0.x, p1.x, p2.x, p3.x),
+        y: blend(p0.y, p1.y, p2.y, p3.y),
+        pressure: p1.pressure + (p2.pressure - p1.pressure) * t as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64) -> StrokePoint {
+        StrokePoint {
+            x,
+            y,
+            pressure: 1.0,
+        }
+    }
+
+    fn settings(string: f32, average: f32, spline: f32) -> StabilizerSettings {
+        StabilizerSettings {
+            string,
+            average,
+            spline,
+        }
+    }
+
+    #[test]
+    fn off_passes_samples_through() {
+        let mut stabilizer = Stabilizer::new(StabilizerSettings::default());
+        assert_eq!(stabilizer.begin(point(0.0, 0.0), true), [point(0.0, 0.0)]);
+        assert_eq!(stabilizer.push(point(3.0, 4.0)), [point(3.0, 4.0)]);
+        assert!(stabilizer.end().is_empty());
+    }
+
+    #[test]
+    fn bypass_ignores_the_settings() {
+        let mut stabilizer = Stabilizer::new(settings(1.0, 1.0, 1.0));
+        stabilizer.begin(point(0.0, 0.0), false);
+        assert_eq!(stabilizer.push(point(1.0, 0.0)), [point(1.0, 0.0)]);
+    }
+
+    #[test]
+    fn string_trails_the_cursor() {
+        // Half strength: a 30 pixel string
+        let mut stabilizer = Stabilizer::new(settings(0.5, 0.0, 0.0));
+        stabilizer.begin(point(0.0, 0.0), true);
+        assert!(stabilizer.push(point(20.0, 0.0)).is_empty());
+        assert_eq!(stabilizer.push(point(50.0, 0.0)), [point(20.0, 0.0)]);
+    }
+
+    #[test]
+    fn average_weighs_recent_samples_more() {
+        let mut stabilizer = Stabilizer::new(settings(0.0, 1.0, 0.0));
+        stabilizer.begin(point(0.0, 0.0), true);
+        let out = stabilizer.push(point(30.0, 0.0));
+        // (0 * 1 + 30 * 2) / 3
+        assert_eq!(out, [point(20.0, 0.0)]);
+    }
+
+    #[test]
+    fn spline_reaches_every_sample() {
+        let mut stabilizer = Stabilizer::new(settings(0.0, 0.0, 1.0));
+        stabilizer.begin(point(0.0, 0.0), true);
+        assert!(stabilizer.push(point(10.0, 0.0)).is_empty());
+        let out = stabilizer.push(point(10.0, 10.0));
+        assert_eq!(out.last(), Some(&point(10.0, 0.0)));
+        // Pieces are at most SPLINE_STEP apart
+        assert!(
+            out.windows(2)
+                .all(|w| w[0].distance(&w[1]) <= SPLINE_STEP + 0.5)
+        );
+        assert_eq!(stabilizer.end().last(), Some(&point(10.0, 10.0)));
+    }
+}
@@ -0,0 +1,267 @@
+use winit::keyboard::ModifiersState;
+
+use crate::colors::{ColorPair, ColorTarget};
+use crate::document::Document;
+use crate::scripting::{LuaEngine, ToolContext, ToolEvent};
+use crate::stabilizer::{Stabilizer, StabilizerSettings, StrokePoint};
+
+/// What Lua tools read and paint on, borrowed for one call
+pub struct StrokeContext<'a> {
+    pub lua: &'a mut LuaEngine,
+    pub document: &'a mut Document,
+    pub colors: &'a ColorPair,
+    pub modifiers: ModifiersState,
+    // The event at the cursor right now (canvas position, button, pressure, time...);
+    // drag events take everything but the position from it
+    pub event: ToolEvent,
+    // Window position to canvas position
+    pub to_canvas: &'a dyn Fn(f64, f64) -> (f64, f64),
+}
+
+impl StrokeContext<'_> {
+    /// Calls a tool callback and paints what it returns into the stroke buffer.
+    /// Returns (the tool handled the event, something was painted).
+    pub fn dispatch(&mut self, callback: &str, event: &ToolEvent) -> (bool, bool) {
+        let ctx = ToolContext {
+            document: self.document,
+            colors: self.colors,
+            paint_with: event.button.unwrap_or(ColorTarget::Primary),
+            modifiers: self.modifiers,
+        };
+        let (commands, handled) = self.lua.dispatch(callback, event, &ctx);
+        for cmd in &commands {
+            self.document.apply_command(cmd);
+        }
+        (handled, !commands.is_empty())
+    }
+}
+
+/// A stroke with a Lua tool, from button down to button up: smooths the pointer,
+/// hands the tool one segment at a time and commits the result. No GPU involved,
+/// so the tests paint through exactly what the app runs.
+pub struct ToolStroke {
+    pub stabilizer: Stabilizer,
+    // Button that started the stroke; None when nothing is being drawn
+    button: Option<ColorTarget>,
+    // Smoothed points (canvas position) waiting to be painted
+    pending: Vec<StrokePoint>,
+    // The last point handed to the tool
+    last: Option<StrokePoint>,
+}
+
+impl ToolStroke {
+    pub fn new(settings: StabilizerSettings) -> Self {
+        Self {
+            stabilizer: Stabilizer::new(settings),
+            button: None,
+            pending: Vec::new(),
+            last: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.button.is_some()
+    }
+
+    /// Drops a stroke in progress without painting or committing it (the document was replaced)
+    pub fn reset(&mut self) {
+        self.button = None;
+        self.pending.clear();
+        self.last = None;
+    }
+
+    /// Button down at `point` (window position). Returns true if on_press painted something.
+    pub fn press(
+        &mut self,
+        ctx: &mut StrokeContext,
+        button: ColorTarget,
+        point: StrokePoint,
+    ) -> bool {
+        self.reset();
+        self.button = Some(button);
+        ctx.lua.begin_stroke();
+        let event = ToolEvent {
+            button: Some(button),
+            ..ctx.event.clone()
+        };
+        let (_, painted) = ctx.dispatch("on_press", &event);
+        let points = self.stabilizer.begin(point, ctx.lua.wants_stabilizer());
+        self.queue(ctx, points);
+        painted
+    }
+
+    /// A pointer sample (window position); ignored unless a stroke is being drawn.
+    /// Nothing is painted until `paint`.
+    pub fn push(&mut self, ctx: &StrokeContext, point: StrokePoint) {
+        if self.is_active() {
+            let points = self.stabilizer.push(point);
+            self.queue(ctx, points);
+        }
+    }
+
+    /// Hands the queued points to the tool segment by segment, with an on_drag for each
+    /// move. Returns true if anything was painted.
+    pub fn paint(&mut self, ctx: &mut StrokeContext) -> bool {
+        let Some(button) = self.button else {
+            return false;
+        };
+        let mut painted = false;
+        for point in std::mem::take(&mut self.pending) {
+            let prev = self.last.unwrap_or(point);
+            // Sub-pixel canvas coordinates go straight to Lua, no rounding
+            let tool_ctx = ToolContext {
+                document: ctx.document,
+                colors: ctx.colors,
+                paint_with: button,
+                modifiers: ctx.modifiers,
+            };
+            let commands = ctx.lua.process_input(
+                (prev.x, prev.y),
+                (point.x, point.y),
+                (prev.pressure, point.pressure),
+                &tool_ctx,
+            );
+            for cmd in &commands {
+                ctx.document.apply_command(cmd);
+            }
+            painted |= !commands.is_empty();
+
+            if self.last.is_some() && (prev.x, prev.y) != (point.x, point.y) {
+                let event = ToolEvent {
+                    x: point.x,
+                    y: point.y,
+                    prev: Some((prev.x, prev.y)),
+                    button: Some(button),
+                    pressure: point.pressure,
+                    ..ctx.event.clone()
+                };
+                painted |= ctx.dispatch("on_drag", &event).1;
+            }
+            self.last = Some(point);
+        }
+        painted
+    }
+
+    /// Button up: paints whatever the stabilizer still holds back, sends on_release
+    /// and commits the stroke to the active layer
+    pub fn release(&mut self, ctx: &mut StrokeContext) {
+        let Some(button) = self.button else {
+            return;
+        };
+        let points = self.stabilizer.end();
+        self.queue(ctx, points);
+        self.paint(ctx);
+        // Still reports the button that was let go
+        let event = ToolEvent {
+            button: Some(button),
+            ..ctx.event.clone()
+        };
+        ctx.dispatch("on_release", &event);
+        self.reset();
+        ctx.document.commit_stroke();
+    }
+
+    fn queue(&mut self, ctx: &StrokeContext, points: Vec<StrokePoint>) {
+        for point in points {
+            let (x, y) = (ctx.to_canvas)(point.x, point.y);
+            self.pending.push(StrokePoint { x, y, ..point });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::LoadedTool;
+    use crate::text::FontLibrary;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
+    // Logs callbacks and segments; every segment paints one pixel at its end
+    const LOGGER: &str = r#"
+        local Tool = {log = {}}
+        for _, name in ipairs({"on_press", "on_drag", "on_release"}) do
+            Tool[name] = function(api, event)
+                table.insert(Tool.log, string.format("%s %g,%g", name, event.x, event.y))
+            end
+        end
+        function Tool.on_paint(api, x1, y1, x2, y2)
+            table.insert(Tool.log, string.format("paint %g,%g-%g,%g", x1, y1, x2, y2))
+            api.draw_pixel(math.floor(x2), math.floor(y2), 255, 0, 0)
+        end
+        return Tool
+    "#;
+
+    fn point(x: f64, y: f64) -> StrokePoint {
+        StrokePoint {
+            x,
+            y,
+            pressure: 1.0,
+        }
+    }
+
+    fn context<'a>(
+        lua: &'a mut LuaEngine,
+        document: &'a mut Document,
+        colors: &'a ColorPair,
+        to_canvas: &'a dyn Fn(f64, f64) -> (f64, f64),
+        at: (f64, f64),
+    ) -> StrokeContext<'a> {
+        StrokeContext {
+            lua,
+            document,
+            colors,
+            modifiers: ModifiersState::empty(),
+            event: ToolEvent {
+                x: at.0,
+                y: at.1,
+                pressure: 1.0,
+                ..Default::default()
+            },
+            to_canvas,
+        }
+    }
+
+    #[test]
+    fn callbacks_run_in_order_and_the_stroke_commits() {
+        let mut lua = LuaEngine::new(Rc::new(FontLibrary::scan(&[])));
+        lua.load_tool(&LoadedTool {
+            name: "Logger".to_string(),
+            script_content: LOGGER.to_string(),
+            package_path: PathBuf::new(),
+        });
+        let mut document = Document::new(8, 8, [255, 255, 255, 255]);
+        let colors = ColorPair::default();
+        // Window pixels are half canvas pixels
+        let to_canvas = |x: f64, y: f64| (x / 2.0, y / 2.0);
+        let mut stroke = ToolStroke::new(StabilizerSettings::default());
+
+        let mut ctx = context(&mut lua, &mut document, &colors, &to_canvas, (1.0, 1.0));
+        stroke.push(&ctx, point(0.0, 0.0));
+        assert!(!stroke.is_active(), "moves before the press are ignored");
+        stroke.press(&mut ctx, ColorTarget::Primary, point(2.0, 2.0));
+        stroke.push(&ctx, point(6.0, 2.0));
+        assert!(stroke.paint(&mut ctx));
+        ctx.event.x = 4.0;
+        stroke.push(&ctx, point(8.0, 4.0));
+        stroke.release(&mut ctx);
+        assert!(!stroke.is_active());
+
+        let log: Vec<String> = lua.eval("return current_tool.log");
+        assert_eq!(
+            log,
+            [
+                "on_press 1,1",
+                "paint 1,1-1,1",
+                "paint 1,1-3,1",
+                "on_drag 3,1",
+                "paint 3,1-4,2",
+                "on_drag 4,2",
+                "on_release 4,1",
+            ]
+        );
+        assert_eq!(document.history.undo_label(), Some("Brush Stroke"));
+        let red = |x: usize, y: usize| document.layers[0].pixel_buffer[(y * 8 + x) * 4 + 1] == 0;
+        assert!(red(1, 1) && red(3, 1) && red(4, 2));
+    }
+}
@@ -0,0 +1,434 @@
+use ab_glyph::{Font, FontArc, FontRef, FontVec, PxScale, ScaleFont, point};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::commands::PaintCommand;
+
+// Always available (compiled in through egui)
+pub const DEFAULT_FAMILY: &str = "Ubuntu";
+
+// Text sizes offered by the text panel and accepted from scripts, in pixels
+pub const MIN_TEXT_SIZE: f32 = 1.0;
+pub const MAX_TEXT_SIZE: f32 = 1000.0;
+
+// Slant of synthetic italics (horizontal shift per pixel above the baseline)
+const FAKE_ITALIC_SLANT: f32 = 0.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    pub const ALL: [TextAlign; 3] = [TextAlign::Left, TextAlign::Center, TextAlign::Right];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextAlign::Left => "Left",
+            TextAlign::Center => "Center",
+            TextAlign::Right => "Right",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(TextAlign::Left),
+            "center" => Some(TextAlign::Center),
+            "right" => Some(TextAlign::Right),
+            _ => None,
+        }
+    }
+}
+
+/// How a block of text is laid out and drawn
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    // Pixel height of the font (ascent to descent)
+    pub size: f32,
+    pub align: TextAlign,
+    // Multiplier on the font's own line height
+    pub line_spacing: f32,
+    // Used when the family has no real bold/italic face
+    pub fake_bold: bool,
+    pub fake_italic: bool,
+    pub antialias: bool,
+}
+
+enum FontSource {
+    File(PathBuf),
+    // Fonts compiled into the app (egui's defaults), so there is always something to use
+    Memory(Cow<'static, [u8]>),
+}
+
+pub struct FontEntry {
+    pub family: String,
+    pub bold: bool,
+    pub italic: bool,
+    // "Regular", "BoldOblique", ... from the file name
+    style: String,
+    source: FontSource,
+}
+
+/// Every font we can find, parsed lazily the first time it is used
+pub struct FontLibrary {
+    pub fonts: Vec<FontEntry>,
+    loaded: RefCell<HashMap<usize, Option<FontArc>>>,
+}
+
+impl FontLibrary {
+    /// Scans the system font folders plus `extra_dirs` (e.g. the packages folder)
+    pub fn scan(extra_dirs: &[PathBuf]) -> Self {
+        let mut fonts = Vec::new();
+
+        for (name, data) in egui::FontDefinitions::default().font_data {
+            if name.starts_with("Hack") || name.starts_with("Ubuntu") {
+                fonts.push(entry_for(&name, FontSource::Memory(data.font)));
+            }
+        }
+
+        for dir in system_font_dirs().iter().chain(extra_dirs) {
+            for file in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+                let path = file.path();
+                let is_font = path.extension().is_some_and(|e| {
+                    e.eq_ignore_ascii_case("ttf") || e.eq_ignore_ascii_case("otf")
+                });
+                if let (true, Some(stem)) = (is_font, path.file_stem()) {
+                    let stem = stem.to_string_lossy().to_string();
+                    fonts.push(entry_for(&stem, FontSource::File(path.to_path_buf())));
+                }
+            }
+        }
+        println!("Found {} fonts", fonts.len());
+
+        Self {
+            fonts,
+            loaded: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Family names, sorted and without duplicates
+    pub fn families(&self) -> Vec<String> {
+        let mut families: Vec<String> = self.fonts.iter().map(|f| f.family.clone()).collect();
+        families.sort_by_key(|f| f.to_lowercase());
+        families.dedup();
+        families
+    }
+
+    /// Picks the face of `family` closest to the requested style.
+    /// Returns the font plus which styles have to be faked because no real face exists.
+    pub fn resolve(&self, family: &str, bold: bool, italic: bool) -> Option<(FontArc, bool, bool)> {
+        let candidates: Vec<usize> = (0..self.fonts.len())
+            .filter(|i| self.fonts[*i].family.eq_ignore_ascii_case(family))
+            .collect();
+        // Prefer an exact style match, then plain faces over "Light", "Condensed"...
+        let score = |i: &usize| {
+            let f = &self.fonts[*i];
+            let mismatches = (f.bold != bold) as u32 + (f.italic != italic) as u32;
+            let plain = matches!(
+                f.style.to_lowercase().as_str(),
+                "" | "regular"
+                    | "book"
+                    | "bold"
+                    | "italic"
+                    | "oblique"
+                    | "bolditalic"
+                    | "boldoblique"
+            );
+            mismatches * 2 + !plain as u32
+        };
+        let index = candidates.into_iter().min_by_key(score)?;
+        let entry = &self.fonts[index];
+        let font = self.load(index)?;
+        Some((font, bold && !entry.bold, italic && !entry.italic))
+    }
+
+    fn load(&self, index: usize) -> Option<FontArc> {
+        self.loaded
+            .borrow_mut()
+            .entry(index)
+            .or_insert_with(|| {
+                let font = match &self.fonts[index].source {
+                    FontSource::File(path) => std::fs::read(path)
+                        .ok()
+                        .and_then(|data| FontVec::try_from_vec(data).ok())
+                        .map(FontArc::new),
+                    FontSource::Memory(Cow::Borrowed(data)) => {
+                        FontRef::try_from_slice(data).ok().map(FontArc::new)
+                    }
+                    FontSource::Memory(Cow::Owned(data)) => {
+                        FontVec::try_from_vec(data.clone()).ok().map(FontArc::new)
+                    }
+                };
+                if font.is_none() {
+                    println!("Could not load font: {}", self.fonts[index].family);
+                }
+                font
+            })
+            .clone()
+    }
+}
+
+/// "DejaVuSans-BoldOblique" -> family "DejaVuSans", bold, italic
+fn entry_for(stem: &str, source: FontSource) -> FontEntry {
+    let (family, style) = stem.split_once('-').unwrap_or((stem, ""));
+    let lower = style.to_lowercase();
+    FontEntry {
+        family: family.to_string(),
+        bold: lower.contains("bold") || lower.contains("black") || lower.contains("heavy"),
+        italic: lower.contains("italic") || lower.contains("oblique"),
+        style: style.to_string(),
+        source,
+    }
+}
+
+fn system_font_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        PathBuf::from("/usr/share/fonts"),
+        PathBuf::from("/usr/local/share/fonts"),
+        PathBuf::from("/System/Library/Fonts"),
+        PathBuf::from("/Library/Fonts"),
+    ];
+    if let Some(home) = std::env::var_os("HOME") {
+        let home = Path::new(&home);
+        dirs.push(home.join(".fonts"));
+        dirs.push(home.join(".local/share/fonts"));
+        dirs.push(home.join("Library/Fonts"));
+    }
+    if let Some(windir) = std::env::var_os("WINDIR") {
+        dirs.push(Path::new(&windir).join("Fonts"));
+    }
+    dirs.into_iter().filter(|d| d.is_dir()).collect()
+}
+
+/// Draws `text` with its top-left (or top-center / top-right, see `align`) at `origin`.
+/// Calls `plot(x, y, coverage)` for every pixel a glyph touches; pixels can be hit
+/// more than once. Glyphs entirely outside a `clip` sized canvas are skipped, but
+/// the ones crossing its edge still plot their outside pixels.
+pub fn render_text(
+    font: &FontArc,
+    text: &str,
+    style: &TextStyle,
+    origin: (f64, f64),
+    clip: (u32, u32),
+    mut plot: impl FnMut(i64, i64, f32),
+) {
+    // A NaN size from a script falls back to the minimum
+    let size = if style.size.is_nan() {
+        MIN_TEXT_SIZE
+    } else {
+        style.size.clamp(MIN_TEXT_SIZE, MAX_TEXT_SIZE)
+    };
+    let scaled = font.as_scaled(PxScale::from(size));
+    let line_height = (scaled.ascent() - scaled.descent() + scaled.line_gap()) * style.line_spacing;
+    // Fake bold smears each glyph sideways a little
+    let embolden = if style.fake_bold {
+        (size / 24.0).max(1.0)
+    } else {
+        0.0
+    };
+
+    for (line_index, line) in text.lines().enumerate() {
+        let baseline = origin.1 as f32 + scaled.ascent() + line_index as f32 * line_height;
+
+        // Measure first so the line can be aligned
+        let mut glyphs = Vec::new();
+        let mut caret = 0.0f32;
+        let mut previous = None;
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(prev) = previous {
+                caret += scaled.kern(prev, id);
+            }
+            glyphs.push((id, caret));
+            caret += scaled.h_advance(id) + embolden;
+            previous = Some(id);
+        }
+        let start_x = origin.0 as f32
+            - match style.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => caret / 2.0,
+                TextAlign::Right => caret,
+            };
+
+        for (id, x) in glyphs {
+            let glyph = id.with_scale_and_position(scaled.scale(), point(start_x + x, baseline));
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            // Fake italic shears the glyph, fake bold widens it to the right
+            let (mut left, mut right) = (bounds.min.x, bounds.max.x + embolden.ceil());
+            if style.fake_italic {
+                let top = (baseline - bounds.min.y) * FAKE_ITALIC_SLANT;
+                let bottom = (baseline - bounds.max.y) * FAKE_ITALIC_SLANT;
+                left += top.min(bottom);
+                right += top.max(bottom);
+            }
+            if right < 0.0
+                || bounds.max.y < 0.0
+                || left >= clip.0 as f32
+                || bounds.min.y >= clip.1 as f32
+            {
+                continue;
+            }
+            outlined.draw(|gx, gy, coverage| {
+                let coverage = if style.antialias {
+                    coverage
+                } else if coverage >= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                };
+                if coverage <= 0.0 {
+                    return;
+                }
+                let py = bounds.min.y + gy as f32;
+                let mut px = bounds.min.x + gx as f32;
+                if style.fake_italic {
+                    px += (baseline - py) * FAKE_ITALIC_SLANT;
+                }
+                let steps = embolden.ceil() as i64;
+                for dx in 0..=steps {
+                    plot(px.round() as i64 + dx, py.round() as i64, coverage);
+                }
+            });
+        }
+    }
+}
+
+/// The built-in text tool: what is being typed and how it looks.
+/// The preview lives in the stroke buffer until it is committed.
+#[derive(Clone, PartialEq)]
+pub struct TextTool {
+    pub text: String,
+    pub family: String,
+    pub size: f32,
+    pub bold: bool,
+    pub italic: bool,
+    pub align: TextAlign,
+    pub line_spacing: f32,
+    pub antialias: bool,
+    // Where the text goes; None until the canvas is clicked
+    pub anchor: Option<(f64, f64)>,
+}
+
+impl Default for TextTool {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            family: DEFAULT_FAMILY.to_string(),
+            size: 32.0,
+            bold: false,
+            italic: false,
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+            antialias: true,
+            anchor: None,
+        }
+    }
+}
+
+impl TextTool {
+    /// What to draw for the current settings, if there is anything to draw
+    pub fn command(&self, fonts: &FontLibrary, color: [u8; 4]) -> Option<PaintCommand> {
+        let (x, y) = self.anchor?;
+        if self.text.is_empty() {
+            return None;
+        }
+        let (font, fake_bold, fake_italic) = fonts.resolve(&self.family, self.bold, self.italic)?;
+        Some(PaintCommand::Text {
+            x,
+            y,
+            text: self.text.clone(),
+            font,
+            style: TextStyle {
+                size: self.size,
+                align: self.align,
+                line_spacing: self.line_spacing,
+                fake_bold,
+                fake_italic,
+                antialias: self.antialias,
+            },
+            color,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> FontArc {
+        FontLibrary::scan(&[])
+            .resolve(DEFAULT_FAMILY, false, false)
+            .unwrap()
+            .0
+    }
+
+    fn style(size: f32) -> TextStyle {
+        TextStyle {
+            size,
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+            fake_bold: false,
+            fake_italic: false,
+            antialias: false,
+        }
+    }
+
+    #[test]
+    fn huge_sizes_are_capped() {
+        let mut plotted = 0u64;
+        render_text(
+            &font(),
+            "W",
+            &style(1e6),
+            (0.0, 0.0),
+            (2000, 2000),
+            |_, _, _| {
+                plotted += 1;
+            },
+        );
+        // A capped glyph crossing the canvas still draws, but nowhere near 1e6 squared
+        assert!(plotted > 0);
+        let max = (MAX_TEXT_SIZE * MAX_TEXT_SIZE * 2.0) as u64;
+        assert!(plotted < max, "{} pixels", plotted);
+    }
+
+    #[test]
+    fn glyphs_off_the_canvas_are_skipped() {
+        let mut plotted = 0;
+        for origin in [(-500.0, 0.0), (20.0, 0.0), (0.0, -500.0), (0.0, 20.0)] {
+            render_text(&font(), "Hi", &style(24.0), origin, (10, 10), |_, _, _| {
+                plotted += 1;
+            });
+        }
+        assert_eq!(plotted, 0);
+    }
+
+    #[test]
+    fn plots_whole_pixels_inside_the_canvas() {
+        let mut points = Vec::new();
+        render_text(
+            &font(),
+            "H",
+            &style(16.0),
+            (0.5, 0.5),
+            (32, 32),
+            |x, y, _| {
+                points.push((x, y));
+            },
+        );
+        assert!(!points.is_empty());
+        assert!(
+            points
+                .iter()
+                .all(|&(x, y)| (0..32).contains(&x) && (0..32).contains(&y))
+        );
+    }
+}
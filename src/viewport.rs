@@ -0,0 +1,222 @@
+pub const MIN_ZOOM: f64 = 0.05;
+pub const MAX_ZOOM: f64 = 64.0;
+
+/// How the canvas sits in the window: zoom level plus a pan offset.
+/// Screen positions are physical window pixels with the origin at the top-left;
+/// canvas positions are fractional canvas pixels and may fall outside the image.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub zoom: f64,
+    // Offset of the canvas center from the window center, in screen pixels
+    pub pan: (f64, f64),
+}
+
+/// Matches `View` in shader.wgsl: clip = quad_pos * scale + offset
+#[derive(Clone, Copy, Debug)]
+pub struct ViewUniform {
+    pub scale: [f32; 2],
+    pub offset: [f32; 2],
+}
+
+impl ViewUniform {
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut out = [0u8; 16];
+        let values = [self.scale[0], self.scale[1], self.offset[0], self.offset[1]];
+        for (i, v) in values.iter().enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&v.to_ne_bytes());
+        }
+        out
+    }
+}
+
+impl Viewport {
+    pub fn new() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: (0.0, 0.0),
+        }
+    }
+
+    /// Screen position of the canvas' top-left corner
+    fn origin(&self, canvas: (u32, u32), window: (u32, u32)) -> (f64, f64) {
+        (
+            window.0 as f64 / 2.0 + self.pan.0 - canvas.0 as f64 * self.zoom / 2.0,
+            window.1 as f64 / 2.0 + self.pan.1 - canvas.1 as f64 * self.zoom / 2.0,
+        )
+    }
+
+    pub fn screen_to_canvas(
+        &self,
+        screen: (f64, f64),
+        canvas: (u32, u32),
+        window: (u32, u32),
+    ) -> (f64, f64) {
+        let origin = self.origin(canvas, window);
+        (
+            (screen.0 - origin.0) / self.zoom,
+            (screen.1 - origin.1) / self.zoom,
+        )
+    }
+
+    pub fn canvas_to_screen(
+        &self,
+        point: (f64, f64),
+        canvas: (u32, u32),
+        window: (u32, u32),
+    ) -> (f64, f64) {
+        let origin = self.origin(canvas, window);
+        (
+            origin.0 + point.0 * self.zoom,
+            origin.1 + point.1 * self.zoom,
+        )
+    }
+
+    /// Zooms by `factor`, keeping the canvas point under `anchor` (a screen position) in place
+    pub fn zoom_at(
+        &mut self,
+        factor: f64,
+        anchor: (f64, f64),
+        canvas: (u32, u32),
+        window: (u32, u32),
+    ) {
+        let before = self.screen_to_canvas(anchor, canvas, window);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let after = self.canvas_to_screen(before, canvas, window);
+        self.pan.0 += anchor.0 - after.0;
+        self.pan.1 += anchor.1 - after.1;
+    }
+
+    /// Largest zoom that shows the whole canvas, centered
+    pub fn fit(&mut self, canvas: (u32, u32), window: (u32, u32)) {
+        let zoom_x = window.0 as f64 / canvas.0 as f64;
+        let zoom_y = window.1 as f64 / canvas.1 as f64;
+        self.zoom = zoom_x.min(zoom_y).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan = (0.0, 0.0);
+    }
+
+    /// 100%: one canvas pixel per screen pixel, centered
+    pub fn actual_size(&mut self) {
+        self.zoom = 1.0;
+        self.pan = (0.0, 0.0);
+    }
+
+    pub fn uniform(&self, canvas: (u32, u32), window: (u32, u32)) -> ViewUniform {
+        ViewUniform {
+            scale: [
+                (canvas.0 as f64 * self.zoom / window.0 as f64) as f32,
+                (canvas.1 as f64 * self.zoom / window.1 as f64) as f32,
+            ],
+            // Clip space Y points up, screen Y points down
+            offset: [
+                (2.0 * self.pan.0 / window.0 as f64) as f32,
+                (-2.0 * self.pan.1 / window.1 as f64) as f32,
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [((u32, u32), (u32, u32)); 3] = [
+        ((64, 64), (800, 600)),
+        ((1920, 200), (1024, 768)),
+        ((30, 500), (640, 1200)),
+    ];
+
+    fn assert_close(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn screen_and_canvas_round_trip() {
+        for (canvas, window) in SIZES {
+            for zoom in [MIN_ZOOM, 0.5, 1.0, 3.0, MAX_ZOOM] {
+                let view = Viewport {
+                    zoom,
+                    pan: (13.5, -40.0),
+                };
+                for screen in [(0.0, 0.0), (123.25, 456.5), (-50.0, 2000.0)] {
+                    let point = view.screen_to_canvas(screen, canvas, window);
+                    assert_close(view.canvas_to_screen(point, canvas, window), screen);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unpanned_canvas_is_centered() {
+        let mut view = Viewport::new();
+        view.zoom = 2.0;
+        let center = view.screen_to_canvas((400.0, 300.0), (64, 32), (800, 600));
+        assert_close(center, (32.0, 16.0));
+        assert_close(
+            view.canvas_to_screen((0.0, 0.0), (64, 32), (800, 600)),
+            (336.0, 268.0),
+        );
+    }
+
+    #[test]
+    fn zoom_keeps_the_anchor_in_place() {
+        for (canvas, window) in SIZES {
+            let mut view = Viewport::new();
+            let anchor = (200.0, 150.0);
+            let under = view.screen_to_canvas(anchor, canvas, window);
+            for factor in [2.0, 0.25, 1.5] {
+                view.zoom_at(factor, anchor, canvas, window);
+                assert_close(view.screen_to_canvas(anchor, canvas, window), under);
+            }
+        }
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut view = Viewport::new();
+        view.zoom_at(1000.0, (0.0, 0.0), (10, 10), (100, 100));
+        assert_eq!(view.zoom, MAX_ZOOM);
+        view.zoom_at(1e-6, (0.0, 0.0), (10, 10), (100, 100));
+        assert_eq!(view.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn fit_shows_the_whole_canvas_centered() {
+        for (canvas, window) in SIZES {
+            let mut view = Viewport {
+                zoom: 7.0,
+                pan: (100.0, 100.0),
+            };
+            view.fit(canvas, window);
+            let top_left = view.canvas_to_screen((0.0, 0.0), canvas, window);
+            let bottom_right =
+                view.canvas_to_screen((canvas.0 as f64, canvas.1 as f64), canvas, window);
+            assert!(top_left.0 >= -1e-9 && top_left.1 >= -1e-9);
+            assert!(bottom_right.0 <= window.0 as f64 + 1e-9);
+            assert!(bottom_right.1 <= window.1 as f64 + 1e-9);
+            // One side touches the window edges
+            let touches_x = top_left.0.abs() < 1e-9;
+            let touches_y = top_left.1.abs() < 1e-9;
+            assert!(touches_x || touches_y);
+            assert_close(
+                (top_left.0 + bottom_right.0, top_left.1 + bottom_right.1),
+                (window.0 as f64, window.1 as f64),
+            );
+        }
+    }
+
+    #[test]
+    fn uniform_matches_the_screen_mapping() {
+        let view = Viewport {
+            zoom: 2.0,
+            pan: (40.0, 30.0),
+        };
+        let uniform = view.uniform((100, 50), (800, 600));
+        assert_eq!(uniform.scale, [0.25, 1.0 / 6.0]);
+        assert_eq!(uniform.offset, [0.1, -0.1]);
+    }
+}